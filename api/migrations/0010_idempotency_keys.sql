@@ -0,0 +1,16 @@
+-- Client-supplied Idempotency-Key values for create endpoints.
+-- A retried create with the same key returns the resource made by the first attempt.
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+  tenant_type TEXT NOT NULL,
+  tenant_id TEXT NOT NULL,
+  key TEXT NOT NULL,
+  -- Endpoint + body fingerprint; reusing a key for a different request is rejected.
+  request_hash TEXT NOT NULL,
+  resource_id UUID NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (tenant_type, tenant_id, key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at
+  ON idempotency_keys(created_at);
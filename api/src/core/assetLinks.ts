@@ -0,0 +1,25 @@
+import { hmacSha256Base64Url, timingSafeEqualString } from "../auth/crypto";
+
+export const MIN_LINK_TTL_SECONDS = 60;
+export const MAX_LINK_TTL_SECONDS = 7 * 24 * 60 * 60;
+export const DEFAULT_LINK_TTL_SECONDS = 60 * 60;
+
+// The R2 key is part of the signed message so a link dies with the object it pointed at.
+function linkMessage(assetId: string, r2Key: string, expires: number): string {
+  return `asset-download\n${assetId}\n${r2Key}\n${expires}`;
+}
+
+export function signAssetLink(key: string, assetId: string, r2Key: string, expires: number): Promise<string> {
+  return hmacSha256Base64Url(key, linkMessage(assetId, r2Key, expires));
+}
+
+export async function verifyAssetLink(
+  key: string,
+  assetId: string,
+  r2Key: string,
+  expires: number,
+  sig: string
+): Promise<boolean> {
+  const expected = await signAssetLink(key, assetId, r2Key, expires);
+  return timingSafeEqualString(expected, sig);
+}
@@ -0,0 +1,55 @@
+import type { Client } from "pg";
+import type { TenantType } from "../tenant";
+
+export type IdempotencyClaim =
+  | { claimed: true }
+  | { claimed: false; requestHash: string; resourceId: string; createdAt: string };
+
+// Keys older than this are purged by the daily cron; a retry after that creates anew.
+export const IDEMPOTENCY_KEY_TTL_HOURS = 24;
+
+// Record `key` for a create that is about to make `resourceId`. If the key was already
+// used, return the earlier request's fingerprint and resource instead.
+//
+// Call inside the transaction that creates the resource: a concurrent request with the
+// same key then waits on the uncommitted row and sees either the finished resource or,
+// if the first create rolled back, a free key. No claim outlives its create.
+export async function claimIdempotencyKey(
+  db: Client,
+  input: {
+    tenantType: TenantType;
+    tenantId: string;
+    key: string;
+    requestHash: string;
+    resourceId: string;
+    nowIso: string;
+  }
+): Promise<IdempotencyClaim> {
+  const inserted = await db.query(
+    `INSERT INTO idempotency_keys (tenant_type, tenant_id, key, request_hash, resource_id, created_at)
+     VALUES ($1, $2, $3, $4, $5, $6)
+     ON CONFLICT (tenant_type, tenant_id, key) DO NOTHING
+     RETURNING key`,
+    [input.tenantType, input.tenantId, input.key, input.requestHash, input.resourceId, input.nowIso]
+  );
+  if ((inserted.rowCount ?? 0) > 0) return { claimed: true };
+
+  const { rows } = await db.query(
+    "SELECT request_hash, resource_id, created_at FROM idempotency_keys WHERE tenant_type = $1 AND tenant_id = $2 AND key = $3",
+    [input.tenantType, input.tenantId, input.key]
+  );
+  const row = rows[0];
+  return {
+    claimed: false,
+    requestHash: String(row?.request_hash ?? ""),
+    resourceId: String(row?.resource_id ?? ""),
+    createdAt: row?.created_at instanceof Date ? row.created_at.toISOString() : String(row?.created_at ?? ""),
+  };
+}
+
+// Delete keys past `IDEMPOTENCY_KEY_TTL_HOURS` (uses idx_idempotency_keys_created_at).
+export async function purgeExpiredIdempotencyKeys(db: Client, now: Date): Promise<number> {
+  const cutoff = new Date(now.getTime() - IDEMPOTENCY_KEY_TTL_HOURS * 60 * 60 * 1000);
+  const res = await db.query("DELETE FROM idempotency_keys WHERE created_at < $1", [cutoff.toISOString()]);
+  return res.rowCount ?? 0;
+}
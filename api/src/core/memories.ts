@@ -49,6 +49,8 @@ export interface ListMemoriesQuery {
   mode?: "full" | "retrieval" | "preview" | "index";
   memoryMode?: MemorySearchMode | null;
   limit?: number | null;
+  // Offset paging applies to the plain (non-search) listing only; ranked search ignores it.
+  offset?: number | null;
 }
 
 function escapeLike(s: string): string {
@@ -280,12 +282,24 @@ export async function listMemories(
   }
 
   params.push(limit);
-  const query = `${selectClause} ${where} ORDER BY updated_at DESC LIMIT $${params.length}`;
+  let query = `${selectClause} ${where} ORDER BY updated_at DESC, id DESC LIMIT $${params.length}`;
+  const offset = Math.max(Math.trunc(q.offset || 0), 0);
+  if (offset > 0) {
+    params.push(offset);
+    query += ` OFFSET $${params.length}`;
+  }
 
   const { rows } = await db.query(query, params);
   return rows;
 }
 
+/** Optimistic-concurrency version of a memory: its `updated_at` in Unix milliseconds. */
+export function memoryVersion(updatedAt: unknown): number | null {
+  if (updatedAt === null || updatedAt === undefined) return null;
+  const ms = updatedAt instanceof Date ? updatedAt.getTime() : Date.parse(String(updatedAt));
+  return Number.isFinite(ms) ? ms : null;
+}
+
 export async function getMemory(db: Client, tenantType: TenantType, tenantId: string, id: string) {
   // Single roundtrip: increment access_count and return the record.
   const { rows } = await db.query(
@@ -399,18 +413,22 @@ export async function updateMemory(
     category: string;
     sourceType: string;
     nowIso: string;
+    // When set (from If-Match), only update if the memory is still at this version.
+    expectedVersion?: number | null;
   }
-) {
+): Promise<{ conflict: boolean; version: number | null }> {
   const beforeRes = await db.query(
-    "SELECT project_id, title, category, source_type, confidence, state, quality FROM memories WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3",
+    "SELECT project_id, title, category, source_type, confidence, state, quality, updated_at FROM memories WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3",
     [input.id, input.tenantType, input.tenantId]
   );
   const before = beforeRes.rows[0] ?? null;
 
-  await db.query(
+  const expected = input.expectedVersion ?? null;
+  const updateRes = await db.query(
     `UPDATE memories
      SET title = $1, content = $2, tags = $3::jsonb, context = $4::jsonb, confidence = $5, category = $6, source_type = $7, updated_at = $8, updated_by = $9
-     WHERE id = $10 AND tenant_type = $11 AND tenant_id = $12`,
+     WHERE id = $10 AND tenant_type = $11 AND tenant_id = $12
+       AND ($13::bigint IS NULL OR floor(EXTRACT(EPOCH FROM updated_at) * 1000)::bigint = $13::bigint)`,
     [
       input.title,
       input.content,
@@ -424,9 +442,20 @@ export async function updateMemory(
       input.id,
       input.tenantType,
       input.tenantId,
+      expected,
     ]
   );
 
+  if (before && expected !== null && (updateRes.rowCount ?? 0) === 0) {
+    // Someone else wrote the memory after the caller read it.
+    const current = await db.query("SELECT updated_at FROM memories WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+      input.id,
+      input.tenantType,
+      input.tenantId,
+    ]);
+    return { conflict: true, version: memoryVersion(current.rows[0]?.updated_at ?? before.updated_at) };
+  }
+
   if (before) {
     await recordMemoryEvent(db, {
       tenantType: input.tenantType,
@@ -455,6 +484,8 @@ export async function updateMemory(
       nowIso: input.nowIso,
     });
   }
+
+  return { conflict: false, version: memoryVersion(input.nowIso) };
 }
 
 export async function deleteMemory(
@@ -494,6 +525,63 @@ export async function deleteMemory(
   ]);
 }
 
+export async function moveMemory(
+  db: Client,
+  input: {
+    tenantType: TenantType;
+    tenantId: string;
+    actorId: string | null;
+    id: string;
+    projectId: string;
+    nowIso: string;
+  }
+): Promise<{ id: string; from_project_id: string; project_id: string; updated_at: string } | null> {
+  const beforeRes = await db.query(
+    "SELECT project_id FROM memories WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3",
+    [input.id, input.tenantType, input.tenantId]
+  );
+  const before = beforeRes.rows[0] ?? null;
+  if (!before) return null;
+
+  const projRes = await db.query("SELECT id FROM projects WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+    input.projectId,
+    input.tenantType,
+    input.tenantId,
+  ]);
+  if (projRes.rowCount === 0) throw new Error("Target project not found (or not in tenant scope).");
+
+  const { rows } = await db.query(
+    `UPDATE memories
+     SET project_id = $1, updated_at = $2, updated_by = $3
+     WHERE id = $4 AND tenant_type = $5 AND tenant_id = $6
+     RETURNING id, project_id, updated_at`,
+    [input.projectId, input.nowIso, input.actorId, input.id, input.tenantType, input.tenantId]
+  );
+  const row = rows[0] as any;
+  if (!row) return null;
+
+  await recordMemoryEvent(db, {
+    tenantType: input.tenantType,
+    tenantId: input.tenantId,
+    actorId: input.actorId,
+    projectId: String(row.project_id),
+    memoryId: input.id,
+    eventType: "move",
+    eventData: {
+      from: { project_id: String(before.project_id) },
+      to: { project_id: String(row.project_id) },
+    },
+    nowIso: input.nowIso,
+  });
+
+  return {
+    id: String(row.id),
+    from_project_id: String(before.project_id),
+    project_id: String(row.project_id),
+    updated_at: String(row.updated_at),
+  };
+}
+
 export async function setMemoryLifecycle(
   db: Client,
   input: {
@@ -3,7 +3,9 @@ export { Sandbox } from "@cloudflare/sandbox";
 
 import { Hono } from "hono";
 import { cors } from "hono/cors";
+import { etag } from "hono/etag";
 import type { Env } from "./types";
+import { withDbClient } from "./db";
 import type { AppEnv } from "./appEnv";
 import { authMiddleware } from "./auth/middleware";
 import { authenticateRequest } from "./auth/authenticate";
@@ -23,6 +25,7 @@ import { TenantError } from "./tenant";
 import { runUnrealAgentsDailyDigestForAllTenants } from "./research/unrealAgents";
 import { runAgentMemoryDailyDigestForAllTenants } from "./research/agentMemory";
 import { runNewProjectsDailyDigestForAllTenants } from "./research/newProjects";
+import { purgeExpiredIdempotencyKeys } from "./core/idempotency";
 import { getOAuthMetadata, getProtectedResourceMetadata, handleAuthorize, handleRegister, handleToken } from "./oauth/server";
 import { handleMcpJsonRpc, MCP_ERROR_CODES, type McpRequest, type McpResponse } from "./mcp/server";
 
@@ -30,9 +33,24 @@ const app = new Hono<AppEnv>();
 
 app.use("/*", cors());
 
+// Optional version pinning: clients may send `X-API-Version`; unknown versions get 406.
+const SUPPORTED_API_VERSIONS = ["1"];
+app.use("/api/*", async (c, next) => {
+  const requested = (c.req.header("x-api-version") || "").trim();
+  if (requested && !SUPPORTED_API_VERSIONS.includes(requested)) {
+    return c.json({ error: `Unsupported API version: ${requested}`, supported_versions: SUPPORTED_API_VERSIONS }, 406);
+  }
+  await next();
+  c.header("X-API-Version", requested || SUPPORTED_API_VERSIONS[SUPPORTED_API_VERSIONS.length - 1]);
+});
+
 // Core auth: Clerk session JWT for web, API keys for agents/services.
 app.use("/api/*", authMiddleware);
 
+// List endpoints answer `If-None-Match` with 304 so polling clients (the CLI cache) skip the body.
+app.use("/api/memories", etag());
+app.use("/api/assets", etag());
+
 app.onError((err, c) => {
   const anyErr = err as any;
   if (anyErr && (anyErr instanceof TenantError || anyErr.name === "TenantError")) {
@@ -195,6 +213,13 @@ export default {
                 console.error("[cron] new-projects digest failed:", err);
               }
             })(),
+            (async () => {
+              try {
+                await withDbClient(env, async (db) => await purgeExpiredIdempotencyKeys(db, when));
+              } catch (err) {
+                console.error("[cron] idempotency key purge failed:", err);
+              }
+            })(),
           ]);
         } catch (err) {
           console.error("[cron] digest sweep failed:", err);
@@ -113,7 +113,8 @@ function safeParseUrl(v: string): URL | null {
 function normalizeRedirectUri(url: URL): string {
   const protocol = url.protocol.toLowerCase();
   const hostname = url.hostname.toLowerCase();
-  const isLoopback = hostname === "localhost" || hostname === "127.0.0.1" || hostname === "::1";
+  // WHATWG URL keeps the brackets on IPv6 hosts: new URL("http://[::1]:80/").hostname === "[::1]".
+  const isLoopback = hostname === "localhost" || hostname === "127.0.0.1" || hostname === "[::1]" || hostname === "::1";
   const hostKey = isLoopback ? "loopback" : hostname;
   const port = url.port || (protocol === "http:" ? "80" : protocol === "https:" ? "443" : "");
   let pathname = url.pathname;
@@ -2,11 +2,16 @@ import { Hono } from "hono";
 import type { AppEnv } from "../appEnv";
 import { withDbClient } from "../db";
 import { requireTenant } from "../tenant";
+import { DEFAULT_LINK_TTL_SECONDS, MAX_LINK_TTL_SECONDS, MIN_LINK_TTL_SECONDS, signAssetLink } from "../core/assetLinks";
+import { bytesToHex } from "../auth/crypto";
 
 const MB = 1024 * 1024;
 const MIN_PART_SIZE = 5 * MB; // S3/R2 multipart minimum (except last part)
 // Cloudflare Workers request body limits vary by plan; stay under 100MB for safety.
 const MAX_PART_SIZE = 95 * MB;
+// R2 single-request put limit; larger server-side copies would need multipart.
+const MAX_SINGLE_PUT_BYTES = 5 * 1000 * MB;
+const SHA256_HEX = /^[0-9a-f]{64}$/;
 
 function requireBucket(env: AppEnv["Bindings"]): R2Bucket {
   if (!env.MEMORY_BUCKET) {
@@ -78,9 +83,11 @@ assetsRouter.get("/", async (c) => {
   const projectId = c.req.query("project_id");
   const memoryId = c.req.query("memory_id");
   const status = c.req.query("status");
+  const sha256 = (c.req.query("sha256") || "").trim().toLowerCase();
   const searchRaw = c.req.query("q") || c.req.query("search") || "";
   const searchQ = searchRaw ? searchRaw.trim() : "";
   const limit = clampInt(c.req.query("limit"), 50, 1, 200);
+  const offset = clampInt(c.req.query("offset"), 0, 0, Number.MAX_SAFE_INTEGER);
   const includeMemoryLinks = truthyQuery(c.req.query("include_memory_links") || c.req.query("include_links"));
 
   const assets = await withDbClient(c.env, async (db) => {
@@ -97,6 +104,11 @@ assetsRouter.get("/", async (c) => {
       sql += ` AND a.status = $${params.length}`;
     }
 
+    if (sha256) {
+      params.push(sha256);
+      sql += ` AND lower(a.sha256) = $${params.length}`;
+    }
+
     if (searchQ) {
       // Search by original filename or storage key suffix.
       // Keep this simple and deterministic (no embeddings here).
@@ -121,7 +133,11 @@ assetsRouter.get("/", async (c) => {
     }
 
     params.push(limit);
-    sql += ` ORDER BY a.created_at DESC LIMIT $${params.length}`;
+    sql += ` ORDER BY a.created_at DESC, a.id DESC LIMIT $${params.length}`;
+    if (offset > 0) {
+      params.push(offset);
+      sql += ` OFFSET $${params.length}`;
+    }
 
     if (!includeMemoryLinks) {
       const { rows } = await db.query(sql, params);
@@ -168,14 +184,14 @@ assetsRouter.get("/", async (c) => {
         GROUP BY l.to_id
       ) links
         ON links.asset_id = base.id
-      ORDER BY base.created_at DESC
+      ORDER BY base.created_at DESC, base.id DESC
     `;
 
     const { rows } = await db.query(full, params);
     return rows;
   });
 
-  return c.json({ assets });
+  return c.json({ assets, meta: { limit, offset } });
 });
 
 // Get asset metadata
@@ -224,6 +240,50 @@ assetsRouter.get("/:id", async (c) => {
   return c.json(asset);
 });
 
+// Update asset original_name and/or merge keys into metadata
+assetsRouter.patch("/:id", async (c) => {
+  const { tenantType, tenantId, actorId } = requireTenant(c);
+  const id = c.req.param("id");
+  const body = await c.req.json();
+
+  let originalName: string | undefined;
+  if (body.original_name !== undefined) {
+    if (typeof body.original_name !== "string" || !body.original_name.trim()) {
+      return c.json({ error: "original_name must be a non-empty string" }, 400);
+    }
+    originalName = body.original_name.trim().slice(0, 1024);
+  }
+
+  let metadata: Record<string, unknown> | undefined;
+  if (body.metadata !== undefined) {
+    if (!body.metadata || typeof body.metadata !== "object" || Array.isArray(body.metadata)) {
+      return c.json({ error: "metadata must be an object" }, 400);
+    }
+    metadata = body.metadata;
+  }
+
+  if (originalName === undefined && metadata === undefined) {
+    return c.json({ error: "Nothing to update (pass original_name and/or metadata)" }, 400);
+  }
+
+  const now = new Date().toISOString();
+  const asset = await withDbClient(c.env, async (db) => {
+    const { rows } = await db.query(
+      `UPDATE assets
+       SET original_name = COALESCE($1, original_name),
+           metadata = metadata || $2::jsonb,
+           updated_at = $3, updated_by = $4
+       WHERE id = $5 AND tenant_type = $6 AND tenant_id = $7
+       RETURNING *`,
+      [originalName ?? null, JSON.stringify(metadata ?? {}), now, actorId, id, tenantType, tenantId]
+    );
+    return rows[0] ?? null;
+  });
+
+  if (!asset) return c.json({ error: "Asset not found" }, 404);
+  return c.json(asset);
+});
+
 // Create an asset + initiate multipart upload
 assetsRouter.post("/", async (c) => {
   const { tenantType, tenantId, actorId } = requireTenant(c);
@@ -512,21 +572,69 @@ assetsRouter.post("/:id/complete", async (c) => {
     }
   }
 
+  // Hashing reads the whole object back, so only verify when the client asks by sending a
+  // checksum here; one recorded at create is kept but not checked.
+  const expectedSha256 =
+    typeof body.sha256 === "string" && body.sha256.trim() ? body.sha256.trim().toLowerCase() : null;
+  if (expectedSha256 && !SHA256_HEX.test(expectedSha256)) {
+    return c.json({ error: "sha256 must be 64 hex characters" }, 400);
+  }
+
   const upload = bucket.resumeMultipartUpload(asset.r2_key, asset.upload_id);
   const completed = await upload.complete(parts);
 
   const now = new Date().toISOString();
+  if (expectedSha256) {
+    // Multipart ETags aren't content hashes, so read the assembled object back.
+    const actualSha256 = await objectSha256(bucket, asset.r2_key);
+    if (actualSha256 !== expectedSha256) {
+      await bucket.delete(asset.r2_key).catch(() => undefined);
+      await withDbClient(c.env, async (db) => {
+        await db.query(
+          "UPDATE assets SET status = 'failed', upload_id = NULL, updated_at = $1, updated_by = $2 WHERE id = $3 AND tenant_type = $4 AND tenant_id = $5",
+          [now, actorId, id, tenantType, tenantId]
+        );
+        await db.query("DELETE FROM asset_upload_parts WHERE asset_id = $1", [id]);
+      });
+      return c.json(
+        {
+          error: "Checksum mismatch: the assembled object does not match the expected SHA-256",
+          details: { expected_sha256: expectedSha256, actual_sha256: actualSha256 },
+        },
+        422
+      );
+    }
+  }
+
   await withDbClient(c.env, async (db) => {
     await db.query(
-      "UPDATE assets SET status = 'ready', upload_id = NULL, updated_at = $1, updated_by = $2, byte_size = $3 WHERE id = $4 AND tenant_type = $5 AND tenant_id = $6",
-      [now, actorId, completed.size, id, tenantType, tenantId]
+      "UPDATE assets SET status = 'ready', upload_id = NULL, updated_at = $1, updated_by = $2, byte_size = $3, sha256 = COALESCE($4, sha256) WHERE id = $5 AND tenant_type = $6 AND tenant_id = $7",
+      [now, actorId, completed.size, expectedSha256, id, tenantType, tenantId]
     );
     await db.query("DELETE FROM asset_upload_parts WHERE asset_id = $1", [id]);
   });
 
-  return c.json({ ok: true, asset_id: id, status: "ready", r2_key: asset.r2_key, byte_size: completed.size, updated_at: now });
+  return c.json({
+    ok: true,
+    asset_id: id,
+    status: "ready",
+    r2_key: asset.r2_key,
+    byte_size: completed.size,
+    sha256: expectedSha256 ?? asset.sha256 ?? null,
+    sha256_verified: expectedSha256 !== null,
+    updated_at: now,
+  });
 });
 
+/** Hex SHA-256 of an R2 object, streamed so large assets aren't buffered. */
+async function objectSha256(bucket: R2Bucket, key: string): Promise<string | null> {
+  const object = await bucket.get(key);
+  if (!object) return null;
+  const digest = new crypto.DigestStream("SHA-256");
+  await object.body.pipeTo(digest);
+  return bytesToHex(new Uint8Array(await digest.digest));
+}
+
 // Abort multipart upload
 assetsRouter.post("/:id/abort", async (c) => {
   const { tenantType, tenantId, actorId } = requireTenant(c);
@@ -605,7 +713,173 @@ assetsRouter.post("/:id/link", async (c) => {
   return c.json({ ok: true, asset_id: assetId, memory_id: memoryId, relation, created_at: now });
 });
 
-// Download the asset object (supports range via byte_start/byte_end query)
+// Copy a ready asset into another project inside R2 (no bytes go through the client).
+assetsRouter.post("/:id/copy", async (c) => {
+  const { tenantType, tenantId, actorId } = requireTenant(c);
+  const sourceId = c.req.param("id");
+  const bucket = requireBucket(c.env);
+  const body = await c.req.json().catch(() => ({}));
+
+  const projectId = typeof body.project_id === "string" ? body.project_id.trim() : "";
+  if (!projectId) return c.json({ error: "project_id is required" }, 400);
+  const memoryId = typeof body.memory_id === "string" && body.memory_id.trim() ? body.memory_id.trim() : null;
+  const relation = typeof body.relation === "string" && body.relation.trim() ? body.relation.trim() : "attachment";
+
+  const source = await withDbClient(c.env, async (db) => {
+    const aRes = await db.query("SELECT * FROM assets WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+      sourceId,
+      tenantType,
+      tenantId,
+    ]);
+    const asset = aRes.rows[0] ?? null;
+    if (!asset) return null;
+
+    const projRes = await db.query("SELECT id FROM projects WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+      projectId,
+      tenantType,
+      tenantId,
+    ]);
+    if (projRes.rowCount === 0) throw new Error("Project not found (or not in tenant scope).");
+
+    if (memoryId) {
+      const mRes = await db.query("SELECT id, project_id FROM memories WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+        memoryId,
+        tenantType,
+        tenantId,
+      ]);
+      const mem = mRes.rows[0] ?? null;
+      if (!mem) throw new Error("Memory not found (or not in tenant scope).");
+      if (String(mem.project_id) !== projectId) throw new Error("Memory is not in the target project.");
+    }
+    return asset;
+  });
+
+  if (!source) return c.json({ error: "Asset not found" }, 404);
+  if (source.status !== "ready") return c.json({ error: "Only ready assets can be copied" }, 400);
+
+  const obj = await bucket.get(source.r2_key);
+  if (!obj) return c.json({ error: "Asset object missing from storage" }, 404);
+  if (obj.size > MAX_SINGLE_PUT_BYTES) {
+    await obj.body.cancel();
+    return c.json({ error: "Asset too large for server-side copy; download and re-upload it instead" }, 413);
+  }
+
+  const id = crypto.randomUUID();
+  const now = new Date().toISOString();
+  const objectKey = `${buildAssetPrefix({ tenantType, tenantId, projectId, assetId: id })}object`;
+
+  // R2 needs a known length for streamed puts.
+  const { readable, writable } = new FixedLengthStream(obj.size);
+  const [, copied] = await Promise.all([
+    obj.body.pipeTo(writable),
+    bucket.put(objectKey, readable, { httpMetadata: obj.httpMetadata }),
+  ]);
+
+  try {
+    await withDbClient(c.env, async (db) => {
+      await db.query(
+        `INSERT INTO assets (
+           id, tenant_type, tenant_id, project_id,
+           status, r2_bucket, r2_key,
+           content_type, byte_size, sha256, original_name, metadata,
+           created_at, updated_at, created_by, updated_by
+         )
+         VALUES ($1, $2, $3, $4, 'ready', $5, $6, $7, $8, $9, $10, $11::jsonb, $12, $13, $14, $15)`,
+        [
+          id,
+          tenantType,
+          tenantId,
+          projectId,
+          source.r2_bucket,
+          objectKey,
+          source.content_type,
+          copied?.size ?? obj.size,
+          source.sha256,
+          source.original_name,
+          JSON.stringify({ ...(source.metadata || {}), copied_from: sourceId }),
+          now,
+          now,
+          actorId,
+          actorId,
+        ]
+      );
+
+      if (memoryId) {
+        await db.query(
+          `INSERT INTO entity_links (
+             id, tenant_type, tenant_id,
+             from_type, from_id, to_type, to_id,
+             relation, metadata, created_at, created_by
+           )
+           VALUES ($1, $2, $3, 'memory', $4::uuid, 'asset', $5::uuid, $6, $7::jsonb, $8, $9)`,
+          [crypto.randomUUID(), tenantType, tenantId, memoryId, id, relation, JSON.stringify({}), now, actorId]
+        );
+      }
+    });
+  } catch (err) {
+    await bucket.delete(objectKey).catch(() => undefined);
+    throw err;
+  }
+
+  return c.json(
+    {
+      id,
+      copied_from: sourceId,
+      project_id: projectId,
+      status: "ready",
+      r2_key: objectKey,
+      byte_size: copied?.size ?? obj.size,
+      content_type: source.content_type,
+      original_name: source.original_name,
+      created_at: now,
+    },
+    201
+  );
+});
+
+// Mint a time-limited link to the asset object that works without credentials.
+assetsRouter.post("/:id/download-url", async (c) => {
+  const { tenantType, tenantId } = requireTenant(c);
+  const id = c.req.param("id");
+  const signingKey = c.env.ASSET_URL_SIGNING_KEY;
+  if (!signingKey) return c.json({ error: "Signed download URLs are not enabled on this server" }, 501);
+
+  const body = await c.req.json().catch(() => ({}));
+  const expiresIn = body.expires_in === undefined ? DEFAULT_LINK_TTL_SECONDS : Number(body.expires_in);
+  if (!Number.isInteger(expiresIn) || expiresIn < MIN_LINK_TTL_SECONDS || expiresIn > MAX_LINK_TTL_SECONDS) {
+    return c.json(
+      { error: `expires_in must be a whole number of seconds between ${MIN_LINK_TTL_SECONDS} and ${MAX_LINK_TTL_SECONDS}` },
+      400
+    );
+  }
+
+  const asset = await withDbClient(c.env, async (db) => {
+    const { rows } = await db.query("SELECT id, status, r2_key FROM assets WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+      id,
+      tenantType,
+      tenantId,
+    ]);
+    return rows[0] ?? null;
+  });
+  if (!asset) return c.json({ error: "Asset not found" }, 404);
+  if (asset.status !== "ready") return c.json({ error: "Asset is not ready" }, 400);
+
+  const expires = Math.floor(Date.now() / 1000) + expiresIn;
+  const sig = await signAssetLink(signingKey, id, asset.r2_key, expires);
+  const url = new URL(`/downloads/assets/${encodeURIComponent(id)}`, c.req.url);
+  url.searchParams.set("expires", String(expires));
+  url.searchParams.set("sig", sig);
+
+  return c.json({
+    id,
+    url: url.toString(),
+    expires_in: expiresIn,
+    expires_at: new Date(expires * 1000).toISOString(),
+  });
+});
+
+// Download the asset object (supports range via byte_start/byte_end query, or a single
+// `Range: bytes=a-b` header answered with 206)
 assetsRouter.get("/:id/object", async (c) => {
   const { tenantType, tenantId } = requireTenant(c);
   const id = c.req.param("id");
@@ -625,9 +899,22 @@ assetsRouter.get("/:id/object", async (c) => {
 
   const byteStartRaw = c.req.query("byte_start");
   const byteEndRaw = c.req.query("byte_end");
+  const rangeHeader = c.req.header("range");
   let obj: Awaited<ReturnType<R2Bucket["get"]>> | null = null;
-
-  if (byteStartRaw !== undefined || byteEndRaw !== undefined) {
+  let contentRange: string | null = null;
+
+  // Only the `bytes=first-last` / `bytes=first-` forms; anything else gets the whole object.
+  const rangeMatch = rangeHeader ? /^bytes=(\d+)-(\d*)$/.exec(rangeHeader.trim()) : null;
+  if (byteStartRaw === undefined && byteEndRaw === undefined && rangeMatch) {
+    const size = Number(asset.byte_size);
+    const first = parseInt(rangeMatch[1]);
+    const last = rangeMatch[2] ? Math.min(parseInt(rangeMatch[2]), size - 1) : size - 1;
+    if (first >= size || last < first) {
+      return new Response(null, { status: 416, headers: { "content-range": `bytes */${size}` } });
+    }
+    obj = await bucket.get(asset.r2_key, { range: { offset: first, length: last - first + 1 } });
+    contentRange = `bytes ${first}-${last}/${size}`;
+  } else if (byteStartRaw !== undefined || byteEndRaw !== undefined) {
     const byteStart = byteStartRaw !== undefined ? parseInt(byteStartRaw) : 0;
     const byteEnd = byteEndRaw !== undefined ? parseInt(byteEndRaw) : NaN;
     if (!Number.isFinite(byteStart) || byteStart < 0) return c.json({ error: "Invalid byte_start" }, 400);
@@ -647,7 +934,12 @@ assetsRouter.get("/:id/object", async (c) => {
   const headers: Record<string, string> = {
     "content-type": asset.content_type || "application/octet-stream",
     "content-disposition": contentDispositionAttachment(filename),
+    "accept-ranges": "bytes",
   };
+  if (contentRange) {
+    headers["content-range"] = contentRange;
+    return new Response(obj.body, { status: 206, headers });
+  }
 
   return new Response(obj.body, { headers });
 });
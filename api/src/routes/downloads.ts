@@ -1,5 +1,7 @@
 import { Hono } from "hono";
 import type { AppEnv } from "../appEnv";
+import { verifyAssetLink } from "../core/assetLinks";
+import { withDbClient } from "../db";
 
 function requireBucket(env: AppEnv["Bindings"]): R2Bucket {
   if (!env.MEMORY_BUCKET) {
@@ -65,5 +67,72 @@ downloadsRouter.get("/pajama", (c) => {
   });
 });
 downloadsRouter.on("HEAD", "/pajama", (c) => new Response(null, { status: 200 }));
+
+function parseReleaseVersion(prefix: string): number[] | null {
+  // `releases/pajama/v0.1.10/` -> [0, 1, 10]
+  const m = /\/v(\d+)\.(\d+)\.(\d+)\/$/.exec(prefix);
+  return m ? [Number(m[1]), Number(m[2]), Number(m[3])] : null;
+}
+
+function compareVersions(a: number[], b: number[]): number {
+  for (let i = 0; i < 3; i++) {
+    if (a[i] !== b[i]) return a[i] - b[i];
+  }
+  return 0;
+}
+
+// Newest published CLI release, for `pajama version --check`.
+downloadsRouter.get("/pajama/latest", async (c) => {
+  const bucket = requireBucket(c.env);
+  let latest: number[] | null = null;
+  let cursor: string | undefined;
+  do {
+    const page = await bucket.list({ prefix: `${PAJAMA_RELEASES_PREFIX}/`, delimiter: "/", cursor });
+    for (const prefix of page.delimitedPrefixes) {
+      const v = parseReleaseVersion(prefix);
+      if (v && (!latest || compareVersions(v, latest) > 0)) latest = v;
+    }
+    cursor = page.truncated ? page.cursor : undefined;
+  } while (cursor);
+
+  if (!latest) return c.json({ error: "No releases published" }, 404);
+  const version = latest.join(".");
+  c.header("cache-control", "public, max-age=300");
+  return c.json({
+    name: "pajama",
+    version,
+    changelog_url: `https://github.com/pajamadot/game-dev-memory/releases/tag/v${version}`,
+  });
+});
 downloadsRouter.get("/pajama/:version/:file", handlePajamaDownload);
 downloadsRouter.on("HEAD", "/pajama/:version/:file", handlePajamaDownload);
+
+// Signed asset links minted by `POST /api/assets/:id/download-url`; the signature stands
+// in for credentials, so every failure looks the same to the caller.
+downloadsRouter.get("/assets/:id", async (c) => {
+  const id = c.req.param("id");
+  const expires = Number(c.req.query("expires"));
+  const sig = c.req.query("sig") || "";
+  const signingKey = c.env.ASSET_URL_SIGNING_KEY;
+  const denied = () => c.json({ error: "Link is invalid or expired" }, 403);
+
+  if (!signingKey || !sig || !Number.isInteger(expires)) return denied();
+  if (expires < Math.floor(Date.now() / 1000)) return denied();
+
+  const asset = await withDbClient(c.env, async (db) => {
+    const { rows } = await db.query("SELECT status, r2_key, content_type, original_name FROM assets WHERE id = $1", [id]);
+    return rows[0] ?? null;
+  }).catch(() => null);
+  if (!asset || asset.status !== "ready") return denied();
+  if (!(await verifyAssetLink(signingKey, id, asset.r2_key, expires, sig))) return denied();
+
+  const obj = await requireBucket(c.env).get(asset.r2_key);
+  if (!obj) return c.json({ error: "Not found" }, 404);
+
+  const filename = String(asset.original_name || "asset.bin").replace(/[^\x20-\x7E]|["\\\/]/g, "_");
+  const headers = new Headers();
+  headers.set("content-type", asset.content_type || "application/octet-stream");
+  headers.set("content-disposition", `attachment; filename="${filename}"`);
+  headers.set("cache-control", "private, no-store");
+  return new Response(obj.body, { status: 200, headers });
+});
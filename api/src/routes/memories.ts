@@ -7,6 +7,8 @@ import {
   deleteMemory,
   getMemory,
   listMemories,
+  memoryVersion,
+  moveMemory,
   setMemoryLifecycle,
   updateMemory,
   type MemoryQuality,
@@ -15,6 +17,8 @@ import {
 } from "../core/memories";
 import { batchGetMemories, listMemorySearchProviders, listMemoryTimeline, searchMemoryIndex } from "../core/memoryRetrieval";
 import { deriveMemoryPlan } from "../core/memoryDerivation";
+import { claimIdempotencyKey } from "../core/idempotency";
+import { sha256Hex } from "../auth/crypto";
 
 export const memoriesRouter = new Hono<AppEnv>();
 
@@ -107,6 +111,15 @@ function parseIsoMs(v: unknown): number | null {
   return Number.isFinite(t) ? t : null;
 }
 
+// `If-Match: "<version>"` (the ETag from GET /:id). Returns undefined when absent or `*`,
+// and null when the header can't be a memory version (which never matches).
+function parseIfMatch(header: string | undefined): number | null | undefined {
+  const raw = (header || "").trim();
+  if (!raw || raw === "*") return undefined;
+  const m = /^(?:W\/)?"?(\d+)"?$/.exec(raw);
+  return m ? Number(m[1]) : null;
+}
+
 // List memories with optional filters
 memoriesRouter.get("/", async (c) => {
   const { tenantType, tenantId } = requireTenant(c);
@@ -121,6 +134,8 @@ memoriesRouter.get("/", async (c) => {
   const includeContent = includeContentParam === undefined || includeContentParam === null ? true : truthy(includeContentParam);
   const stateParam = c.req.query("state") || null;
   const limit = parseInt(c.req.query("limit") || "50");
+  const offsetRaw = parseInt(c.req.query("offset") || "0");
+  const offset = Number.isFinite(offsetRaw) && offsetRaw > 0 ? offsetRaw : 0;
 
   const states = parseStates({ includeInactive, stateParam });
 
@@ -134,11 +149,16 @@ memoriesRouter.get("/", async (c) => {
       states,
       memoryMode,
       limit,
+      offset,
       mode: includeContent ? "full" : "preview",
     })
   );
 
-  return c.json({ memories, meta: { total: memories.length, memory_mode: memoryMode } });
+  // `offset` is echoed only when it was applied, so clients can detect unsupported paging (search).
+  return c.json({
+    memories,
+    meta: { total: memories.length, memory_mode: memoryMode, ...(search ? {} : { offset }) },
+  });
 });
 
 // List search providers available for progressive-disclosure retrieval.
@@ -476,35 +496,83 @@ memoriesRouter.get("/:id", async (c) => {
   const memory = await withDbClient(c.env, async (db) => await getMemory(db, tenantType, tenantId, id));
 
   if (!memory) return c.json({ error: "Memory not found" }, 404);
-  return c.json(memory);
+  const version = memoryVersion(memory.updated_at);
+  if (version !== null) c.header("ETag", `"${version}"`);
+  return c.json({ ...memory, version });
 });
 
-// Create memory
+// Create memory. An `Idempotency-Key` header makes retries safe: a repeated request with
+// the same key and body returns the memory created the first time instead of a duplicate.
 memoriesRouter.post("/", async (c) => {
   const { tenantType, tenantId, actorId } = requireTenant(c);
   const body = await c.req.json();
   const id = crypto.randomUUID();
   const now = new Date().toISOString();
+  const idempotencyKey = (c.req.header("Idempotency-Key") || "").trim();
+  if (idempotencyKey.length > 255) {
+    return c.json({ error: "Idempotency-Key must be at most 255 characters" }, 400);
+  }
+  const requestHash = idempotencyKey ? await sha256Hex(`POST /api/memories\n${JSON.stringify(body)}`) : "";
 
-  await withDbClient(c.env, async (db) => {
-    await createMemory(db, {
-      tenantType,
-      tenantId,
-      actorId,
-      id,
-      projectId: body.project_id,
-      sessionId: body.session_id ?? null,
-      category: body.category,
-      sourceType: body.source_type ?? "manual",
-      title: body.title,
-      content: body.content,
-      tags: body.tags || [],
-      context: body.context || {},
-      confidence: body.confidence ?? 0.5,
-      nowIso: now,
-    });
+  const replay = await withDbClient(c.env, async (db) => {
+    // The key claim and the insert commit together (see claimIdempotencyKey).
+    await db.query("BEGIN");
+    try {
+      if (idempotencyKey) {
+        const claim = await claimIdempotencyKey(db, {
+          tenantType,
+          tenantId,
+          key: idempotencyKey,
+          requestHash,
+          resourceId: id,
+          nowIso: now,
+        });
+        if (!claim.claimed) {
+          const { rows } = await db.query("SELECT 1 FROM memories WHERE id = $1 AND tenant_type = $2 AND tenant_id = $3", [
+            claim.resourceId,
+            tenantType,
+            tenantId,
+          ]);
+          await db.query("ROLLBACK");
+          return { ...claim, exists: rows.length > 0 };
+        }
+      }
+
+      await createMemory(db, {
+        tenantType,
+        tenantId,
+        actorId,
+        id,
+        projectId: body.project_id,
+        sessionId: body.session_id ?? null,
+        category: body.category,
+        sourceType: body.source_type ?? "manual",
+        title: body.title,
+        content: body.content,
+        tags: body.tags || [],
+        context: body.context || {},
+        confidence: body.confidence ?? 0.5,
+        nowIso: now,
+      });
+      await db.query("COMMIT");
+    } catch (err) {
+      await db.query("ROLLBACK");
+      throw err;
+    }
+    return null;
   });
 
+  if (replay) {
+    if (replay.requestHash !== requestHash) {
+      return c.json({ error: "Idempotency-Key was already used for a different request" }, 422);
+    }
+    if (!replay.exists) {
+      return c.json({ error: "The memory created with this Idempotency-Key has since been deleted" }, 410);
+    }
+    c.header("Idempotent-Replayed", "true");
+    return c.json({ id: replay.resourceId, created_at: replay.createdAt }, 201);
+  }
+
   return c.json({ id, created_at: now }, 201);
 });
 
@@ -514,9 +582,13 @@ memoriesRouter.put("/:id", async (c) => {
   const id = c.req.param("id");
   const body = await c.req.json();
   const now = new Date().toISOString();
+  const expectedVersion = parseIfMatch(c.req.header("If-Match"));
+  if (expectedVersion === null) {
+    return c.json({ error: "If-Match must be a memory version (the ETag from GET /api/memories/:id)" }, 412);
+  }
 
-  await withDbClient(c.env, async (db) => {
-    await updateMemory(db, {
+  const result = await withDbClient(c.env, async (db) => {
+    return await updateMemory(db, {
       tenantType,
       tenantId,
       actorId,
@@ -529,10 +601,16 @@ memoriesRouter.put("/:id", async (c) => {
       category: body.category,
       sourceType: body.source_type ?? "manual",
       nowIso: now,
+      expectedVersion: expectedVersion ?? null,
     });
   });
 
-  return c.json({ id, updated_at: now });
+  if (result.conflict) {
+    if (result.version !== null) c.header("ETag", `"${result.version}"`);
+    return c.json({ error: "Memory was modified since it was read", current_version: result.version }, 412);
+  }
+  if (result.version !== null) c.header("ETag", `"${result.version}"`);
+  return c.json({ id, updated_at: now, version: result.version });
 });
 
 // Delete memory
@@ -583,6 +661,29 @@ memoriesRouter.post("/:id/lifecycle", async (c) => {
   return c.json({ ok: true, ...updated });
 });
 
+// Move a memory to another project in the same tenant.
+memoriesRouter.post("/:id/move", async (c) => {
+  const { tenantType, tenantId, actorId } = requireTenant(c);
+  const id = c.req.param("id");
+  const body = await c.req.json().catch(() => ({}));
+  const now = new Date().toISOString();
+
+  const projectId = typeof body.project_id === "string" && body.project_id.trim() ? body.project_id.trim() : null;
+  if (!projectId) return c.json({ ok: false, error: "project_id is required" }, 400);
+
+  let moved: Awaited<ReturnType<typeof moveMemory>> = null;
+  try {
+    moved = await withDbClient(c.env, async (db) => {
+      return await moveMemory(db, { tenantType, tenantId, actorId, id, projectId, nowIso: now });
+    });
+  } catch (err) {
+    return c.json({ ok: false, error: err instanceof Error ? err.message : String(err) }, 400);
+  }
+
+  if (!moved) return c.json({ ok: false, error: "Memory not found" }, 404);
+  return c.json({ ok: true, ...moved });
+});
+
 // Link memory -> memory (entity_links). Useful for "supersedes", "contradicts", "supports", etc.
 memoriesRouter.post("/:id/link", async (c) => {
   const { tenantType, tenantId, actorId } = requireTenant(c);
@@ -21,6 +21,10 @@ export interface Env {
   // Used by OAuth /authorize to redirect to the web consent page.
   WEBSITE_URL?: string;
 
+  // HMAC key for signed asset download links (`POST /api/assets/:id/download-url`).
+  // Unset disables the endpoint.
+  ASSET_URL_SIGNING_KEY?: string;
+
   // Temporary escape hatch for local testing only.
   // If true, allows X-Tenant-* headers when Authorization is missing.
   ALLOW_INSECURE_TENANT_HEADERS?: string;
@@ -2,28 +2,194 @@ use anyhow::{Context, Result, anyhow};
 use reqwest::header;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use url::Url;
 
+use crate::cache;
+use crate::output::{self, Level, log_warn};
+use crate::resolve::{self, IpPreference};
+use crate::throttle::RequestLimiter;
+use crate::timing::{self, RequestTiming};
+
+/// API version this CLI was built against; sent as `X-API-Version` unless overridden.
+pub const API_VERSION: &str = "1";
+
+/// Clock difference from the server beyond which the CLI warns (time-based checks
+/// such as token expiry go wrong when the local clock is off).
+pub const CLOCK_SKEW_WARN_SECS: i64 = 60;
+
+/// Server clock minus local clock, in seconds, from the latest `Date` header;
+/// `i64::MIN` until a response carried one.
+static CLOCK_SKEW: AtomicI64 = AtomicI64::new(i64::MIN);
+static CLOCK_SKEW_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Tries (first request plus retries) for `post_json_idempotent`.
+const IDEMPOTENT_ATTEMPTS: u32 = 3;
+
+/// Tries for a request the server answers with 429 while `--rps` is set.
+const RATE_LIMITED_ATTEMPTS: u32 = 4;
+
+/// How long `get_json` reuses a response body within one invocation.
+const SESSION_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// `get_json` bodies by `GET <url>`, shared by a client's clones.
+type SessionCache = Mutex<HashMap<String, (Instant, String)>>;
+
+/// Per-invocation knobs for the HTTP client (mostly from global CLI flags).
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Appended after the `pajama-cli/<version>` product token.
+    pub user_agent_suffix: Option<String>,
+    /// Extra `(name, value)` headers sent with every request.
+    pub extra_headers: Vec<(String, String)>,
+    /// Let an `Authorization` entry in `extra_headers` replace the bearer token.
+    pub allow_override_auth: bool,
+    /// Dump every request/response (credentials redacted) to stderr.
+    pub debug_http: bool,
+    /// Value for the `X-API-Version` header (`--api-version`); `None` sends no header.
+    pub api_version: Option<String>,
+    /// Value for the `Accept-Language` header (`--accept-language` or the system locale);
+    /// `None` sends no header.
+    pub accept_language: Option<String>,
+    /// TLS client certificate for gateways that require mutual TLS.
+    pub client_identity: Option<ClientIdentity>,
+    /// Address family to connect over first (`--prefer-ipv4` / `--prefer-ipv6`).
+    pub ip_preference: Option<IpPreference>,
+    /// Log DNS, connect, time-to-first-byte and total time for every request.
+    pub trace_timing: bool,
+    /// Cap on requests per second across all of this client's clones (`--rps`).
+    pub rps: Option<f64>,
+    /// Redirects followed per request (`--max-redirects`); 0 returns the 3xx as is.
+    pub max_redirects: usize,
+    /// Reuse `get_json` responses within this process (`--session-cache`).
+    pub session_cache: bool,
+}
+
+/// Where the mutual-TLS client certificate comes from.
+#[derive(Debug, Clone)]
+pub enum ClientIdentity {
+    /// PEM certificate (chain) and private key (`--client-cert` / `--client-key`).
+    Pem { cert: PathBuf, key: PathBuf },
+    /// PKCS#12 bundle (`--client-pfx` / `--pfx-password`).
+    Pkcs12 { path: PathBuf, password: String },
+}
+
 #[derive(Clone)]
 pub struct ApiClient {
     base: Url,
     client: reqwest::Client,
-    token: String,
+    /// Full `Authorization` header value (normally `Bearer <token>`); `None` for public
+    /// endpoints called without a token, which get no header at all.
+    authorization: Option<String>,
+    /// Copy of the client's `--header` defaults, which `RequestBuilder::build` doesn't include.
+    default_headers: header::HeaderMap,
+    debug_http: bool,
+    trace_timing: bool,
+    api_version: Option<String>,
+    limiter: Option<Arc<RequestLimiter>>,
+    session_cache: Option<Arc<SessionCache>>,
 }
 
 impl ApiClient {
-    pub fn new(api_base_url: &str, token: &str) -> Result<Self> {
-        let base = Url::parse(api_base_url)
+    pub fn new(api_base_url: &str, token: &str, opts: &ClientOptions) -> Result<Self> {
+        let mut base = Url::parse(api_base_url)
             .with_context(|| format!("invalid api base url: {api_base_url}"))?;
-        let client = reqwest::Client::builder()
-            .user_agent(format!("pajama-cli/{}", env!("CARGO_PKG_VERSION")))
+        // `Url::join` replaces the last path segment unless it ends with '/', which
+        // would turn `https://host/v2` + `api/...` into `https://host/api/...`.
+        if !base.path().ends_with('/') {
+            let path = format!("{}/", base.path());
+            base.set_path(&path);
+        }
+        let mut authorization = (!token.is_empty()).then(|| format!("Bearer {token}"));
+        let mut default_headers = header::HeaderMap::new();
+        for (name, value) in &opts.extra_headers {
+            let name = header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name: {name}"))?;
+            let value = header::HeaderValue::from_str(value)
+                .with_context(|| format!("invalid value for header {name}"))?;
+            if name == header::AUTHORIZATION {
+                if !opts.allow_override_auth {
+                    return Err(anyhow!(
+                        "refusing to override Authorization via --header (pass --allow-override-auth)"
+                    ));
+                }
+                // Per-request headers win over defaults, so route this through the auth field.
+                authorization = Some(
+                    value
+                        .to_str()
+                        .context("Authorization header must be visible ASCII")?
+                        .to_string(),
+                );
+                continue;
+            }
+            default_headers.append(name, value);
+        }
+        // An explicit `--header X-API-Version: ...` wins over `--api-version`.
+        if let Some(version) = opts
+            .api_version
+            .as_deref()
+            .filter(|_| !default_headers.contains_key("x-api-version"))
+        {
+            let value = header::HeaderValue::from_str(version)
+                .with_context(|| format!("invalid --api-version: {version:?}"))?;
+            default_headers.insert("x-api-version", value);
+        }
+        // Same for `--header Accept-Language: ...` over `--accept-language`.
+        if let Some(language) = opts
+            .accept_language
+            .as_deref()
+            .filter(|_| !default_headers.contains_key(header::ACCEPT_LANGUAGE))
+        {
+            let value = header::HeaderValue::from_str(language)
+                .with_context(|| format!("invalid --accept-language: {language:?}"))?;
+            default_headers.insert(header::ACCEPT_LANGUAGE, value);
+        }
+
+        let mut builder = resolve::apply(
+            reqwest::Client::builder(),
+            opts.ip_preference,
+            opts.trace_timing,
+        );
+        if let Some(identity) = &opts.client_identity {
+            builder = with_identity(builder, identity)?;
+        }
+        // `Authorization` is routed to its own field above, so what is left are
+        // `--header` values reqwest knows nothing about.
+        let custom_headers = opts
+            .extra_headers
+            .iter()
+            .any(|(name, _)| !name.eq_ignore_ascii_case("authorization"));
+        let client = builder
+            .user_agent(user_agent(opts.user_agent_suffix.as_deref())?)
+            .default_headers(default_headers.clone())
+            .redirect(redirect_policy(opts.max_redirects, custom_headers))
+            // Advertises Accept-Encoding and transparently decompresses bodies, so
+            // `parse_json` keeps reading plain text via `res.text()`.
+            .gzip(true)
+            .brotli(true)
+            .deflate(true)
             .build()
-            .context("build http client")?;
+            .context(if opts.client_identity.is_some() {
+                "build http client (check that the client key matches the certificate)"
+            } else {
+                "build http client"
+            })?;
 
         Ok(Self {
             base,
             client,
-            token: token.to_string(),
+            authorization,
+            default_headers,
+            debug_http: opts.debug_http,
+            trace_timing: opts.trace_timing,
+            api_version: opts.api_version.clone(),
+            limiter: opts.rps.map(RequestLimiter::new),
+            session_cache: opts.session_cache.then(Default::default),
         })
     }
 
@@ -34,24 +200,141 @@ impl ApiClient {
             .with_context(|| format!("join url path: {path}"))
     }
 
+    /// Request builder carrying the `Authorization` header, when there is one.
+    fn request(&self, method: reqwest::Method, url: Url) -> reqwest::RequestBuilder {
+        let req = self.client.request(method, url);
+        match &self.authorization {
+            Some(value) => req.header(header::AUTHORIZATION, value),
+            None => req,
+        }
+    }
+
+    fn get(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::RequestBuilder> {
+        let url = self.url(path)?;
+        let mut req = self.request(reqwest::Method::GET, url);
+
+        if !query.is_empty() {
+            let pairs: Vec<(&str, &str)> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            req = req.query(&pairs);
+        }
+        Ok(req)
+    }
+
     pub async fn get_json<T: DeserializeOwned>(
         &self,
         path: &str,
         query: &[(&str, String)],
     ) -> Result<T> {
-        let url = self.url(path)?;
-        let mut req = self
-            .client
-            .get(url)
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.token));
+        let Some(cache) = &self.session_cache else {
+            return self.get_json_fresh(path, query).await;
+        };
+        let req = self.get(path, query)?.build().context("http get")?;
+        let key = format!("GET {}", req.url());
+        let hit = {
+            let entries = cache.lock().unwrap_or_else(|e| e.into_inner());
+            entries
+                .get(&key)
+                .filter(|(at, _)| at.elapsed() < SESSION_CACHE_TTL)
+                .map(|(_, body)| body.clone())
+        };
+        if let Some(body) = hit {
+            if self.debug_http {
+                output::log(
+                    Level::Debug,
+                    &format!("http {key} answered from the session cache"),
+                    &[("direction", "cache".into()), ("bytes", body.len().into())],
+                );
+            }
+            return parse_body(&body);
+        }
+        let req = reqwest::RequestBuilder::from_parts(self.client.clone(), req);
+        let res = self.send(req, "http get").await?;
+        let body = self.read_body(res).await?;
+        cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, (Instant::now(), body.clone()));
+        parse_body(&body)
+    }
 
+    /// `get_json` that always goes to the server, for polls that wait for a state change.
+    pub async fn get_json_fresh<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let res = self.send(self.get(path, query)?, "http get").await?;
+        self.parse_json(res).await
+    }
+
+    /// `get_json` for list endpoints whose bodies can be large (see `parse_json_streamed`).
+    pub async fn get_json_streamed<T: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let res = self.send(self.get(path, query)?, "http get").await?;
+        self.parse_json_streamed(res).await
+    }
+
+    /// `get_json` with a conditional-request cache: when the server sent an `ETag` or
+    /// `Last-Modified` last time, revalidate and reuse the stored body on `304`.
+    pub async fn get_json_cached<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let url = self.url(path)?;
+        let mut req = self.request(reqwest::Method::GET, url);
         if !query.is_empty() {
             let pairs: Vec<(&str, &str)> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
             req = req.query(&pairs);
         }
+        let req = req.build().context("http get")?;
+
+        // Key on the full URL and the credential so accounts never share entries.
+        let mut h = Sha256::new();
+        h.update(req.url().as_str().as_bytes());
+        h.update(b"\n");
+        h.update(self.authorization.as_deref().unwrap_or_default().as_bytes());
+        let key = format!("{:x}", h.finalize());
 
-        let res = req.send().await.context("http get")?;
-        parse_json_response(res).await
+        let cached = cache::load(&key);
+        let mut req = reqwest::RequestBuilder::from_parts(self.client.clone(), req);
+        if let Some(c) = &cached {
+            if let Some(etag) = &c.etag {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            } else if let Some(last_modified) = &c.last_modified {
+                req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let res = self.send(req, "http get").await?;
+        if let (reqwest::StatusCode::NOT_MODIFIED, Some(c)) = (res.status(), &cached) {
+            return parse_body(&c.body);
+        }
+        let header_str = |name| {
+            res.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let etag = header_str(header::ETAG);
+        let last_modified = header_str(header::LAST_MODIFIED);
+
+        let text = self.read_body(res).await?;
+        let parsed = parse_body(&text)?;
+        if etag.is_some() || last_modified.is_some() {
+            cache::store(
+                &key,
+                &cache::CachedResponse {
+                    etag,
+                    last_modified,
+                    body: text,
+                },
+            );
+        }
+        Ok(parsed)
     }
 
     pub async fn post_json<T: DeserializeOwned, B: Serialize>(
@@ -60,72 +343,1050 @@ impl ApiClient {
         body: &B,
     ) -> Result<T> {
         let url = self.url(path)?;
-        let res = self
-            .client
-            .post(url)
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.token))
+        let req = self
+            .request(reqwest::Method::POST, url)
             .header(header::CONTENT_TYPE, "application/json")
-            .json(body)
-            .send()
-            .await
-            .context("http post")?;
-        parse_json_response(res).await
+            .json(body);
+        let res = self.send(req, "http post").await?;
+        self.parse_json(res).await
+    }
+
+    /// POST JSON with an `Idempotency-Key`. Connection failures and 409/502/503/504
+    /// responses are retried with the same key, so the server returns the first attempt's
+    /// result instead of creating a duplicate.
+    pub async fn post_json_idempotent<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+        key: &str,
+    ) -> Result<T> {
+        let url = self.url(path)?;
+        let mut attempt = 1;
+        loop {
+            let req = self
+                .request(reqwest::Method::POST, url.clone())
+                .header(header::CONTENT_TYPE, "application/json")
+                .header("Idempotency-Key", key)
+                .json(body);
+            let reason = match self.send(req, "http post").await {
+                Ok(res) if attempt < IDEMPOTENT_ATTEMPTS && retryable_status(res.status()) => {
+                    format!("HTTP {}", res.status())
+                }
+                Ok(res) => return self.parse_json(res).await,
+                Err(err) if attempt < IDEMPOTENT_ATTEMPTS => format!("{err:#}"),
+                Err(err) => return Err(err),
+            };
+            let delay = std::time::Duration::from_millis(500 << (attempt - 1));
+            log_warn!(
+                "POST {path} failed ({reason}); retrying in {}ms with the same idempotency key",
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    pub async fn put_json<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
+        let url = self.url(path)?;
+        let req = self
+            .request(reqwest::Method::PUT, url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(body);
+        let res = self.send(req, "http put").await?;
+        self.parse_json(res).await
+    }
+
+    /// PUT JSON, optionally conditional on `If-Match`. The inner `Err` carries the
+    /// response body of a 412: the resource changed since that version was read.
+    pub async fn put_json_if_match<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+        if_match: Option<&str>,
+    ) -> Result<Result<T, serde_json::Value>> {
+        let url = self.url(path)?;
+        let mut req = self
+            .request(reqwest::Method::PUT, url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(body);
+        if let Some(etag) = if_match {
+            req = req.header(header::IF_MATCH, etag);
+        }
+        let res = self.send(req, "http put").await?;
+        if res.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            let text = res.text().await.unwrap_or_default();
+            return Ok(Err(
+                serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+            ));
+        }
+        self.parse_json(res).await.map(Ok)
+    }
+
+    pub async fn patch_json<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
+        let url = self.url(path)?;
+        let req = self
+            .request(reqwest::Method::PATCH, url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(body);
+        let res = self.send(req, "http patch").await?;
+        self.parse_json(res).await
     }
 
-    pub async fn put_bytes<T: DeserializeOwned>(
+    /// PUT a raw body of `len` bytes. The length is sent explicitly because streamed
+    /// (throttled) bodies don't carry one, and R2 parts must declare their size.
+    pub async fn put_body<T: DeserializeOwned>(
         &self,
         path: &str,
         content_type: &str,
-        bytes: Vec<u8>,
+        body: reqwest::Body,
+        len: u64,
     ) -> Result<T> {
         let url = self.url(path)?;
-        let res = self
-            .client
-            .put(url)
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.token))
+        let req = self
+            .request(reqwest::Method::PUT, url)
             .header(header::CONTENT_TYPE, content_type)
-            .body(bytes)
-            .send()
-            .await
-            .context("http put")?;
-        parse_json_response(res).await
+            .header(header::CONTENT_LENGTH, len)
+            .body(body);
+        let res = self.send(req, "http put").await?;
+        self.parse_json(res).await
     }
 
-    #[allow(dead_code)]
     pub async fn delete_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
         let url = self.url(path)?;
-        let res = self
-            .client
-            .delete(url)
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.token))
-            .send()
-            .await
-            .context("http delete")?;
-        parse_json_response(res).await
+        let req = self.request(reqwest::Method::DELETE, url);
+        let res = self.send(req, "http delete").await?;
+        self.parse_json(res).await
     }
 
     pub async fn raw_get(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::Response> {
+        let req = self.raw_get_request(path, query)?;
+        let res = self.send(req, "http get").await?;
+        Ok(res)
+    }
+
+    /// `raw_get` of bytes `first..=last`. Servers that honor `Range` answer 206; a 200
+    /// carries the whole object.
+    pub async fn raw_get_range(
+        &self,
+        path: &str,
+        first: u64,
+        last: u64,
+    ) -> Result<reqwest::Response> {
+        let req = self
+            .raw_get_request(path, &[])?
+            .header(header::RANGE, format!("bytes={first}-{last}"));
+        self.send(req, "http get").await
+    }
+
+    fn raw_get_request(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<reqwest::RequestBuilder> {
         let url = self.url(path)?;
         let mut req = self
-            .client
-            .get(url)
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.token));
+            .request(reqwest::Method::GET, url)
+            // Raw object bytes: keep sizes comparable with Content-Length.
+            .header(header::ACCEPT_ENCODING, "identity");
 
         if !query.is_empty() {
             let pairs: Vec<(&str, &str)> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
             req = req.query(&pairs);
         }
+        Ok(req)
+    }
 
-        let res = req.send().await.context("http get")?;
+    /// POST JSON and hand back the response as-is, for callers that branch on the status.
+    pub async fn raw_post_json<B: Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<reqwest::Response> {
+        let url = self.url(path)?;
+        let req = self
+            .request(reqwest::Method::POST, url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(body);
+        self.send(req, "http post").await
+    }
+
+    pub async fn head(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::Response> {
+        let url = self.url(path)?;
+        let mut req = self
+            .request(reqwest::Method::HEAD, url)
+            // Raw object bytes: keep sizes comparable with Content-Length.
+            .header(header::ACCEPT_ENCODING, "identity");
+
+        if !query.is_empty() {
+            let pairs: Vec<(&str, &str)> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            req = req.query(&pairs);
+        }
+
+        let res = self.send(req, "http head").await?;
+        Ok(res)
+    }
+
+    async fn send(
+        &self,
+        req: reqwest::RequestBuilder,
+        what: &'static str,
+    ) -> Result<reqwest::Response> {
+        let mut req = req.build().context(what)?;
+        // Any write may change what an earlier GET returned.
+        if let Some(cache) = self
+            .session_cache
+            .as_ref()
+            .filter(|_| !matches!(*req.method(), reqwest::Method::GET | reqwest::Method::HEAD))
+        {
+            cache.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        let mut attempt = 1;
+        loop {
+            // Under `--rps`, a 429 is retried at the lowered rate if the body can be replayed
+            // (streamed uploads can't).
+            let retry = match &self.limiter {
+                Some(_) if attempt < RATE_LIMITED_ATTEMPTS => req.try_clone(),
+                _ => None,
+            };
+            let (method, path) = (req.method().clone(), req.url().path().to_string());
+            let res = self.send_once(req, what).await?;
+            let lowered = match &self.limiter {
+                Some(limiter) => {
+                    limiter
+                        .observe(res.status(), retry_after_secs(res.headers()))
+                        .await
+                }
+                None => None,
+            };
+            if let Some(rps) = lowered {
+                log_warn!("server answered 429; lowering the request rate to {rps:.2}/s");
+            }
+            match retry {
+                Some(next) if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    log_warn!("{method} {path} was rate limited; retrying");
+                    req = next;
+                    attempt += 1;
+                }
+                _ => return Ok(res),
+            }
+        }
+    }
+
+    async fn send_once(
+        &self,
+        req: reqwest::Request,
+        what: &'static str,
+    ) -> Result<reqwest::Response> {
+        if self.debug_http {
+            debug_request(&req, &self.default_headers);
+        }
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        let res = if self.trace_timing {
+            let (method, url) = (req.method().clone(), req.url().clone());
+            let started = std::time::Instant::now();
+            let (res, phases) = timing::observe(self.client.execute(req)).await;
+            let timing = RequestTiming::new(
+                &method,
+                &url,
+                res.as_ref().ok().map(|r| r.status()),
+                started,
+                phases,
+            );
+            // Kept with the response so the line is logged once its body has been read.
+            res.map(|mut r| {
+                r.extensions_mut().insert(Arc::new(timing));
+                r
+            })
+        } else {
+            self.client.execute(req).await
+        };
+        let res = res.context(what)?;
+        note_clock_skew(res.headers());
+        if self.debug_http {
+            output::log(
+                Level::Debug,
+                &format!("http < {}", res.status()),
+                &[
+                    ("direction", "response".into()),
+                    ("url", res.url().as_str().into()),
+                    ("status", res.status().as_u16().into()),
+                ],
+            );
+            debug_headers("<", res.headers());
+        }
         Ok(res)
     }
+
+    /// Body of a 2xx response as JSON (see `read_body` for errors).
+    pub async fn parse_json<T: DeserializeOwned>(&self, res: reqwest::Response) -> Result<T> {
+        let text = self.read_body(res).await?;
+        parse_body(&text)
+    }
+
+    /// `parse_json` without buffering the body: chunks are handed to `serde_json::from_reader`
+    /// on a blocking thread as they arrive, so peak memory is the parsed value plus a few
+    /// chunks instead of the value plus the whole body text. Error statuses and
+    /// `--debug-http` (which logs the body) take the text path.
+    pub async fn parse_json_streamed<T: DeserializeOwned + Send + 'static>(
+        &self,
+        mut res: reqwest::Response,
+    ) -> Result<T> {
+        if self.debug_http || !res.status().is_success() {
+            return self.parse_json(res).await;
+        }
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_CHANNEL_CHUNKS);
+        let parse = tokio::task::spawn_blocking(move || {
+            // serde_json asks for one byte per `read`; buffer so each call is a slice copy.
+            let reader = std::io::BufReader::with_capacity(
+                STREAM_READ_BUFFER,
+                ChunkReader {
+                    rx,
+                    chunk: Vec::new(),
+                    pos: 0,
+                },
+            );
+            serde_json::from_reader::<_, T>(reader)
+        });
+        let pump = async move {
+            loop {
+                let next = match res.chunk().await {
+                    Ok(Some(chunk)) => Ok(Vec::from(chunk)),
+                    Ok(None) => break,
+                    Err(err) => Err(std::io::Error::other(err)),
+                };
+                let failed = next.is_err();
+                // A closed channel means the parser already stopped on an error.
+                if tx.send(next).await.is_err() || failed {
+                    break;
+                }
+            }
+        };
+        let ((), parsed) = tokio::join!(pump, parse);
+        parsed
+            .context("parse json response")?
+            .context("parse json response (streamed)")
+    }
+
+    /// Response text for a 2xx status; anything else becomes an `HTTP <status>` error.
+    async fn read_body(&self, mut res: reqwest::Response) -> Result<String> {
+        let status = res.status();
+        let request_id = request_id(res.headers());
+        // `text()` drops the extensions up front; hold the timing until the body is in.
+        let timing = res
+            .extensions_mut()
+            .remove::<std::sync::Arc<RequestTiming>>();
+        let text = res.text().await.unwrap_or_default();
+        drop(timing);
+        if self.debug_http {
+            output::log(
+                Level::Debug,
+                &format!("http < {text}"),
+                &[
+                    ("direction", "response".into()),
+                    ("status", status.as_u16().into()),
+                    ("bytes", text.len().into()),
+                ],
+            );
+        }
+        if let Some(version) = self
+            .api_version
+            .as_deref()
+            .filter(|_| status == reqwest::StatusCode::NOT_ACCEPTABLE)
+        {
+            return Err(HttpError {
+                status,
+                request_id,
+                message: format!(
+                    "the server does not support API version {version} (set --api-version or PAJAMA_API_VERSION): {text}"
+                ),
+            }
+            .into());
+        }
+        if !status.is_success() {
+            return Err(HttpError {
+                status,
+                request_id,
+                message: text,
+            }
+            .into());
+        }
+        Ok(text)
+    }
+}
+
+/// Non-2xx API response. Displays as `HTTP <status>: <body>`; `--json-errors` also
+/// reports the status and request id.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: reqwest::StatusCode,
+    pub request_id: Option<String>,
+    message: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Id the server or the edge in front of it assigned to a request, for bug reports.
+fn request_id(headers: &header::HeaderMap) -> Option<String> {
+    ["x-request-id", "cf-ray"]
+        .iter()
+        .find_map(|name| headers.get(*name)?.to_str().ok())
+        .map(str::to_string)
+}
+
+fn with_identity(
+    builder: reqwest::ClientBuilder,
+    identity: &ClientIdentity,
+) -> Result<reqwest::ClientBuilder> {
+    match identity {
+        ClientIdentity::Pem { cert, key } => {
+            // rustls wants the certificate chain and the key in one PEM buffer.
+            let mut pem = std::fs::read(cert)
+                .with_context(|| format!("read client certificate {}", cert.display()))?;
+            pem.push(b'\n');
+            pem.extend(
+                std::fs::read(key).with_context(|| format!("read client key {}", key.display()))?,
+            );
+            let identity = reqwest::Identity::from_pem(&pem).with_context(|| {
+                format!(
+                    "load client certificate {} with key {} (expected PEM)",
+                    cert.display(),
+                    key.display()
+                )
+            })?;
+            Ok(builder.use_rustls_tls().identity(identity))
+        }
+        ClientIdentity::Pkcs12 { path, password } => pkcs12_identity(builder, path, password),
+    }
+}
+
+#[cfg(feature = "pkcs12")]
+fn pkcs12_identity(
+    builder: reqwest::ClientBuilder,
+    path: &std::path::Path,
+    password: &str,
+) -> Result<reqwest::ClientBuilder> {
+    let der = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    let identity = reqwest::Identity::from_pkcs12_der(&der, password).with_context(|| {
+        format!(
+            "load PKCS#12 client certificate {} (wrong --pfx-password?)",
+            path.display()
+        )
+    })?;
+    // rustls can't read PKCS#12, so this identity needs the native TLS backend.
+    Ok(builder.use_native_tls().identity(identity))
+}
+
+#[cfg(not(feature = "pkcs12"))]
+fn pkcs12_identity(
+    _builder: reqwest::ClientBuilder,
+    path: &std::path::Path,
+    _password: &str,
+) -> Result<reqwest::ClientBuilder> {
+    Err(anyhow!(
+        "--client-pfx needs a build with the `pkcs12` feature; or convert the bundle to PEM \
+         (openssl pkcs12 -in {} -out client.pem -nodes) and pass it as --client-cert and --client-key",
+        path.display()
+    ))
+}
+
+fn user_agent(suffix: Option<&str>) -> Result<String> {
+    // Keep the product token first so server-side parsing stays stable.
+    let base = format!("pajama-cli/{}", env!("CARGO_PKG_VERSION"));
+    let Some(suffix) = suffix.map(|s| s.trim()).filter(|s| !s.is_empty()) else {
+        return Ok(base);
+    };
+    if suffix
+        .chars()
+        .any(|c| !c.is_ascii() || c.is_ascii_control())
+    {
+        return Err(anyhow!(
+            "invalid user agent suffix (printable ASCII only): {suffix:?}"
+        ));
+    }
+    Ok(format!("{base} {suffix}"))
+}
+
+/// Language tag for `Accept-Language` from the POSIX locale variables (`LC_ALL`, then
+/// `LC_MESSAGES`, then `LANG`): `de_DE.UTF-8` becomes `de-DE`. Falls back to `en` when
+/// none is set or the locale is `C`/`POSIX`.
+pub fn system_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|v| !v.trim().is_empty())
+        .and_then(|locale| language_tag(&locale))
+        .unwrap_or_else(|| "en".to_string())
+}
+
+fn language_tag(locale: &str) -> Option<String> {
+    // Drop the `.codeset` and `@modifier` parts.
+    let name = locale.trim().split(['.', '@']).next().unwrap_or_default();
+    if name.is_empty() || name == "C" || name == "POSIX" {
+        return None;
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return None;
+    }
+    Some(name.replace('_', "-"))
+}
+
+fn debug_request(req: &reqwest::Request, defaults: &header::HeaderMap) {
+    output::log(
+        Level::Debug,
+        &format!("http > {} {}", req.method(), req.url()),
+        &[
+            ("direction", "request".into()),
+            ("method", req.method().as_str().into()),
+            ("url", req.url().as_str().into()),
+        ],
+    );
+    debug_headers(">", req.headers());
+    let mut inherited = header::HeaderMap::new();
+    for (name, value) in defaults {
+        if !req.headers().contains_key(name) {
+            inherited.append(name.clone(), value.clone());
+        }
+    }
+    debug_headers(">", &inherited);
+    if let Some(bytes) = req.body().and_then(|b| b.as_bytes()) {
+        let message = match std::str::from_utf8(bytes) {
+            Ok(text) if bytes.len() <= DEBUG_BODY_MAX => format!("http > {text}"),
+            _ => format!("http > <{} bytes>", bytes.len()),
+        };
+        output::log(
+            Level::Debug,
+            &message,
+            &[
+                ("direction", "request".into()),
+                ("bytes", bytes.len().into()),
+            ],
+        );
+    }
+}
+
+/// Header names (lowercase) whose values are credentials and must never be shown or stored.
+pub fn sensitive_header(name: &str) -> bool {
+    ["auth", "cookie", "key", "password", "secret", "token"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+fn debug_headers(dir: &str, headers: &header::HeaderMap) {
+    for (name, value) in headers {
+        let n = name.as_str();
+        let shown = if sensitive_header(n) {
+            // Keep the auth scheme (e.g. "Bearer") so it's clear what was sent.
+            let v = value.to_str().unwrap_or("");
+            match v.split_once(' ') {
+                Some((scheme, _)) => format!("{scheme} <redacted>"),
+                None => "<redacted>".to_string(),
+            }
+        } else {
+            value.to_str().unwrap_or("<non-ascii>").to_string()
+        };
+        output::log(
+            Level::Debug,
+            &format!("http {dir} {n}: {shown}"),
+            &[("header", n.into()), ("value", shown.into())],
+        );
+    }
+}
+
+/// Server clock minus local clock, in seconds, as of the latest API response.
+pub fn clock_skew() -> Option<i64> {
+    Some(CLOCK_SKEW.load(Ordering::Relaxed)).filter(|&s| s != i64::MIN)
+}
+
+/// "local clock is 75s behind the server" style wording for a `clock_skew` value.
+pub fn describe_clock_skew(skew: i64) -> String {
+    match skew {
+        0 => "local clock matches the server".to_string(),
+        s if s > 0 => format!("local clock is {s}s behind the server"),
+        s => format!("local clock is {}s ahead of the server", -s),
+    }
+}
+
+/// Record the offset to the server's `Date` header and warn once per run when it is
+/// large. The header has one-second resolution and includes network latency, so only
+/// offsets well past a second mean anything.
+fn note_clock_skew(headers: &header::HeaderMap) {
+    let Some(server) = headers
+        .get(header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date)
+    else {
+        return;
+    };
+    let local = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let skew = server - local;
+    CLOCK_SKEW.store(skew, Ordering::Relaxed);
+    if skew.abs() > CLOCK_SKEW_WARN_SECS && !CLOCK_SKEW_WARNED.swap(true, Ordering::Relaxed) {
+        log_warn!(
+            "{}; token expiry and other time-based checks may misbehave (sync the system clock)",
+            describe_clock_skew(skew)
+        );
+    }
+}
+
+/// Unix seconds for an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT` (the only
+/// `Date` format current servers send).
+fn parse_http_date(s: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let mut parts = s.split_whitespace().skip(1);
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|&m| m == month_name)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut hms = parts.next()?.split(':').map(|p| p.parse::<i64>().ok());
+    let (h, m, sec) = (hms.next()??, hms.next()??, hms.next()??);
+    if parts.next()? != "GMT" {
+        return None;
+    }
+
+    // Days since 1970-01-01 for a proleptic Gregorian date (years start in March so
+    // the leap day is last).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Some(days * 86_400 + h * 3_600 + m * 60 + sec)
+}
+
+/// Response chunks buffered between the network and the parser in `parse_json_streamed`.
+const STREAM_CHANNEL_CHUNKS: usize = 8;
+
+/// Read buffer in front of `ChunkReader`.
+const STREAM_READ_BUFFER: usize = 64 * 1024;
+
+/// Blocking `Read` over response chunks fed from the async side.
+struct ChunkReader {
+    rx: tokio::sync::mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl std::io::Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Redirect handling for API requests. reqwest itself drops `Authorization` and cookies
+/// when a redirect changes host or port, but not `--header` values (gateway secrets) or
+/// anything on an `https` -> `http` hop that keeps the port, so those redirects fail
+/// instead of being followed.
+fn redirect_policy(max: usize, custom_headers: bool) -> reqwest::redirect::Policy {
+    if max == 0 {
+        return reqwest::redirect::Policy::none();
+    }
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > max {
+            return attempt.error(format!(
+                "stopped after {max} redirects (raise --max-redirects)"
+            ));
+        }
+        let refusal = attempt
+            .previous()
+            .last()
+            .and_then(|from| redirect_refusal(from, attempt.url(), custom_headers));
+        match refusal {
+            Some(msg) => attempt.error(msg),
+            None => attempt.follow(),
+        }
+    })
+}
+
+/// Why `redirect_policy` won't follow a hop from `from` to `to`, if it won't.
+fn redirect_refusal(from: &Url, to: &Url, custom_headers: bool) -> Option<String> {
+    if from.scheme() == "https" && to.scheme() != "https" {
+        Some(format!(
+            "refusing to follow a redirect from https to {to}; credentials would be sent unencrypted"
+        ))
+    } else if custom_headers && to.origin() != from.origin() {
+        Some(format!(
+            "refusing to follow a redirect to {to}, another origin, because --header values would be sent there; point --api-url at the final URL"
+        ))
+    } else {
+        None
+    }
+}
+
+/// Statuses worth retrying for an idempotent POST: the first attempt still in flight
+/// (409) or a gateway/server hiccup.
+fn retryable_status(status: reqwest::StatusCode) -> bool {
+    matches!(status.as_u16(), 409 | 502 | 503 | 504)
+}
+
+/// `Retry-After` given in seconds; the HTTP-date form is rare for 429 and ignored.
+fn retry_after_secs(headers: &header::HeaderMap) -> Option<std::time::Duration> {
+    let secs: u64 = headers
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(std::time::Duration::from_secs(secs))
 }
 
-async fn parse_json_response<T: DeserializeOwned>(res: reqwest::Response) -> Result<T> {
-    let status = res.status();
-    let text = res.text().await.unwrap_or_default();
-    if !status.is_success() {
-        return Err(anyhow!("HTTP {status}: {text}"));
+/// Bodies larger than this are summarized by length in `--debug-http` request dumps.
+const DEBUG_BODY_MAX: usize = 64 * 1024;
+
+fn parse_body<T: DeserializeOwned>(text: &str) -> Result<T> {
+    serde_json::from_str(text).with_context(|| {
+        format!(
+            "parse json response ({} bytes; body starts: {})",
+            text.len(),
+            body_excerpt(text)
+        )
+    })
+}
+
+/// Leading slice of a response body for error messages, cut on a char boundary.
+fn body_excerpt(text: &str) -> &str {
+    const MAX: usize = 512;
+    if text.len() <= MAX {
+        return text;
+    }
+    let mut end = MAX;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::{Value, json};
+    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer) -> ApiClient {
+        ApiClient::new(&server.uri(), "secret", &ClientOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn url_joins_under_a_base_path_with_or_without_trailing_slash() {
+        let opts = ClientOptions::default();
+        for base in ["https://host/v2", "https://host/v2/"] {
+            let client = ApiClient::new(base, "t", &opts).unwrap();
+            assert_eq!(
+                client.url("/api/memories").unwrap().as_str(),
+                "https://host/v2/api/memories"
+            );
+        }
+        let client = ApiClient::new("https://host", "t", &opts).unwrap();
+        assert_eq!(
+            client.url("api/memories").unwrap().as_str(),
+            "https://host/api/memories"
+        );
+    }
+
+    #[tokio::test]
+    async fn get_json_sends_bearer_token_and_query() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/memories"))
+            .and(header("authorization", "Bearer secret"))
+            .and(query_param("limit", "5"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let got: Value = client(&server)
+            .get_json("/api/memories", &[("limit", "5".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(got, json!({ "ok": true }));
+    }
+
+    #[tokio::test]
+    async fn post_json_sends_json_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/memories"))
+            .and(header("content-type", "application/json"))
+            .and(body_json(json!({ "title": "t" })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": "m1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let got: Value = client(&server)
+            .post_json("/api/memories", &json!({ "title": "t" }))
+            .await
+            .unwrap();
+        assert_eq!(got["id"], "m1");
+    }
+
+    #[tokio::test]
+    async fn put_body_sends_bytes_with_length_and_type() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/api/assets/a1/parts/1"))
+            .and(header("content-type", "application/octet-stream"))
+            .and(header("content-length", "5"))
+            .and(wiremock::matchers::body_bytes(b"hello".to_vec()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "etag": "e1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let got: Value = client(&server)
+            .put_body(
+                "/api/assets/a1/parts/1",
+                "application/octet-stream",
+                reqwest::Body::from(b"hello".to_vec()),
+                5,
+            )
+            .await
+            .unwrap();
+        assert_eq!(got["etag"], "e1");
+    }
+
+    #[tokio::test]
+    async fn raw_get_returns_the_response_untouched() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/assets/a1/object"))
+            .and(header("authorization", "Bearer secret"))
+            .and(header("accept-encoding", "identity"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("missing"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let res = client(&server)
+            .raw_get("/api/assets/a1/object", &[])
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(res.text().await.unwrap(), "missing");
+    }
+
+    #[tokio::test]
+    async fn non_2xx_surfaces_status_body_and_request_id() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/projects"))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("x-request-id", "req-1")
+                    .set_body_json(json!({ "error": "forbidden" })),
+            )
+            .mount(&server)
+            .await;
+
+        let err = client(&server)
+            .get_json::<Value>("/api/projects", &[])
+            .await
+            .unwrap_err();
+        let http = err.downcast_ref::<HttpError>().unwrap();
+        assert_eq!(http.status, reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(http.request_id.as_deref(), Some("req-1"));
+        assert!(err.to_string().contains("forbidden"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn patch_json_sends_patch_with_json_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/memories/m1"))
+            .and(header("content-type", "application/json"))
+            .and(body_json(json!({ "title": "renamed" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "m1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let got: Value = client(&server)
+            .patch_json("/api/memories/m1", &json!({ "title": "renamed" }))
+            .await
+            .unwrap();
+        assert_eq!(got["id"], "m1");
+    }
+
+    #[tokio::test]
+    async fn empty_token_sends_no_authorization() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .mount(&server)
+            .await;
+
+        let client = ApiClient::new(&server.uri(), "", &ClientOptions::default()).unwrap();
+        client.get_json::<Value>("/health", &[]).await.unwrap();
+        let requests = server.received_requests().await.unwrap();
+        assert!(!requests[0].headers.contains_key("authorization"));
+    }
+
+    #[test]
+    fn credential_headers_are_sensitive() {
+        for name in [
+            "authorization",
+            "proxy-authorization",
+            "cookie",
+            "set-cookie",
+            "x-api-key",
+            "x-db-password",
+            "x-client-secret",
+            "x-auth-token",
+            "cf-access-client-id-auth",
+        ] {
+            assert!(sensitive_header(name), "{name}");
+        }
+        for name in ["accept", "content-type", "x-api-version", "user-agent"] {
+            assert!(!sensitive_header(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn http_dates_parse_to_unix_seconds() {
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        // Leap day, and the day after it.
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 12:00:00 GMT"),
+            Some(1_709_208_000)
+        );
+        assert_eq!(
+            parse_http_date("Fri, 01 Mar 2024 00:00:00 GMT"),
+            Some(1_709_251_200)
+        );
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+
+    #[test]
+    fn clock_skew_is_described_from_the_local_side() {
+        assert_eq!(describe_clock_skew(0), "local clock matches the server");
+        assert_eq!(
+            describe_clock_skew(90),
+            "local clock is 90s behind the server"
+        );
+        assert_eq!(
+            describe_clock_skew(-5),
+            "local clock is 5s ahead of the server"
+        );
+    }
+
+    #[test]
+    fn redirect_refusal_blocks_https_downgrade() {
+        let from = Url::parse("https://api.example.com/a").unwrap();
+        let to = Url::parse("http://api.example.com/b").unwrap();
+        assert!(redirect_refusal(&from, &to, false).is_some());
+        let to = Url::parse("https://api.example.com/b").unwrap();
+        assert_eq!(redirect_refusal(&from, &to, false), None);
+    }
+
+    #[test]
+    fn redirect_refusal_keeps_custom_headers_on_one_origin() {
+        let from = Url::parse("https://api.example.com/a").unwrap();
+        let other = Url::parse("https://cdn.example.com/a").unwrap();
+        assert_eq!(redirect_refusal(&from, &other, false), None);
+        assert!(redirect_refusal(&from, &other, true).is_some());
+        let same = Url::parse("https://api.example.com/b").unwrap();
+        assert_eq!(redirect_refusal(&from, &same, true), None);
+    }
+
+    #[tokio::test]
+    async fn cross_origin_redirect_drops_authorization() {
+        let api = MockServer::start().await;
+        let other = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/assets/a1/object"))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("location", format!("{}/blob/a1", other.uri()).as_str()),
+            )
+            .mount(&api)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/blob/a1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("bytes"))
+            .expect(1)
+            .mount(&other)
+            .await;
+
+        let opts = ClientOptions {
+            max_redirects: 5,
+            ..ClientOptions::default()
+        };
+        let client = ApiClient::new(&api.uri(), "secret", &opts).unwrap();
+        let res = client.raw_get("/api/assets/a1/object", &[]).await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "bytes");
+
+        let first = &api.received_requests().await.unwrap()[0];
+        assert_eq!(first.headers["authorization"], "Bearer secret");
+        let followed = &other.received_requests().await.unwrap()[0];
+        assert!(!followed.headers.contains_key("authorization"));
+    }
+
+    #[tokio::test]
+    async fn cross_origin_redirect_with_custom_headers_fails() {
+        let api = MockServer::start().await;
+        let other = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("location", format!("{}/elsewhere", other.uri()).as_str()),
+            )
+            .mount(&api)
+            .await;
+
+        let opts = ClientOptions {
+            max_redirects: 5,
+            extra_headers: vec![("X-Gateway-Secret".into(), "s".into())],
+            ..ClientOptions::default()
+        };
+        let client = ApiClient::new(&api.uri(), "secret", &opts).unwrap();
+        let err = client.raw_get("/api/projects", &[]).await.unwrap_err();
+        assert!(format!("{err:#}").contains("another origin"), "{err:#}");
+        assert!(other.received_requests().await.unwrap().is_empty());
     }
-    serde_json::from_str(&text).context("parse json response")
 }
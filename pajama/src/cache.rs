@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{config_dir, config_disabled, create_private_file, restrict_permissions};
+use crate::output::log_warn;
+
+/// A list response kept for conditional re-requests (`If-None-Match` / `If-Modified-Since`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("cache"))
+}
+
+/// Cached entry for `key`; any read or parse problem is treated as a miss.
+pub fn load(key: &str) -> Option<CachedResponse> {
+    load_from(&cache_dir().ok()?, key)
+}
+
+fn load_from(dir: &Path, key: &str) -> Option<CachedResponse> {
+    let text = fs::read_to_string(dir.join(format!("{key}.json"))).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Best-effort write; responses can hold private memory content, so files are 0600.
+pub fn store(key: &str, entry: &CachedResponse) {
+    if config_disabled() {
+        return;
+    }
+    if let Err(err) = cache_dir().and_then(|dir| store_in(&dir, key, entry)) {
+        log_warn!("could not update response cache: {err:#}");
+    }
+}
+
+fn store_in(dir: &Path, key: &str, entry: &CachedResponse) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    restrict_permissions(dir, 0o700)?;
+    let path = dir.join(format!("{key}.json"));
+    let tmp = dir.join(format!("{key}.json.tmp"));
+    let mut f = create_private_file(&tmp)?;
+    f.write_all(serde_json::to_string(entry)?.as_bytes())
+        .with_context(|| format!("write {}", tmp.display()))?;
+    drop(f);
+    fs::rename(&tmp, &path).with_context(|| format!("replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Remove every cached response; returns how many entries were deleted.
+pub fn clear() -> Result<usize> {
+    let dir = cache_dir()?;
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in fs::read_dir(&dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::remove_file(&path).with_context(|| format!("remove {}", path.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn cached_responses_are_private() {
+        use crate::config::{test_dir, test_mode};
+        let dir = test_dir("cache-perms").join("cache");
+        let entry = CachedResponse {
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+            body: "{\"memories\":[]}".into(),
+        };
+        store_in(&dir, "k1", &entry).unwrap();
+        assert_eq!(test_mode(&dir), 0o700);
+        assert_eq!(test_mode(&dir.join("k1.json")), 0o600);
+        let loaded = load_from(&dir, "k1").unwrap();
+        assert_eq!(loaded.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(loaded.body, entry.body);
+        assert!(load_from(&dir, "missing").is_none());
+    }
+}
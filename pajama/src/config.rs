@@ -1,22 +1,70 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use url::Url;
+
+use crate::output::log_warn;
+
+/// Current config schema; see `migrate` for what each step changes.
+pub const CONFIG_VERSION: u32 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Schema version the file was written with; files from before versioning read as 0.
+    #[serde(default)]
+    pub version: u32,
     pub api_base_url: String,
     pub client_id: Option<String>,
     pub access_token: Option<String>,
+    /// Project used when a command needs one and `--project-id` is omitted.
+    #[serde(default)]
+    pub default_project_id: Option<String>,
+    /// Fail login when the issued token lacks the API key prefix (same as `--strict-token`).
+    #[serde(default)]
+    pub strict_token: bool,
+    /// Expected API key prefix; `PAJAMA_TOKEN_PREFIX` overrides it. Empty disables the check.
+    #[serde(default)]
+    pub token_prefix: Option<String>,
+    /// Space-separated scopes granted with `access_token` at the last `pajama login`.
+    #[serde(default)]
+    pub granted_scopes: Option<String>,
+    /// OAuth metadata document to use instead of the API host's `/.well-known` paths, for
+    /// deployments whose auth server lives elsewhere; `--oauth-metadata-url` overrides it.
+    #[serde(default)]
+    pub oauth_metadata_url: Option<String>,
+}
+
+pub const DEFAULT_TOKEN_PREFIX: &str = "gdm_";
+
+impl Config {
+    /// Prefix issued tokens are expected to start with: `PAJAMA_TOKEN_PREFIX`, then the
+    /// config file, then `gdm_`.
+    pub fn token_prefix(&self) -> String {
+        std::env::var("PAJAMA_TOKEN_PREFIX")
+            .ok()
+            .or_else(|| self.token_prefix.clone())
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|| DEFAULT_TOKEN_PREFIX.to_string())
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             api_base_url: default_api_base_url(),
             client_id: None,
             access_token: None,
+            default_project_id: None,
+            strict_token: false,
+            token_prefix: None,
+            granted_scopes: None,
+            oauth_metadata_url: None,
         }
     }
 }
@@ -31,35 +79,552 @@ pub fn default_api_base_url() -> String {
         .unwrap_or_else(|| "https://api-game-dev-memory.pajamadot.com".to_string())
 }
 
+/// Canonical form of a user-supplied API base URL.
+///
+/// Adds `https://` when no scheme is given, rejects anything but http(s), and drops
+/// trailing slashes. `source` names the flag/setting the value came from for errors.
+pub fn normalize_api_url(raw: &str, source: &str) -> Result<String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Err(anyhow!("{source} is empty"));
+    }
+    let with_scheme = if trimmed.contains("://") {
+        trimmed.to_string()
+    } else {
+        format!("https://{trimmed}")
+    };
+    let url = Url::parse(&with_scheme)
+        .map_err(|err| anyhow!("{source} is not a valid URL ({err}): {trimmed}"))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow!(
+            "{source} must use http or https, got '{}': {trimmed}",
+            url.scheme()
+        ));
+    }
+    if url.host_str().is_none_or(|h| h.is_empty()) {
+        return Err(anyhow!("{source} has no host: {trimmed}"));
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// Config file chosen on the command line. `own_dir` is false for `--config`, whose
+/// directory belongs to the user (a repo, their home) and is never chmod'ed.
+struct ConfigOverride {
+    path: PathBuf,
+    own_dir: bool,
+}
+
+static CONFIG_PATH_OVERRIDE: OnceLock<ConfigOverride> = OnceLock::new();
+
+/// Pick the config location from `--config` / `PAJAMA_CONFIG` (the file) or, failing
+/// that, `--config-dir` / `PAJAMA_CONFIG_DIR` (the directory holding `config.json`). The
+/// cache, templates, history and version check live next to the config file, so they move
+/// with it. Empty values (e.g. `PAJAMA_CONFIG=`) fall through to the platform default.
+///
+/// Must be called before the first `config_path()` lookup; later calls are ignored.
+pub fn set_config_location(file: Option<PathBuf>, dir: Option<PathBuf>) {
+    if let Some(o) = location_override(file, dir) {
+        let _ = CONFIG_PATH_OVERRIDE.set(o);
+    }
+}
+
+fn location_override(file: Option<PathBuf>, dir: Option<PathBuf>) -> Option<ConfigOverride> {
+    if let Some(path) = file.filter(|p| !p.as_os_str().is_empty()) {
+        return Some(ConfigOverride {
+            path,
+            own_dir: false,
+        });
+    }
+    dir.filter(|p| !p.as_os_str().is_empty())
+        .map(|dir| ConfigOverride {
+            path: dir.join("config.json"),
+            own_dir: true,
+        })
+}
+
+static CONFIG_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// `--no-config`: `load_config` ignores the file, `save_config` refuses to write it, and
+/// `config_dir` fails for everything else kept next to it.
+pub fn disable_config() {
+    CONFIG_DISABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn config_disabled() -> bool {
+    CONFIG_DISABLED.load(Ordering::Relaxed)
+}
+
 pub fn config_path() -> Result<PathBuf> {
+    if let Some(o) = CONFIG_PATH_OVERRIDE.get() {
+        return Ok(o.path.clone());
+    }
     let proj = ProjectDirs::from("com", "PajamaDot", "pajama")
         .context("could not determine config directory")?;
     Ok(proj.config_dir().join("config.json"))
 }
 
 pub fn load_config() -> Result<Config> {
+    if config_disabled() {
+        return Ok(Config::default());
+    }
     let path = config_path()?;
     if !path.exists() {
         return Ok(Config::default());
     }
 
-    let text =
-        fs::read_to_string(&path).with_context(|| format!("read config {}", path.display()))?;
-    let mut cfg: Config = serde_json::from_str(&text).context("parse config json")?;
+    warn_if_permissive(&path);
+    let mut cfg = match read_config_file(&path) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            // A torn write can leave the primary unparseable; fall back to the last good copy.
+            let bak = backup_path(&path);
+            match read_config_file(&bak) {
+                Ok(cfg) => {
+                    log_warn!("{err:#}; using backup {}", bak.display());
+                    cfg
+                }
+                Err(_) => return Err(err),
+            }
+        }
+    };
     if cfg.api_base_url.trim().is_empty() {
         cfg.api_base_url = default_api_base_url();
     }
     Ok(cfg)
 }
 
-pub fn save_config(cfg: &Config) -> Result<()> {
+/// Read a config file, upgrading older layouts in memory (`config migrate` persists that).
+pub fn read_config_file(path: &Path) -> Result<Config> {
+    let raw = read_raw_config(path)?;
+    let version = raw_version(&raw);
+    if version > CONFIG_VERSION {
+        log_warn!(
+            "{} was written by a newer pajama (config version {version}); settings this version doesn't know are ignored and dropped when it saves",
+            path.display()
+        );
+    }
+    serde_json::from_value(migrate(raw))
+        .with_context(|| format!("parse config json {}", path.display()))
+}
+
+fn read_raw_config(path: &Path) -> Result<serde_json::Value> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("read config {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("parse config json {}", path.display()))
+}
+
+fn raw_version(raw: &serde_json::Value) -> u32 {
+    raw.get("version")
+        .and_then(|v| v.as_u64())
+        .map_or(0, |v| u32::try_from(v).unwrap_or(u32::MAX))
+}
+
+/// Upgrade a config object one schema step at a time up to `CONFIG_VERSION`. Newer
+/// versions pass through unchanged.
+///
+/// - 0 -> 1: adds `version`. Version 0 is every file written before the field existed;
+///   the fields added since then (`default_project_id`, `strict_token`, `token_prefix`,
+///   `granted_scopes`, `oauth_metadata_url`) already default when missing. A missing or
+///   null `api_base_url` becomes empty, which `load_config` replaces with the default URL.
+fn migrate(mut raw: serde_json::Value) -> serde_json::Value {
+    let mut version = raw_version(&raw);
+    let Some(obj) = raw.as_object_mut() else {
+        // Not an object: leave it for serde to report.
+        return raw;
+    };
+    while version < CONFIG_VERSION {
+        match version {
+            0 => {
+                if obj.get("api_base_url").is_none_or(|v| v.is_null()) {
+                    obj.insert("api_base_url".to_string(), "".into());
+                }
+            }
+            _ => unreachable!("every version below CONFIG_VERSION has a step"),
+        }
+        version += 1;
+        obj.insert("version".to_string(), version.into());
+    }
+    raw
+}
+
+/// Outcome of `migrate_config_file`.
+pub enum Migration {
+    /// No config file yet; the next save writes the current version.
+    Missing,
+    /// Already at (or past) `CONFIG_VERSION`.
+    Current(u32),
+    /// Upgraded from `from`; the original file was copied to `backup`.
+    Upgraded { from: u32, backup: PathBuf },
+}
+
+/// `config migrate`: rewrite the config file in the current schema, keeping the old file
+/// as `config.json.v<old>.bak`.
+pub fn migrate_config_file() -> Result<Migration> {
     let path = config_path()?;
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("create config dir {}", parent.display()))?;
+    if !path.exists() {
+        return Ok(Migration::Missing);
+    }
+    let from = raw_version(&read_raw_config(&path)?);
+    if from >= CONFIG_VERSION {
+        return Ok(Migration::Current(from));
+    }
+    let cfg = read_config_file(&path)?;
+    let backup = path.with_extension(format!("json.v{from}.bak"));
+    fs::copy(&path, &backup).with_context(|| format!("write backup {}", backup.display()))?;
+    restrict_permissions(&backup, 0o600)?;
+    save_config(&cfg)?;
+    Ok(Migration::Upgraded { from, backup })
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+/// Directory holding the config file; a bare `--config config.json` means the current one.
+///
+/// Under `--no-config` nothing else in it is used either (response cache, templates,
+/// history, version check), so this fails.
+pub fn config_dir() -> Result<PathBuf> {
+    if config_disabled() {
+        return Err(anyhow!(
+            "--no-config is set, so nothing in the config directory is read or written"
+        ));
+    }
+    dir_of(&config_path()?)
+}
+
+fn dir_of(path: &Path) -> Result<PathBuf> {
+    let dir = path
+        .parent()
+        .context("config path has no parent directory")?;
+    Ok(if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir.to_path_buf()
+    })
+}
+
+/// Create the config directory if needed and return it. Only a directory the CLI picked
+/// (the platform default or `--config-dir`) is restricted to the current user; the files
+/// written into it are private either way.
+pub fn ensure_config_dir() -> Result<PathBuf> {
+    let dir = config_dir()?;
+    create_config_dir(&dir, CONFIG_PATH_OVERRIDE.get().is_none_or(|o| o.own_dir))?;
+    Ok(dir)
+}
+
+fn create_config_dir(dir: &Path, own_dir: bool) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("create config dir {}", dir.display()))?;
+    if own_dir {
+        restrict_permissions(dir, 0o700)?;
+    }
+    Ok(())
+}
+
+pub fn save_config(cfg: &Config) -> Result<()> {
+    if config_disabled() {
+        return Err(anyhow!(
+            "--no-config is set, so the config file is not written"
+        ));
     }
+    let path = config_path()?;
+    ensure_config_dir()?;
+    write_config(&path, cfg)
+}
+
+/// `save_config` into `path`, whose directory already exists.
+fn write_config(path: &Path, cfg: &Config) -> Result<()> {
+    let parent = dir_of(path)?;
     let text = serde_json::to_string_pretty(cfg).context("serialize config json")?;
-    fs::write(&path, format!("{text}\n"))
-        .with_context(|| format!("write config {}", path.display()))?;
+
+    // Keep the previous config as a fallback for `load_config`, but only if it still parses.
+    if read_config_file(path).is_ok() {
+        let bak = backup_path(path);
+        fs::copy(path, &bak).with_context(|| format!("write backup {}", bak.display()))?;
+        restrict_permissions(&bak, 0o600)?;
+    }
+
+    // Write a sibling temp file and rename it over the config so readers never see a
+    // partially written file.
+    let tmp = path.with_extension("json.tmp");
+    {
+        let mut f = create_private_file(&tmp)?;
+        f.write_all(format!("{text}\n").as_bytes())
+            .with_context(|| format!("write config {}", tmp.display()))?;
+        f.sync_all()
+            .with_context(|| format!("fsync config {}", tmp.display()))?;
+    }
+    // The config holds a bearer token; keep it private to the current user.
+    restrict_permissions(&tmp, 0o600)?;
+    fs::rename(&tmp, path).with_context(|| format!("replace config {}", path.display()))?;
+    sync_dir(&parent);
     Ok(())
 }
+
+/// Create or truncate `path` for writing, readable only by the current user. `mode` only
+/// applies to new files, so an existing one is restricted through the open handle too.
+#[cfg(unix)]
+pub fn create_private_file(path: &Path) -> Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let f = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("create {}", path.display()))?;
+    f.set_permissions(fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("set permissions on {}", path.display()))?;
+    Ok(f)
+}
+
+#[cfg(not(unix))]
+pub fn create_private_file(path: &Path) -> Result<fs::File> {
+    fs::File::create(path).with_context(|| format!("create {}", path.display()))
+}
+
+/// Best-effort: persist the rename itself. Directories can't be opened this way on Windows.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(d) = fs::File::open(dir) {
+        let _ = d.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+#[cfg(unix)]
+pub fn restrict_permissions(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("set permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+pub fn restrict_permissions(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+fn warn_if_permissive(path: &Path) {
+    if let Some(mode) = permissive_mode(path) {
+        log_warn!(
+            "{} is accessible by other users (mode {:o}); it contains your access token. Run `chmod 600` on it or re-run `pajama login`.",
+            path.display(),
+            mode
+        );
+    }
+}
+
+/// The file's permission bits when group/other have any access (Unix only).
+#[cfg(unix)]
+pub fn permissive_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o077 != 0).then_some(mode)
+}
+
+#[cfg(not(unix))]
+pub fn permissive_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Empty scratch directory for tests, unique to this process and `name`.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pajama-test-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Permission bits of `path`.
+#[cfg(all(test, unix))]
+pub fn test_mode(path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn saved_config_is_private() {
+        let dir = test_dir("config-perms").join("pajama");
+        create_config_dir(&dir, true).unwrap();
+        assert_eq!(test_mode(&dir), 0o700);
+
+        let path = dir.join("config.json");
+        let cfg = Config {
+            access_token: Some("gdm_secret".into()),
+            ..Config::default()
+        };
+        write_config(&path, &cfg).unwrap();
+        assert_eq!(test_mode(&path), 0o600);
+        assert_eq!(permissive_mode(&path), None);
+        // The second save keeps the first as a backup, just as private.
+        write_config(&path, &cfg).unwrap();
+        assert_eq!(test_mode(&backup_path(&path)), 0o600);
+        assert_eq!(
+            read_config_file(&path).unwrap().access_token.as_deref(),
+            Some("gdm_secret")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn user_directories_keep_their_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = test_dir("config-user-dir");
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        create_config_dir(&dir, false).unwrap();
+        assert_eq!(test_mode(&dir), 0o755);
+        write_config(&dir.join("config.json"), &Config::default()).unwrap();
+        assert_eq!(test_mode(&dir.join("config.json")), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissive_mode_flags_group_and_other_access() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = test_dir("config-permissive");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(permissive_mode(&path), Some(0o644));
+        restrict_permissions(&path, 0o600).unwrap();
+        assert_eq!(permissive_mode(&path), None);
+    }
+
+    #[test]
+    fn api_urls_are_normalized() {
+        let norm = |raw| normalize_api_url(raw, "--api-url").map_err(|e| e.to_string());
+        assert_eq!(
+            norm("https://host.example/"),
+            Ok("https://host.example".into())
+        );
+        assert_eq!(
+            norm("https://host.example//"),
+            Ok("https://host.example".into())
+        );
+        assert_eq!(norm("host.example"), Ok("https://host.example".into()));
+        assert_eq!(
+            norm("  http://host.example:8787 \n"),
+            Ok("http://host.example:8787".into())
+        );
+        // A path prefix (e.g. a gateway mount at `/api`) is kept; only the slash goes.
+        assert_eq!(
+            norm("https://host.example/api/"),
+            Ok("https://host.example/api".into())
+        );
+        assert_eq!(
+            norm("host.example/v2"),
+            Ok("https://host.example/v2".into())
+        );
+    }
+
+    #[test]
+    fn bad_api_urls_name_their_source() {
+        let err = |raw| {
+            normalize_api_url(raw, "PAJAMA_API_URL")
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(err("   "), "PAJAMA_API_URL is empty");
+        assert!(
+            err("ftp://host.example")
+                .starts_with("PAJAMA_API_URL must use http or https, got 'ftp'")
+        );
+        assert!(err("https://").starts_with("PAJAMA_API_URL"));
+        assert!(err("https://exa mple").starts_with("PAJAMA_API_URL is not a valid URL"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_files_are_made_private() {
+        let dir = test_dir("private-existing");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("version-check.json");
+        fs::write(&path, b"old").unwrap();
+        restrict_permissions(&path, 0o644).unwrap();
+        create_private_file(&path).unwrap();
+        assert_eq!(test_mode(&path), 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"");
+    }
+
+    #[test]
+    fn migrate_adds_version_and_api_url() {
+        let migrated = migrate(serde_json::json!({ "access_token": "gdm_x" }));
+        assert_eq!(raw_version(&migrated), CONFIG_VERSION);
+        assert_eq!(migrated["api_base_url"], "");
+        assert_eq!(migrated["access_token"], "gdm_x");
+
+        let migrated = migrate(serde_json::json!({ "api_base_url": null }));
+        assert_eq!(migrated["api_base_url"], "");
+    }
+
+    #[test]
+    fn migrate_keeps_current_and_newer_files() {
+        let current = serde_json::json!({
+            "version": CONFIG_VERSION,
+            "api_base_url": "https://example.com",
+        });
+        assert_eq!(migrate(current.clone()), current);
+        let newer = serde_json::json!({ "version": CONFIG_VERSION + 1, "future": true });
+        assert_eq!(migrate(newer.clone()), newer);
+        assert_eq!(
+            migrate(serde_json::json!([1, 2])),
+            serde_json::json!([1, 2])
+        );
+    }
+
+    #[test]
+    fn version_0_files_load() {
+        let dir = test_dir("migrate-v0");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(
+            &path,
+            r#"{"default_project_id":"p1","access_token":"gdm_x"}"#,
+        )
+        .unwrap();
+        let cfg = read_config_file(&path).unwrap();
+        assert_eq!(cfg.default_project_id.as_deref(), Some("p1"));
+        assert_eq!(cfg.access_token.as_deref(), Some("gdm_x"));
+        assert_eq!(cfg.api_base_url, "");
+    }
+
+    #[test]
+    fn config_file_wins_over_config_dir() {
+        let dir = test_dir("location");
+        let o = location_override(Some(dir.join("mine.json")), Some(dir.clone())).unwrap();
+        assert_eq!(o.path, dir.join("mine.json"));
+        assert!(!o.own_dir);
+        // An empty `PAJAMA_CONFIG=` falls through to the directory, then the default.
+        let o = location_override(Some(PathBuf::new()), Some(dir.clone())).unwrap();
+        assert_eq!(o.path, dir.join("config.json"));
+        assert!(o.own_dir);
+        assert!(location_override(None, Some(PathBuf::new())).is_none());
+        assert!(location_override(None, None).is_none());
+    }
+
+    #[test]
+    fn config_dir_override_holds_the_config() {
+        let dir = test_dir("config-dir").join("nested");
+        let o = location_override(None, Some(dir.clone())).unwrap();
+        create_config_dir(&dir, o.own_dir).unwrap();
+        #[cfg(unix)]
+        assert_eq!(test_mode(&dir), 0o700);
+        let cfg = Config {
+            default_project_id: Some("p1".into()),
+            ..Config::default()
+        };
+        write_config(&o.path, &cfg).unwrap();
+        assert_eq!(dir_of(&o.path).unwrap(), dir);
+        let loaded = read_config_file(&dir.join("config.json")).unwrap();
+        assert_eq!(loaded.default_project_id.as_deref(), Some("p1"));
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::api::sensitive_header;
+use crate::config::{config_dir, config_disabled, create_private_file, ensure_config_dir};
+use crate::output::log_warn;
+
+/// Entries kept in the history file; older ones are dropped on write.
+const HISTORY_MAX: usize = 100;
+
+/// Stands in for secret argument values in the history file.
+const REDACTED: &str = "<redacted>";
+
+/// Flags whose value is always a secret.
+const SECRET_FLAGS: &[&str] = &["--token", "--pfx-password"];
+
+/// One successfully executed command (arguments after the program name, secrets redacted).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix seconds.
+    pub ts: u64,
+    pub argv: Vec<String>,
+}
+
+pub fn history_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("history.jsonl"))
+}
+
+/// Recorded commands, oldest first; unreadable lines are skipped.
+pub fn load() -> Result<Vec<Entry>> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Best-effort append of a finished command (after redacting secrets); a no-op under
+/// `--no-config`.
+pub fn record(argv: &[String]) {
+    if argv.is_empty() || config_disabled() {
+        return;
+    }
+    let write = || -> Result<()> {
+        let mut entries = load()?;
+        entries.push(Entry {
+            ts: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            argv: redact(argv),
+        });
+        let skip = entries.len().saturating_sub(HISTORY_MAX);
+        ensure_config_dir()?;
+        write_entries(&history_path()?, &entries[skip..])
+    };
+    if let Err(err) = write() {
+        log_warn!("could not update command history: {err:#}");
+    }
+}
+
+/// Replace the history file with `entries`, readable only by the current user.
+fn write_entries(path: &Path, entries: &[Entry]) -> Result<()> {
+    let tmp = path.with_extension("jsonl.tmp");
+    let mut f = create_private_file(&tmp)?;
+    for entry in entries {
+        writeln!(f, "{}", serde_json::to_string(entry)?)
+            .with_context(|| format!("write {}", tmp.display()))?;
+    }
+    drop(f);
+    fs::rename(&tmp, path).with_context(|| format!("replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Replace secret values: `SECRET_FLAGS` arguments and the values of
+/// sensitive `--header` entries (the header name is kept).
+fn redact(argv: &[String]) -> Vec<String> {
+    let mut out = Vec::with_capacity(argv.len());
+    let mut args = argv.iter();
+    while let Some(arg) = args.next() {
+        if SECRET_FLAGS.contains(&arg.as_str()) {
+            out.push(arg.clone());
+            if args.next().is_some() {
+                out.push(REDACTED.to_string());
+            }
+            continue;
+        }
+        if let Some((flag, _)) = arg
+            .split_once('=')
+            .filter(|(flag, _)| SECRET_FLAGS.contains(flag))
+        {
+            out.push(format!("{flag}={REDACTED}"));
+            continue;
+        }
+        if arg == "--header" {
+            out.push(arg.clone());
+            if let Some(value) = args.next() {
+                out.push(redact_header(value));
+            }
+            continue;
+        }
+        if let Some(value) = arg.strip_prefix("--header=") {
+            out.push(format!("--header={}", redact_header(value)));
+            continue;
+        }
+        out.push(arg.clone());
+    }
+    out
+}
+
+fn redact_header(value: &str) -> String {
+    match value.split_once(':') {
+        Some((name, _)) if sensitive_header(&name.trim().to_ascii_lowercase()) => {
+            format!("{name}: {REDACTED}")
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Flags never replayed: a repeat must not skip a confirmation prompt or wait on stdin
+/// for a token.
+const UNREPLAYABLE_FLAGS: &[&str] = &["--yes", "-y", "--token-stdin"];
+
+/// Arguments to replay: redacted secrets are dropped along with their flag, so the
+/// token (or header) comes from the config file or environment instead, and so are
+/// `UNREPLAYABLE_FLAGS`.
+pub fn replayable(argv: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(argv.len());
+    for arg in argv {
+        if UNREPLAYABLE_FLAGS.contains(&arg.as_str()) {
+            continue;
+        }
+        if arg.contains(REDACTED) {
+            if !arg.starts_with("--") {
+                out.pop();
+            }
+            continue;
+        }
+        out.push(arg.clone());
+    }
+    out
+}
+
+/// The command as it could be typed again (single-quoting arguments that need it).
+pub fn display(argv: &[String]) -> String {
+    let quoted: Vec<String> = argv
+        .iter()
+        .map(|a| {
+            let plain = !a.is_empty()
+                && a.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_=.,/:@+%".contains(c));
+            if plain {
+                a.clone()
+            } else {
+                format!("'{}'", a.replace('\'', r"'\''"))
+            }
+        })
+        .collect();
+    format!("pajama {}", quoted.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn history_file_is_private() {
+        use crate::config::{test_dir, test_mode};
+        let dir = test_dir("history-perms");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.jsonl");
+        let entries = [Entry {
+            ts: 1,
+            argv: vec!["projects".into(), "list".into()],
+        }];
+        write_entries(&path, &entries).unwrap();
+        assert_eq!(test_mode(&path), 0o600);
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 1);
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let argv = args(&[
+            "--token",
+            "t1",
+            "--pfx-password=p1",
+            "--header",
+            "X-Api-Key: k1",
+            "--header=Proxy-Authorization: Basic b1",
+            "--header",
+            "X-Trace: keep",
+            "projects",
+        ]);
+        assert_eq!(
+            redact(&argv),
+            args(&[
+                "--token",
+                REDACTED,
+                "--pfx-password=<redacted>",
+                "--header",
+                "X-Api-Key: <redacted>",
+                "--header=Proxy-Authorization: <redacted>",
+                "--header",
+                "X-Trace: keep",
+                "projects",
+            ])
+        );
+    }
+
+    #[test]
+    fn replay_drops_secrets_confirmations_and_stdin_tokens() {
+        let argv = args(&[
+            "--token",
+            REDACTED,
+            "--header=Cookie: <redacted>",
+            "--token-stdin",
+            "memories",
+            "delete",
+            "m1",
+            "--yes",
+            "-y",
+        ]);
+        assert_eq!(replayable(&argv), args(&["memories", "delete", "m1"]));
+    }
+}
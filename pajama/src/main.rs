@@ -1,32 +1,206 @@
 mod api;
+mod cache;
 mod config;
+mod history;
 mod oauth;
+mod output;
+mod progress;
+mod resolve;
+mod templates;
+mod throttle;
+mod timing;
+mod version_check;
 
 use anyhow::{Context, Result, anyhow};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tokio::io::AsyncReadExt;
-
-use crate::api::ApiClient;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::api::{ApiClient, ClientIdentity, ClientOptions};
 use crate::config::{load_config, save_config};
-use crate::oauth::{discover_oauth, login_oauth_pkce};
+use crate::oauth::{PrefixCheck, TokenCheck, discover_oauth, login_oauth_pkce, missing_scopes};
+use crate::output::{log_info, log_warn};
+use crate::resolve::IpPreference;
+use crate::throttle::RateLimiter;
 
 #[derive(Parser)]
 #[command(
     name = "pajama",
     version,
-    about = "PajamaDot CLI for Game Dev Memory (API + OAuth login)"
+    about = "PajamaDot CLI for Game Dev Memory (API + OAuth login)",
+    after_help = "Path arguments expand ~, ~user, $VAR and ${VAR}; unset variables are left as written."
 )]
 struct Cli {
     /// Memory API base URL (defaults to config or PAJAMA_API_URL)
     #[arg(long, global = true)]
     api_url: Option<String>,
 
-    /// Bearer token override (API key). If omitted, uses the saved token from `pajama login`.
+    /// OAuth metadata document URL, for auth servers not hosted with the API (defaults to
+    /// config, then the API's /.well-known paths)
+    #[arg(long, global = true, env = "PAJAMA_OAUTH_METADATA_URL")]
+    oauth_metadata_url: Option<String>,
+
+    /// Bearer token override (API key). Token sources, first match wins: --token or
+    /// --token-stdin, --token-file, PAJAMA_TOKEN_FILE, PAJAMA_TOKEN, the saved `pajama login` token.
     #[arg(long, global = true)]
     token: Option<String>,
 
+    /// Read the bearer token from stdin (trimmed), keeping it out of argv, the environment
+    /// and shell history
+    #[arg(long, global = true, conflicts_with = "token")]
+    token_stdin: bool,
+
+    /// Read the bearer token from a file (trimmed); used when --token/--token-stdin are not given
+    #[arg(long, global = true, env = "PAJAMA_TOKEN_FILE", value_parser = parse_path)]
+    token_file: Option<PathBuf>,
+
+    /// Connect over IPv4 first when a host has both families (e.g. IPv6 is broken locally);
+    /// also binds the OAuth callback to 127.0.0.1
+    #[arg(long, global = true, conflicts_with = "prefer_ipv6")]
+    prefer_ipv4: bool,
+
+    /// Connect over IPv6 first when a host has both families; also binds the OAuth
+    /// callback to [::1]
+    #[arg(long, global = true)]
+    prefer_ipv6: bool,
+
+    /// Extra User-Agent text appended after `pajama-cli/<version>` (or PAJAMA_USER_AGENT)
+    #[arg(long, global = true, env = "PAJAMA_USER_AGENT")]
+    user_agent: Option<String>,
+
+    /// Redirects to follow per API request; redirects that would leak credentials (to
+    /// plain http, or to another origin when --header is set) are always refused
+    #[arg(long, global = true, default_value_t = 10)]
+    max_redirects: usize,
+
+    /// Don't follow redirects; a 3xx response is reported as an error
+    #[arg(long, global = true, conflicts_with = "max_redirects")]
+    no_follow_redirects: bool,
+
+    /// Extra request header as "Key: Value" (repeatable). Applies to every API call,
+    /// including asset part uploads and downloads.
+    #[arg(long = "header", global = true, value_parser = parse_header_arg)]
+    headers: Vec<(String, String)>,
+
+    /// Allow --header to replace the Authorization header
+    #[arg(long, global = true)]
+    allow_override_auth: bool,
+
+    /// PEM client certificate for mutual TLS (with --client-key)
+    #[arg(long, global = true, requires = "client_key", value_parser = parse_path)]
+    client_cert: Option<PathBuf>,
+
+    /// PEM private key for --client-cert
+    #[arg(long, global = true, requires = "client_cert", value_parser = parse_path)]
+    client_key: Option<PathBuf>,
+
+    /// PKCS#12 (.pfx/.p12) client certificate for mutual TLS
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "client_cert",
+        value_parser = parse_path
+    )]
+    client_pfx: Option<PathBuf>,
+
+    /// Password for --client-pfx (or PAJAMA_PFX_PASSWORD)
+    #[arg(
+        long,
+        global = true,
+        requires = "client_pfx",
+        env = "PAJAMA_PFX_PASSWORD",
+        hide_env_values = true
+    )]
+    pfx_password: Option<String>,
+
+    /// Dump raw HTTP requests and responses (headers + bodies) to stderr; credentials are redacted
+    #[arg(long, global = true)]
+    debug_http: bool,
+
+    /// Log DNS, connect+TLS, time to first byte and total time of every request to stderr
+    /// (one JSON object per request with --log-format json)
+    #[arg(long, global = true)]
+    trace_timing: bool,
+
+    /// Send at most this many API requests per second (fractions allowed), shared by all
+    /// of a command's concurrent requests; a 429 lowers the rate until responses succeed again
+    #[arg(long, global = true, env = "PAJAMA_RPS", value_parser = throttle::parse_rps)]
+    rps: Option<f64>,
+
+    /// Reuse a record this command already read in the last 30 seconds instead of fetching
+    /// it again (writes made by the command clear those copies)
+    #[arg(long, global = true)]
+    session_cache: bool,
+
+    /// Report upload/download progress as JSON lines for GUI wrappers, written to `stderr`
+    /// or an open file descriptor (e.g. 3); replaces the human progress lines
+    #[arg(long, global = true, value_name = "stderr|FD", value_parser = progress::parse_target)]
+    progress_json: Option<progress::Target>,
+
+    /// Suppress informational notices on stderr (row counts, empty-result messages)
+    #[arg(long, global = true)]
+    quiet: bool,
+
+    /// Stderr log style: human `[pajama]` lines or one JSON object per event
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "PAJAMA_LOG_FORMAT",
+        default_value_t = output::LogFormat::Text
+    )]
+    log_format: output::LogFormat,
+
+    /// On failure, print one JSON object to stderr ({"error":{"kind","message",
+    /// "request_id","http_status"}}) instead of the plain message; the exit code is unchanged
+    #[arg(long, global = true)]
+    json_errors: bool,
+
+    /// Structured output format for commands that support --json (implies --json)
+    #[arg(long, global = true, value_enum)]
+    format: Option<output::Format>,
+
+    /// Indent JSON output even when stdout isn't a terminal
+    #[arg(long, global = true, conflicts_with = "compact")]
+    pretty: bool,
+
+    /// Print JSON output on one line even when stdout is a terminal
+    #[arg(long, global = true)]
+    compact: bool,
+
+    /// Accept-Language sent with API requests, e.g. de or "fr-CA, fr;q=0.8" (default: the
+    /// locale from LC_ALL / LC_MESSAGES / LANG, else en; empty sends none)
+    #[arg(long, global = true)]
+    accept_language: Option<String>,
+
+    /// API version sent as X-API-Version; pin it to protect scripts from breaking changes
+    #[arg(long, global = true, env = "PAJAMA_API_VERSION", default_value = api::API_VERSION)]
+    api_version: String,
+
+    /// Config file to use instead of the platform default (or PAJAMA_CONFIG)
+    #[arg(long, global = true, env = "PAJAMA_CONFIG", value_parser = parse_path)]
+    config: Option<PathBuf>,
+
+    /// Directory for config.json, the response cache, templates and history instead of the
+    /// platform default (or PAJAMA_CONFIG_DIR); --config still picks the file when both are set
+    #[arg(long, global = true, env = "PAJAMA_CONFIG_DIR", value_parser = parse_path)]
+    config_dir: Option<PathBuf>,
+
+    /// Ignore config.json entirely (for hermetic CI runs): the API URL must come from
+    /// --api-url or PAJAMA_API_URL and the token from a flag or environment variable
+    #[arg(
+        long,
+        global = true,
+        env = "PAJAMA_NO_CONFIG",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    no_config: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -35,13 +209,42 @@ struct Cli {
 enum Commands {
     /// Login via browser (OAuth PKCE). Stores an API key locally.
     Login {
-        /// OAuth scopes requested (space-separated)
-        #[arg(long)]
+        /// OAuth scopes requested (space-separated); overrides --role
+        #[arg(long, conflicts_with = "role")]
         scope: Option<String>,
 
+        /// Scope preset: read-only, editor, or admin (default: admin)
+        #[arg(long, value_enum)]
+        role: Option<Role>,
+
         /// Do not attempt to open a browser automatically (prints URL instead)
         #[arg(long)]
         no_open: bool,
+
+        /// Register a new OAuth client instead of reusing the saved client_id (e.g. after
+        /// it was revoked); the new id is saved with the token
+        #[arg(long)]
+        reauthorize: bool,
+
+        /// Print the token to stdout instead of saving it to config
+        #[arg(long)]
+        print_token: bool,
+
+        /// Abort (and restore the previous config) if the post-login API check fails
+        #[arg(long)]
+        verify_strict: bool,
+
+        /// Fail if the issued token doesn't look like an API key (or set `strict_token` in config)
+        #[arg(long, conflicts_with = "accept_any_token")]
+        strict_token: bool,
+
+        /// Skip the API key prefix check on the issued token
+        #[arg(long)]
+        accept_any_token: bool,
+
+        /// Fail (and save nothing) if the server grants fewer scopes than requested
+        #[arg(long)]
+        strict_scope: bool,
     },
 
     /// Remove the saved access token
@@ -53,6 +256,51 @@ enum Commands {
     /// Print the config path
     ConfigPath,
 
+    /// Check config, connectivity, OAuth discovery, and the stored token
+    Doctor,
+
+    /// Print the CLI version; with --check, ask the API whether a newer release exists
+    Version {
+        /// Compare against the latest published release (cached for a day)
+        #[arg(long)]
+        check: bool,
+
+        /// With --check, ignore the cached result and ask the server now
+        #[arg(long, requires = "check")]
+        refresh: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Inspect or upgrade the config file
+    Config {
+        #[command(subcommand)]
+        cmd: ConfigCmd,
+    },
+
+    /// Manage the local response cache used by list commands
+    Cache {
+        #[command(subcommand)]
+        cmd: CacheCmd,
+    },
+
+    /// Re-run the last successful command (secrets are not replayed)
+    #[command(alias = "!!")]
+    Repeat,
+
+    /// Show recently run commands
+    History {
+        /// How many entries to show (most recent last)
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
     Projects {
         #[command(subcommand)]
         cmd: ProjectsCmd,
@@ -79,10 +327,118 @@ enum Commands {
     },
 }
 
+/// Named scope presets for `pajama login --role`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Role {
+    /// Read projects, memories, artifacts, and assets
+    ReadOnly,
+    /// Read everything; write memories, artifacts, and assets (not projects)
+    Editor,
+    /// Full read/write access
+    Admin,
+}
+
+impl Role {
+    fn scopes(self) -> &'static str {
+        match self {
+            Role::ReadOnly => "projects:read memories:read artifacts:read assets:read",
+            Role::Editor => {
+                "projects:read memories:read memories:write artifacts:read artifacts:write assets:read assets:write"
+            }
+            Role::Admin => {
+                "projects:read projects:write memories:read memories:write artifacts:read artifacts:write assets:read assets:write"
+            }
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /// Rewrite the config file in the current schema version, keeping a backup of the old one
+    Migrate,
+}
+
+#[derive(Subcommand)]
+enum CacheCmd {
+    /// Delete all cached list responses
+    Clear,
+}
+
 #[derive(Subcommand)]
 enum ProjectsCmd {
     /// List projects in the current tenant scope
     List {
+        /// Comma-separated columns for table output (e.g. id,title,confidence)
+        #[arg(long)]
+        fields: Option<String>,
+
+        /// Line format with {field} placeholders, e.g. "{id} {title} ({confidence})";
+        /// `{{`/`}}` are literal braces, missing fields print as empty
+        #[arg(long, conflicts_with = "fields")]
+        output_template: Option<String>,
+
+        /// Exit with status 3 when nothing matches (for CI checks)
+        #[arg(long)]
+        fail_if_empty: bool,
+
+        /// Append per-engine project counts (a `facets` object with --json)
+        #[arg(long, conflicts_with_all = ["fields", "output_template", "only_ids"])]
+        facets: bool,
+
+        /// Also count memories per project (one extra request per project)
+        #[arg(long, requires = "facets")]
+        with_memory_counts: bool,
+
+        /// Parallel requests for --with-memory-counts
+        #[arg(long, default_value_t = 4, requires = "with_memory_counts")]
+        concurrency: usize,
+
+        /// Print only the id of each row, one per line (for xargs); overrides --format
+        #[arg(long, conflicts_with_all = ["fields", "output_template", "json"])]
+        only_ids: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the canonical engine names accepted by `projects create --engine`
+    Engines,
+
+    /// Set (or show) the default project used when `--project-id` is omitted
+    Use {
+        /// Project id to use by default. Omit to print the current default.
+        id: Option<String>,
+
+        /// Clear the saved default project
+        #[arg(long, conflicts_with = "id")]
+        clear: bool,
+    },
+
+    /// Summarize memory counts per category for a project (every state, the same totals
+    /// as `projects list --with-memory-counts`)
+    Stats {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Get a project by id (includes memory stats)
+    Get {
+        id: String,
+
+        /// Line format with {field} placeholders instead of the default layout
+        #[arg(long)]
+        output_template: Option<String>,
+
+        /// Print only the id (for scripts); overrides --format
+        #[arg(long, conflicts_with_all = ["output_template", "json"])]
+        only_id: bool,
+
         /// Output raw JSON
         #[arg(long)]
         json: bool,
@@ -93,11 +449,20 @@ enum ProjectsCmd {
         #[arg(long)]
         name: String,
 
+        /// unreal, unity, godot, or custom; aliases like ue5/unity3d are normalized (see --force)
         #[arg(long, default_value = "custom")]
         engine: String,
 
         #[arg(long, default_value = "")]
         description: String,
+
+        /// Seed the new project with the engine's starter category notes
+        #[arg(long)]
+        seed: bool,
+
+        /// Accept an --engine value outside the known list
+        #[arg(long)]
+        force: bool,
     },
 }
 
@@ -117,9 +482,107 @@ enum MemoriesCmd {
         #[arg(long)]
         tag: Option<String>,
 
+        /// Max rows (0 = server maximum, 200)
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+
+        /// Skip this many rows (offset paging; pair with --limit)
+        #[arg(long, default_value_t = 0)]
+        offset: u32,
+
+        /// Always refetch instead of revalidating a cached response (ETag / Last-Modified)
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Extra query parameter as key=value (repeatable); replaces a typed filter with the same key
+        #[arg(long = "raw-query", value_parser = parse_query_arg)]
+        raw_query: Vec<(String, String)>,
+
+        /// Comma-separated columns for table output (e.g. id,title,confidence)
+        #[arg(long)]
+        fields: Option<String>,
+
+        /// Line format with {field} placeholders, e.g. "{id} {title} ({confidence})";
+        /// `{{`/`}}` are literal braces, missing fields print as empty
+        #[arg(long, conflicts_with = "fields")]
+        output_template: Option<String>,
+
+        /// Exit with status 3 when nothing matches (for CI checks)
+        #[arg(long)]
+        fail_if_empty: bool,
+
+        /// Print only the id of each row, one per line (for xargs); overrides --format
+        #[arg(long, conflicts_with_all = ["fields", "output_template", "json"])]
+        only_ids: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print how many memories match the filters
+    Count {
+        #[arg(long)]
+        project_id: Option<String>,
+
+        #[arg(long)]
+        category: Option<String>,
+
+        #[arg(long, alias = "query")]
+        q: Option<String>,
+
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Output {"count": N} JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Poll a project and print memories as they are created or updated, like `tail -f`
+    Watch {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        #[arg(long)]
+        category: Option<String>,
+
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Seconds between polls
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+
+        /// Newest memories checked per poll (0 = server maximum, 200); changes to older
+        /// memories are not noticed
         #[arg(long, default_value_t = 50)]
         limit: u32,
 
+        /// Comma-separated columns for table output (e.g. id,title,confidence)
+        #[arg(long)]
+        fields: Option<String>,
+
+        /// Line format with {field} placeholders, e.g. "{id} {title} ({confidence})";
+        /// `{{`/`}}` are literal braces, missing fields print as empty
+        #[arg(long, conflicts_with = "fields")]
+        output_template: Option<String>,
+
+        /// Print one JSON object per change: {"event": "created"|"updated", "memory": {...}}
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List every tag used in a project with how many memories carry it
+    Tags {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        #[arg(long)]
+        category: Option<String>,
+
         /// Output raw JSON
         #[arg(long)]
         json: bool,
@@ -127,34 +590,172 @@ enum MemoriesCmd {
 
     /// Get a memory by id
     Get {
-        id: String,
+        /// One or more memory ids; several are fetched concurrently (see docs for the output)
+        #[arg(required = true, value_name = "ID")]
+        ids: Vec<String>,
+
+        /// Print directly instead of through $PAJAMA_PAGER / $PAGER
+        #[arg(long)]
+        no_pager: bool,
+
+        /// Line format with {field} placeholders instead of the default layout
+        #[arg(long)]
+        output_template: Option<String>,
+
+        /// Print only the id (for scripts); overrides --format
+        #[arg(long, conflicts_with_all = ["output_template", "json"])]
+        only_id: bool,
 
         /// Output raw JSON
         #[arg(long)]
         json: bool,
     },
 
+    /// Compare two memories: metadata field by field, then a unified diff of the content
+    Diff {
+        a: String,
+
+        b: String,
+
+        /// Don't colorize (also off when stdout isn't a terminal or NO_COLOR is set)
+        #[arg(long)]
+        no_color: bool,
+
+        /// Output a structured diff as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
     /// Create a memory
     Create {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        /// Required unless the --template sets one
         #[arg(long)]
-        project_id: String,
+        category: Option<String>,
+
+        /// Single line; tabs/newlines become spaces and other control or zero-width
+        /// characters are removed (see --strict)
+        #[arg(long, required_unless_present = "stdin_json")]
+        title: Option<String>,
 
+        /// Required unless --template is given (overrides the rendered template body)
         #[arg(long)]
-        category: String,
+        content: Option<String>,
 
+        /// Template name from `<config dir>/templates/<name>.md`; fills defaults for
+        /// category/tags/confidence and renders {{title}}/{{project}} into the content
         #[arg(long)]
-        title: String,
+        template: Option<String>,
 
+        /// Comma-separated tags (normalized like --title)
         #[arg(long)]
-        content: String,
+        tags: Option<String>,
 
-        /// Comma-separated tags
-        #[arg(long, default_value = "")]
-        tags: String,
+        /// Confidence 0..1 (default 0.5)
+        #[arg(long)]
+        confidence: Option<f64>,
+
+        /// Reject control/zero-width characters in --title/--tags instead of scrubbing them
+        /// (and unknown fields with --stdin-json)
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+
+        /// Read the whole memory as a JSON object from stdin instead of the field flags;
+        /// fields the flags don't cover are sent as is
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "category", "title", "content", "template", "tags", "confidence",
+                "source_type", "session_id", "context", "context_file", "context_kv",
+            ]
+        )]
+        stdin_json: bool,
+
+        /// Who produced the memory (agent-generated vs human notes group differently)
+        #[arg(long, default_value = "manual", value_parser = SOURCE_TYPES)]
+        source_type: String,
+
+        /// Session the memory belongs to
+        #[arg(long)]
+        session_id: Option<String>,
+
+        /// Structured context as a JSON object, e.g. '{"platform":"Win64"}'
+        #[arg(long, conflicts_with = "context_file")]
+        context: Option<String>,
+
+        /// Read the context JSON object from a file
+        #[arg(long, value_parser = parse_path)]
+        context_file: Option<PathBuf>,
+
+        /// Context entry as key=value (repeatable; string values, merged over --context)
+        #[arg(long = "context-kv", value_parser = parse_query_arg)]
+        context_kv: Vec<(String, String)>,
+
+        /// Key the server uses to dedupe retries of this create (default: a fresh UUID);
+        /// pass the same value when re-running a create that may have gone through
+        #[arg(long)]
+        idempotency_key: Option<String>,
+
+        /// Print the memory as stored (server defaults and normalized tags) instead of the id
+        #[arg(long)]
+        full: bool,
+
+        /// Upload a file and link it to the new memory as an attachment (repeatable)
+        #[arg(long, value_parser = parse_path)]
+        attach: Vec<PathBuf>,
+
+        /// Output raw JSON (the create response, or the stored memory with --full)
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Update fields of a memory. Fails if it changed since it was read (by this command,
+    /// or at --if-version) unless --force is given.
+    Update {
+        id: String,
+
+        /// Single line; cleaned like `memories create --title`
+        #[arg(long)]
+        title: Option<String>,
+
+        #[arg(long)]
+        content: Option<String>,
+
+        #[arg(long)]
+        category: Option<String>,
+
+        /// Comma-separated tags; replaces the current tags
+        #[arg(long)]
+        tags: Option<String>,
 
         /// Confidence 0..1
-        #[arg(long, default_value_t = 0.5)]
-        confidence: f64,
+        #[arg(long)]
+        confidence: Option<f64>,
+
+        /// Reject control/zero-width characters in --title/--tags instead of scrubbing them
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+
+        /// Only update if the memory is still at this version (shown by `memories get`)
+        #[arg(long, conflicts_with = "force")]
+        if_version: Option<i64>,
+
+        /// Overwrite even if someone else changed the memory in the meantime
+        #[arg(long)]
+        force: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Manage local memory templates used by `memories create --template`.
+    Templates {
+        #[command(subcommand)]
+        cmd: TemplatesCmd,
     },
 
     /// Progressive-disclosure index search (compact hits).
@@ -186,6 +787,7 @@ enum MemoriesCmd {
         #[arg(long, default_value = "balanced")]
         memory_mode: String,
 
+        /// Max rows (0 = server maximum, 100)
         #[arg(long, default_value_t = 20)]
         limit: u32,
 
@@ -232,6 +834,7 @@ enum MemoriesCmd {
         #[arg(long)]
         after: Option<String>,
 
+        /// Max rows (0 = server maximum, 500)
         #[arg(long, default_value_t = 100)]
         limit: u32,
 
@@ -260,6 +863,7 @@ enum MemoriesCmd {
         #[arg(long, default_value_t = 60)]
         within_days: u32,
 
+        /// Max rows (0 = server maximum, 300)
         #[arg(long, default_value_t = 50)]
         limit: u32,
 
@@ -292,38 +896,161 @@ enum MemoriesCmd {
         #[arg(long)]
         json: bool,
     },
-}
-
-#[derive(Subcommand)]
-enum AssetsCmd {
-    /// Upload a large file as an asset (R2 multipart via the API)
-    Upload {
-        #[arg(long)]
-        project_id: String,
-
-        #[arg(long)]
-        path: PathBuf,
 
-        /// Optional memory id to link as an attachment
-        #[arg(long)]
-        memory_id: Option<String>,
+    /// Move a memory to another project.
+    Move {
+        id: String,
 
-        /// MIME type override (default: application/octet-stream)
         #[arg(long)]
-        content_type: Option<String>,
+        to_project: String,
 
-        /// Part size in MB (5..95). Defaults based on file size.
-        #[arg(long)]
-        part_size_mb: Option<u32>,
+        /// Skip the confirmation prompt
+        #[arg(long, short = 'y')]
+        yes: bool,
 
-        /// Output raw JSON for create/complete responses
+        /// Output raw JSON
         #[arg(long)]
         json: bool,
     },
 
-    /// Get asset metadata
-    Get {
-        id: String,
+    /// Find duplicate memories in a project and optionally delete the extras.
+    Dedupe {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        /// What makes two memories duplicates
+        #[arg(long, value_enum, default_value_t = DedupeKey::Content)]
+        by: DedupeKey,
+
+        /// Delete all but one memory per cluster (highest confidence, then most recent)
+        #[arg(long, default_value_t = false)]
+        delete: bool,
+
+        /// With --delete, print what would be removed without deleting
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Skip the confirmation prompt for --delete
+        #[arg(long, short = 'y')]
+        yes: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Rename, add, or remove a tag across a project's memories.
+    Retag {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        /// Tag to rename (requires --to)
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+
+        /// New name for the --from tag
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+
+        /// Tag to remove from matching memories
+        #[arg(long)]
+        remove: Option<String>,
+
+        /// Tag to add to matching memories
+        #[arg(long)]
+        add: Option<String>,
+
+        /// Only touch memories carrying this tag (defaults to --from or --remove)
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Print planned changes without sending updates
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplatesCmd {
+    /// List template names
+    List,
+
+    /// Print a template's raw text
+    Show { name: String },
+
+    /// Print the templates directory
+    Path,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DedupeKey {
+    /// Normalized content body
+    Content,
+    /// Normalized title
+    Title,
+    /// Title and content together
+    Both,
+}
+
+#[derive(Subcommand)]
+enum AssetsCmd {
+    /// Upload a large file as an asset (R2 multipart via the API)
+    Upload(UploadArgs),
+
+    /// Poll an asset until it is ready (exits nonzero on failure or timeout)
+    Wait {
+        id: String,
+
+        /// Seconds to keep polling
+        #[arg(long, default_value_t = 300)]
+        wait_timeout: u64,
+
+        /// Print each status change
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Finalize an upload whose parts are all stored, e.g. after `assets upload` gave up
+    /// on the complete step
+    Complete {
+        id: String,
+
+        /// Hex SHA-256 for the server to verify the assembled object against
+        #[arg(long, value_parser = parse_sha256)]
+        sha256: Option<String>,
+
+        /// Output the raw JSON response
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Get asset metadata
+    Get {
+        /// One or more asset ids; several are fetched concurrently (see docs for the output)
+        #[arg(required = true, value_name = "ID")]
+        ids: Vec<String>,
+
+        /// Print a temporary signed link to the file instead of the metadata
+        #[arg(long, conflicts_with = "output_template")]
+        download_url: bool,
+
+        /// Link lifetime for --download-url, e.g. 90s, 15m, 1h, 7d (server allows 1m to 7d)
+        #[arg(long, requires = "download_url", value_parser = parse_expiry)]
+        expires: Option<u64>,
+
+        /// Line format with {field} placeholders instead of the default layout
+        #[arg(long)]
+        output_template: Option<String>,
+
+        /// Print only the id (for scripts); overrides --format
+        #[arg(long, conflicts_with_all = ["output_template", "json", "download_url"])]
+        only_id: bool,
 
         /// Output raw JSON
         #[arg(long)]
@@ -341,9 +1068,132 @@ enum AssetsCmd {
         #[arg(long)]
         status: Option<String>,
 
+        /// Keep only these statuses, comma-separated (e.g. ready,failed); several values
+        /// are applied client-side to the fetched page
+        #[arg(long, value_delimiter = ',', conflicts_with = "status")]
+        status_filter: Vec<String>,
+
+        /// Max rows (0 = server maximum, 200)
         #[arg(long, default_value_t = 50)]
         limit: u32,
 
+        /// Skip this many rows (offset paging; pair with --limit)
+        #[arg(long, default_value_t = 0)]
+        offset: u32,
+
+        /// Always refetch instead of revalidating a cached response (ETag / Last-Modified)
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Extra query parameter as key=value (repeatable); replaces a typed filter with the same key
+        #[arg(long = "raw-query", value_parser = parse_query_arg)]
+        raw_query: Vec<(String, String)>,
+
+        /// Keep only assets whose metadata has key=value (repeatable, all must match;
+        /// applied client-side to the fetched page)
+        #[arg(long = "meta-filter", value_parser = parse_query_arg)]
+        meta_filter: Vec<(String, String)>,
+
+        /// Keep only assets not linked to any memory (applied client-side to the fetched page)
+        #[arg(long, conflicts_with = "memory_id")]
+        orphans: bool,
+
+        /// Comma-separated columns for table output (e.g. id,title,confidence)
+        #[arg(long)]
+        fields: Option<String>,
+
+        /// Line format with {field} placeholders, e.g. "{id} {title} ({confidence})";
+        /// `{{`/`}}` are literal braces, missing fields print as empty
+        #[arg(long, conflicts_with = "fields")]
+        output_template: Option<String>,
+
+        /// Exit with status 3 when nothing matches (for CI checks)
+        #[arg(long)]
+        fail_if_empty: bool,
+
+        /// Print only the id of each row, one per line (for xargs); overrides --format
+        #[arg(long, conflicts_with_all = ["fields", "output_template", "json"])]
+        only_ids: bool,
+
+        /// Re-fetch and redraw the list every --interval seconds until interrupted
+        #[arg(long, conflicts_with = "fail_if_empty")]
+        watch: bool,
+
+        /// Seconds between refreshes with --watch
+        #[arg(long, requires = "watch", default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+
+        /// Don't colorize statuses (also off when stdout isn't a terminal or NO_COLOR is set)
+        #[arg(long)]
+        no_color: bool,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print how many assets match the filters
+    Count {
+        #[arg(long)]
+        project_id: Option<String>,
+
+        #[arg(long)]
+        memory_id: Option<String>,
+
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Output {"count": N} JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Summarize asset counts and sizes by status and content type
+    Stats {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check that an asset's object exists and is downloadable (exits nonzero otherwise)
+    Exists { id: String },
+
+    /// Rename an asset and/or set metadata fields
+    Rename {
+        id: String,
+
+        /// New original_name
+        #[arg(long, required_unless_present = "set_meta")]
+        name: Option<String>,
+
+        /// Metadata field as key=value (repeatable; merged into existing metadata)
+        #[arg(long = "set-meta", value_parser = parse_query_arg)]
+        set_meta: Vec<(String, String)>,
+
+        /// Output raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Copy a ready asset into another project (server-side; falls back to download + upload)
+    Copy {
+        id: String,
+
+        /// Project to copy into
+        #[arg(long)]
+        to_project: String,
+
+        /// Memory in the target project to attach the copy to
+        #[arg(long)]
+        link_memory: Option<String>,
+
         /// Output raw JSON
         #[arg(long)]
         json: bool,
@@ -353,17 +1203,141 @@ enum AssetsCmd {
     Download {
         id: String,
 
+        #[arg(
+            long,
+            required_unless_present = "to_stdout",
+            conflicts_with = "to_stdout",
+            value_parser = parse_path
+        )]
+        out: Option<PathBuf>,
+
+        /// Stream the object bytes to stdout (no progress output)
         #[arg(long)]
-        out: PathBuf,
+        to_stdout: bool,
+
+        /// Fetch the file as this many byte ranges in parallel; falls back to one stream
+        /// when the server doesn't support ranged requests
+        #[arg(long, default_value_t = 1, conflicts_with = "to_stdout")]
+        part_concurrency: usize,
+
+        /// Expected SHA-256 of the file (64 hex chars); on a mismatch nothing is written to --out
+        #[arg(long, value_parser = parse_sha256, conflicts_with = "to_stdout")]
+        sha256: Option<String>,
+
+        /// Cap throughput in bytes per second, e.g. 500KB, 2MB or 1.5MiB (best-effort)
+        #[arg(long, value_parser = throttle::parse_rate)]
+        rate_limit: Option<u64>,
+    },
+
+    /// Download every ready asset of a project into a directory
+    DownloadAll {
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
+
+        #[arg(long, value_parser = parse_path)]
+        out_dir: PathBuf,
+
+        /// Parallel downloads
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+
+        /// Cap combined throughput of all parallel downloads in bytes per second,
+        /// e.g. 500KB, 2MB or 1.5MiB (best-effort)
+        #[arg(long, value_parser = throttle::parse_rate)]
+        rate_limit: Option<u64>,
     },
 }
 
+#[derive(clap::Args)]
+struct UploadArgs {
+    /// Defaults to the project set via `pajama projects use`
+    #[arg(long, alias = "project")]
+    project_id: Option<String>,
+
+    #[arg(
+        long,
+        required_unless_present = "stdin",
+        conflicts_with = "stdin",
+        value_parser = parse_path
+    )]
+    path: Option<PathBuf>,
+
+    /// Read the asset bytes from stdin instead of --path (requires --name and --size)
+    #[arg(long, requires_all = ["name", "size"])]
+    stdin: bool,
+
+    /// Asset file name for --stdin uploads
+    #[arg(long, requires = "stdin")]
+    name: Option<String>,
+
+    /// Exact byte count for --stdin uploads (multipart needs the total up front)
+    #[arg(long, requires = "stdin")]
+    size: Option<u64>,
+
+    /// Optional memory id to link as an attachment
+    #[arg(long)]
+    memory_id: Option<String>,
+
+    /// MIME type override (default: application/octet-stream)
+    #[arg(long)]
+    content_type: Option<String>,
+
+    /// Detect the MIME type from the first bytes of the file (then its extension)
+    /// instead of defaulting to application/octet-stream
+    #[arg(long, conflicts_with = "content_type")]
+    sniff: bool,
+
+    /// Metadata field as key=value (repeatable; string values, merged over --meta-file)
+    #[arg(long = "meta", value_parser = parse_query_arg)]
+    meta: Vec<(String, String)>,
+
+    /// Read the metadata JSON object from a file
+    #[arg(long, value_parser = parse_path)]
+    meta_file: Option<PathBuf>,
+
+    /// Part size in MB (5..95). Defaults based on file size.
+    #[arg(long)]
+    part_size_mb: Option<u32>,
+
+    /// Hash the file and skip the upload if a ready asset with the same SHA-256 exists in the project
+    /// (the checksum is recorded on upload, so only assets uploaded this way can match)
+    #[arg(long, conflicts_with = "stdin")]
+    skip_existing: bool,
+
+    /// With --skip-existing, upload even when a matching asset is found
+    #[arg(long, requires = "skip_existing")]
+    force: bool,
+
+    /// SHA-256 the server verifies the assembled object against: `auto` (hashed while the
+    /// parts are sent), a 64-character hex digest, or `none`
+    #[arg(long, default_value = "none", value_parser = parse_checksum)]
+    checksum: Checksum,
+
+    /// Cap throughput in bytes per second, e.g. 500KB, 2MB or 1.5MiB (best-effort)
+    #[arg(long, value_parser = throttle::parse_rate)]
+    rate_limit: Option<u64>,
+
+    /// After completing, poll until the asset is ready (exits nonzero if it fails)
+    #[arg(long)]
+    wait: bool,
+
+    /// Seconds to keep polling with --wait
+    #[arg(long, default_value_t = 300, requires = "wait")]
+    wait_timeout: u64,
+
+    /// Output raw JSON for create/complete responses
+    #[arg(long)]
+    json: bool,
+}
+
 #[derive(Subcommand)]
 enum EvolveCmd {
     /// Show the active retrieval policy for a project.
     Policy {
-        #[arg(long)]
-        project_id: String,
+        /// Defaults to the project set via `pajama projects use`
+        #[arg(long, alias = "project")]
+        project_id: Option<String>,
 
         /// Output raw JSON
         #[arg(long)]
@@ -542,6 +1516,16 @@ struct ProjectsListResponse {
     projects: Vec<ProjectRow>,
 }
 
+/// Column names accepted by `projects list --fields` (mirrors `ProjectRow`).
+const PROJECT_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "engine",
+    "description",
+    "created_at",
+    "updated_at",
+];
+
 #[derive(Debug, Deserialize, Serialize)]
 struct ProjectRow {
     id: String,
@@ -557,10 +1541,43 @@ struct ProjectRow {
 #[derive(Debug, Deserialize, Serialize)]
 struct MemoriesListResponse {
     memories: Vec<MemoryRow>,
-    #[allow(dead_code)]
     meta: Option<serde_json::Value>,
 }
 
+/// `memories create --source-type` values (the ones the API itself writes, plus `manual`).
+const SOURCE_TYPES: [&str; 4] = ["manual", "agent", "agent_pro", "derived"];
+
+/// Tags kept per memory; extra ones are dropped client-side.
+const MAX_TAGS: usize = 32;
+
+/// Fields of `POST /api/memories`; `--stdin-json --strict` rejects any others.
+const MEMORY_CREATE_FIELDS: [&str; 9] = [
+    "project_id",
+    "session_id",
+    "category",
+    "source_type",
+    "title",
+    "content",
+    "tags",
+    "context",
+    "confidence",
+];
+
+/// Column names accepted by `memories list --fields` (mirrors `MemoryRow`).
+const MEMORY_FIELDS: &[&str] = &[
+    "id",
+    "project_id",
+    "category",
+    "title",
+    "content",
+    "tags",
+    "confidence",
+    "updated_at",
+    "source_type",
+    "session_id",
+    "context",
+];
+
 #[derive(Debug, Deserialize, Serialize)]
 struct MemoryRow {
     id: String,
@@ -571,8 +1588,13 @@ struct MemoryRow {
     content: String,
     tags: serde_json::Value,
     confidence: f64,
-    #[allow(dead_code)]
     updated_at: String,
+    #[serde(default)]
+    source_type: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    context: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -584,13 +1606,39 @@ struct MemoryGetResponse {
     content: String,
     tags: serde_json::Value,
     confidence: f64,
+    #[serde(default)]
+    source_type: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    context: Option<serde_json::Value>,
+    /// Optimistic-concurrency version (also the `ETag`); absent on older servers.
+    #[serde(default)]
+    version: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct AssetsListResponse {
     assets: Vec<AssetRow>,
+    #[serde(default)]
+    meta: Option<serde_json::Value>,
 }
 
+/// Column names accepted by `assets list --fields` (mirrors `AssetRow`).
+const ASSET_FIELDS: &[&str] = &[
+    "id",
+    "project_id",
+    "status",
+    "r2_key",
+    "content_type",
+    "byte_size",
+    "original_name",
+    "sha256",
+    "metadata",
+    "linked_memory_count",
+    "created_at",
+];
+
 #[derive(Debug, Deserialize, Serialize)]
 struct AssetRow {
     id: String,
@@ -601,6 +1649,17 @@ struct AssetRow {
     #[serde(deserialize_with = "de_u64_from_str_or_int")]
     byte_size: u64,
     original_name: Option<String>,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    /// Only present when listed with `include_memory_links`.
+    #[serde(
+        default,
+        deserialize_with = "de_opt_u64_from_str_or_int",
+        skip_serializing_if = "Option::is_none"
+    )]
+    linked_memory_count: Option<u64>,
     created_at: Option<String>,
 }
 
@@ -634,6 +1693,27 @@ struct CreateMemoryResponse {
     id: String,
 }
 
+/// Full-replacement body for `PUT /api/memories/{id}`; omitted fields are reset server-side.
+#[derive(Debug, Serialize)]
+struct UpdateMemoryRequest<'a> {
+    category: &'a str,
+    source_type: &'a str,
+    title: &'a str,
+    content: &'a str,
+    tags: Vec<String>,
+    context: serde_json::Value,
+    confidence: f64,
+}
+
+/// Partial body for `PATCH /api/assets/{id}`; metadata keys are merged server-side.
+#[derive(Debug, Serialize)]
+struct UpdateAssetRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_name: Option<String>,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    metadata: serde_json::Map<String, serde_json::Value>,
+}
+
 #[derive(Debug, Serialize)]
 struct CreateAssetRequest<'a> {
     project_id: &'a str,
@@ -643,6 +1723,8 @@ struct CreateAssetRequest<'a> {
     part_size: u64,
     memory_id: Option<&'a str>,
     relation: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<&'a str>,
     metadata: serde_json::Value,
 }
 
@@ -653,133 +1735,1525 @@ struct CreateAssetResponse {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() {
+    let argv: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // There's no `Cli` to read the flag from yet, so look for it in the raw args.
+        Err(err) if err.use_stderr() && argv.iter().any(|a| a == "--json-errors") => {
+            let rendered = err.to_string();
+            let message = rendered.lines().next().unwrap_or_default();
+            let message = message.strip_prefix("error: ").unwrap_or(message);
+            eprintln!(
+                "{}",
+                serde_json::json!({
+                    "error": {
+                        "kind": "usage",
+                        "message": message,
+                        "request_id": null,
+                        "http_status": null,
+                    }
+                })
+            );
+            std::process::exit(err.exit_code());
+        }
+        Err(err) => err.exit(),
+    };
+    let json_errors = cli.json_errors;
+    if let Err(err) = dispatch(cli, &argv).await {
+        report_error(&err, json_errors);
+        std::process::exit(EXIT_FAILURE);
+    }
+}
+
+/// The single place top-level errors reach the user: anyhow's `Error: ...` report with
+/// its causes, or one JSON object for `--json-errors`.
+fn report_error(err: &anyhow::Error, json: bool) {
+    if !json {
+        eprintln!("Error: {err:?}");
+        return;
+    }
+    eprintln!("{}", serde_json::json!({ "error": error_object(err) }));
+}
+
+/// `{"kind","message","request_id","http_status"}` for `--json-errors` and per-id failures.
+fn error_object(err: &anyhow::Error) -> serde_json::Value {
+    let http = err.chain().find_map(|e| e.downcast_ref::<api::HttpError>());
+    serde_json::json!({
+        "kind": error_kind(err),
+        "message": format!("{err:#}"),
+        "request_id": http.and_then(|e| e.request_id.as_deref()),
+        "http_status": http.map(|e| e.status.as_u16()),
+    })
+}
+
+/// Coarse failure class for `--json-errors`, from the first recognizable cause.
+fn error_kind(err: &anyhow::Error) -> &'static str {
+    for cause in err.chain() {
+        if cause.is::<api::HttpError>() {
+            return "http";
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return if e.is_timeout() { "timeout" } else { "network" };
+        }
+        if cause.is::<std::io::Error>() {
+            return "io";
+        }
+        if cause.is::<serde_json::Error>() {
+            return "parse";
+        }
+    }
+    "error"
+}
+
+async fn dispatch(cli: Cli, argv: &[String]) -> Result<()> {
+    if !matches!(cli.command, Commands::Repeat | Commands::History { .. }) {
+        run(cli).await?;
+        history::record(argv);
+        return Ok(());
+    }
+
+    // The history file lives next to the config, so honor --config before reading it.
+    config::set_config_location(cli.config.clone(), cli.config_dir.clone());
+    if cli.no_config {
+        config::disable_config();
+    }
+    output::set_log_format(cli.log_format);
+    if cli.pretty || cli.compact {
+        output::set_pretty_json(cli.pretty);
+    }
+    let entries = history::load()?;
+    match cli.command {
+        Commands::History { limit, json } => {
+            let skip = entries.len().saturating_sub(limit);
+            let shown = &entries[skip..];
+            if output::structured(json) {
+                output::print_structured(&shown)?;
+                return Ok(());
+            }
+            for (i, entry) in shown.iter().enumerate() {
+                println!("{:>4}  {}", skip + i + 1, history::display(&entry.argv));
+            }
+        }
+        Commands::Repeat => {
+            let last = entries
+                .last()
+                .ok_or_else(|| anyhow!("no command history yet"))?;
+            let replay = history::replayable(&last.argv);
+            log_info!("Repeating: {}", history::display(&replay));
+            let cli = Cli::try_parse_from(std::iter::once("pajama".to_string()).chain(replay))
+                .map_err(|err| anyhow!("the last command no longer parses: {err}"))?;
+            run(cli).await?;
+            history::record(&last.argv);
+        }
+        _ => unreachable!("checked above"),
+    }
+    Ok(())
+}
+
+/// Commands that exist to change the config file, named for the `--no-config` error.
+fn config_command(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Login {
+            print_token: false, ..
+        } => Some("login"),
+        Commands::Logout => Some("logout"),
+        Commands::Projects {
+            cmd: ProjectsCmd::Use { .. },
+        } => Some("projects use"),
+        Commands::Config {
+            cmd: ConfigCmd::Migrate,
+        } => Some("config migrate"),
+        _ => None,
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
     let Cli {
         api_url,
+        oauth_metadata_url,
         token,
+        token_stdin,
+        token_file,
+        prefer_ipv4,
+        prefer_ipv6,
+        user_agent,
+        max_redirects,
+        no_follow_redirects,
+        headers,
+        allow_override_auth,
+        client_cert,
+        client_key,
+        client_pfx,
+        pfx_password,
+        debug_http,
+        trace_timing,
+        rps,
+        session_cache,
+        progress_json,
+        accept_language,
+        api_version,
+        quiet,
+        log_format,
+        json_errors: _,
+        format,
+        pretty,
+        compact,
+        config: config_file,
+        config_dir,
+        no_config,
         command,
     } = cli;
 
+    output::set_quiet(quiet);
+    output::set_log_format(log_format);
+    if let Some(target) = progress_json {
+        progress::init(target)?;
+    }
+
+    if let Some(format) = format {
+        output::set_format(format);
+    }
+    if pretty || compact {
+        output::set_pretty_json(pretty);
+    }
+
+    config::set_config_location(config_file, config_dir);
+
+    let stdin_user = match &command {
+        Commands::Assets {
+            cmd: AssetsCmd::Upload(UploadArgs { stdin: true, .. }),
+        } => Some("assets upload --stdin"),
+        Commands::Memories {
+            cmd: MemoriesCmd::Create {
+                stdin_json: true, ..
+            },
+        } => Some("memories create --stdin-json"),
+        _ => None,
+    };
+    if let Some(other) = stdin_user.filter(|_| token_stdin) {
+        return Err(anyhow!(
+            "--token-stdin can't be combined with `{other}`; pass the token with --token-file or PAJAMA_TOKEN instead"
+        ));
+    }
+    let token = TokenOverride {
+        value: if token_stdin {
+            Some(read_token_stdin()?)
+        } else {
+            token
+        },
+        file: token_file,
+    };
+
+    let client_opts = ClientOptions {
+        user_agent_suffix: user_agent,
+        extra_headers: headers,
+        allow_override_auth,
+        debug_http,
+        api_version: Some(api_version).filter(|v| !v.trim().is_empty()),
+        accept_language: Some(accept_language.unwrap_or_else(api::system_language))
+            .filter(|v| !v.trim().is_empty()),
+        client_identity: match (client_cert, client_key, client_pfx) {
+            (Some(cert), Some(key), _) => Some(ClientIdentity::Pem { cert, key }),
+            (_, _, Some(path)) => Some(ClientIdentity::Pkcs12 {
+                path,
+                password: pfx_password.unwrap_or_default(),
+            }),
+            _ => None,
+        },
+        trace_timing,
+        rps,
+        session_cache,
+        max_redirects: if no_follow_redirects {
+            0
+        } else {
+            max_redirects
+        },
+        ip_preference: match (prefer_ipv4, prefer_ipv6) {
+            (true, _) => Some(IpPreference::V4),
+            (_, true) => Some(IpPreference::V6),
+            _ => None,
+        },
+    };
+
+    if no_config {
+        config::disable_config();
+        if let Some(name) = config_command(&command) {
+            return Err(anyhow!(
+                "`pajama {name}` saves to the config file, which --no-config turns off"
+            ));
+        }
+        let env_url = std::env::var("PAJAMA_API_URL").ok();
+        if api_url.is_none() && env_url.is_none_or(|v| v.trim().is_empty()) {
+            return Err(anyhow!("--no-config needs --api-url or PAJAMA_API_URL"));
+        }
+    }
+
     match command {
         Commands::ConfigPath => {
             let path = config::config_path()?;
             println!("{}", path.display());
             return Ok(());
         }
+        Commands::Doctor => {
+            return run_doctor(
+                api_url.as_deref(),
+                oauth_metadata_url.as_deref(),
+                &token,
+                &client_opts,
+            )
+            .await;
+        }
+        Commands::Config {
+            cmd: ConfigCmd::Migrate,
+        } => {
+            match config::migrate_config_file()? {
+                config::Migration::Missing => {
+                    log_info!("No config file yet; nothing to migrate.");
+                }
+                config::Migration::Current(version) if version > config::CONFIG_VERSION => {
+                    log_warn!(
+                        "Config version {version} is newer than this pajama understands ({}); left unchanged.",
+                        config::CONFIG_VERSION
+                    );
+                }
+                config::Migration::Current(version) => {
+                    log_info!("Config is already at version {version}.");
+                }
+                config::Migration::Upgraded { from, backup } => {
+                    log_info!(
+                        "Migrated config from version {from} to {}; the old file is at {}",
+                        config::CONFIG_VERSION,
+                        backup.display()
+                    );
+                }
+            }
+            return Ok(());
+        }
+        Commands::Cache {
+            cmd: CacheCmd::Clear,
+        } => {
+            let removed = cache::clear()?;
+            log_info!("Removed {removed} cached response(s).");
+            return Ok(());
+        }
         _ => {}
     }
 
     let mut cfg = load_config()?;
-    if let Some(api) = api_url.as_deref() {
-        cfg.api_base_url = api.to_string();
-    }
+    cfg.api_base_url = match api_url.as_deref() {
+        Some(api) => config::normalize_api_url(api, "--api-url")?,
+        None => config::normalize_api_url(
+            &cfg.api_base_url,
+            "api_base_url (from config or PAJAMA_API_URL)",
+        )?,
+    };
+    cfg.oauth_metadata_url = match oauth_metadata_url.as_deref() {
+        Some(url) => Some(config::normalize_api_url(url, "--oauth-metadata-url")?),
+        None => cfg
+            .oauth_metadata_url
+            .as_deref()
+            .map(|url| config::normalize_api_url(url, "oauth_metadata_url (from config)"))
+            .transpose()?,
+    };
 
     match command {
-        Commands::Login { scope, no_open } => {
-            let meta = discover_oauth(&cfg.api_base_url).await?;
-            let scope = scope.unwrap_or_else(|| {
-                // Default: full access for a personal/org token in this system.
-                // Enforcement is server-side; this is a request hint.
-                "projects:read projects:write memories:read memories:write artifacts:read artifacts:write assets:read assets:write"
-                    .to_string()
-            });
-
+        Commands::Login {
+            scope,
+            role,
+            no_open,
+            reauthorize,
+            print_token,
+            verify_strict,
+            strict_token,
+            accept_any_token,
+            strict_scope,
+        } => {
+            let token_check = TokenCheck {
+                prefix: cfg.token_prefix(),
+                mode: if accept_any_token {
+                    PrefixCheck::Off
+                } else if strict_token || cfg.strict_token {
+                    PrefixCheck::Strict
+                } else {
+                    PrefixCheck::Warn
+                },
+            };
+            let ip = client_opts.ip_preference;
+            let meta =
+                discover_oauth(&cfg.api_base_url, cfg.oauth_metadata_url.as_deref(), ip).await?;
+            // Default: full access for a personal/org token in this system.
+            // Enforcement is server-side; this is a request hint.
+            let scope = scope.unwrap_or_else(|| role.unwrap_or(Role::Admin).scopes().to_string());
+            log_info!("Requesting scopes: {scope}");
+
+            if reauthorize && cfg.client_id.is_some() {
+                log_info!("Registering a new OAuth client (--reauthorize).");
+            }
             let res = login_oauth_pkce(
                 &meta,
-                &cfg.api_base_url,
-                cfg.client_id.clone(),
+                cfg.client_id.clone().filter(|_| !reauthorize),
                 &scope,
                 no_open,
+                &token_check,
+                ip,
             )
             .await?;
 
+            // A token response without `scope` means the request was granted as is (RFC 6749 5.1).
+            let granted = res.scope.clone().unwrap_or_else(|| scope.clone());
+            let missing = missing_scopes(&scope, &granted);
+            if !missing.is_empty() {
+                let msg = format!(
+                    "the server granted fewer scopes than requested; missing: {}",
+                    missing.join(" ")
+                );
+                if strict_scope {
+                    return Err(anyhow!("{msg}"));
+                }
+                log_warn!("{msg} (commands that need them will fail with HTTP 403)");
+            }
+
+            if print_token {
+                let verified = verify_token(&cfg, &res.access_token, &client_opts).await;
+                report_token_check(&cfg, verified, verify_strict)?;
+                println!("{}", res.access_token);
+                return Ok(());
+            }
+
+            let previous = cfg.clone();
             cfg.client_id = Some(res.client_id);
-            cfg.access_token = Some(res.access_token);
+            cfg.access_token = Some(res.access_token.clone());
+            cfg.granted_scopes = Some(granted);
             save_config(&cfg)?;
-            eprintln!("[pajama] Login saved.");
+            log_info!("Login saved.");
+
+            let verified = verify_token(&cfg, &res.access_token, &client_opts).await;
+            if let Err(err) = report_token_check(&cfg, verified, verify_strict) {
+                save_config(&previous)?;
+                log_info!("Restored the previous config.");
+                return Err(err);
+            }
+            return Ok(());
+        }
+        Commands::Version {
+            check,
+            refresh,
+            json,
+        } => {
+            let current = env!("CARGO_PKG_VERSION");
+            if !check {
+                if output::structured(json) {
+                    output::print_structured(&serde_json::json!({ "current": current }))?;
+                } else {
+                    println!("pajama {current}");
+                }
+                return Ok(());
+            }
+            // The release endpoint is public, so no token is needed.
+            let api = ApiClient::new(&cfg.api_base_url, "", &client_opts)?;
+            let (latest, cached) = version_check::latest(&api, &cfg.api_base_url, refresh).await?;
+            let update_available = version_check::is_newer(&latest.version, current);
+            if output::structured(json) {
+                output::print_structured(&serde_json::json!({
+                    "current": current,
+                    "latest": latest.version,
+                    "update_available": update_available,
+                    "changelog_url": latest.changelog_url,
+                    "cached": cached,
+                }))?;
+                return Ok(());
+            }
+            if update_available {
+                println!("Update available: {current} -> {}", latest.version);
+                if let Some(url) = &latest.changelog_url {
+                    println!("Changelog: {url}");
+                }
+                println!("Install: npm i -g @pajamadot/pajama");
+            } else {
+                println!(
+                    "pajama {current} is up to date (latest: {})",
+                    latest.version
+                );
+            }
+            if cached {
+                log_info!("Using the cached check from the last 24h; pass --refresh to ask again.");
+            }
             return Ok(());
         }
         Commands::Logout => {
             cfg.access_token = None;
+            cfg.granted_scopes = None;
             save_config(&cfg)?;
             println!("ok");
             return Ok(());
         }
         Commands::Token => {
-            let token = resolve_token(token.as_deref(), &cfg)?;
+            let token = resolve_token(&token, &cfg)?;
             println!("{token}");
             return Ok(());
         }
-        Commands::Projects { cmd } => {
-            let api = authed_api(token.as_deref(), &cfg)?;
-            handle_projects(api, cmd).await?;
+        Commands::Projects {
+            cmd: ProjectsCmd::Use { id, clear },
+        } => {
+            if clear {
+                cfg.default_project_id = None;
+                save_config(&cfg)?;
+                println!("ok");
+                return Ok(());
+            }
+            match id.map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) {
+                Some(id) => {
+                    cfg.default_project_id = Some(id.clone());
+                    save_config(&cfg)?;
+                    log_info!("Default project set to {id}");
+                }
+                None => match cfg.default_project_id.as_deref() {
+                    Some(id) => println!("{id}"),
+                    None => return Err(anyhow!("no default project set")),
+                },
+            }
+            return Ok(());
+        }
+        Commands::Projects {
+            cmd: ProjectsCmd::Engines,
+        } => {
+            for p in ENGINE_PRESETS {
+                println!("{}\t{}", p.engine, p.aliases.join(","));
+            }
+            return Ok(());
+        }
+        Commands::Projects { cmd } => {
+            let api = authed_api(&token, &cfg, &client_opts)?;
+            handle_projects(api, &cfg, cmd).await?;
+        }
+        Commands::Memories {
+            cmd: MemoriesCmd::Templates { cmd },
+        } => {
+            handle_templates(cmd)?;
+            return Ok(());
         }
         Commands::Memories { cmd } => {
-            let api = authed_api(token.as_deref(), &cfg)?;
-            handle_memories(api, cmd).await?;
+            let api = authed_api(&token, &cfg, &client_opts)?;
+            handle_memories(api, &cfg, cmd).await?;
         }
         Commands::Assets { cmd } => {
-            let api = authed_api(token.as_deref(), &cfg)?;
-            handle_assets(api, cmd).await?;
+            let api = authed_api(&token, &cfg, &client_opts)?;
+            handle_assets(api, &cfg, cmd).await?;
         }
         Commands::Evolve { cmd } => {
-            let api = authed_api(token.as_deref(), &cfg)?;
-            handle_evolve(api, cmd).await?;
+            let api = authed_api(&token, &cfg, &client_opts)?;
+            handle_evolve(api, &cfg, cmd).await?;
         }
         Commands::Agent { cmd } => {
-            let api = authed_api(token.as_deref(), &cfg)?;
+            let api = authed_api(&token, &cfg, &client_opts)?;
             handle_agent(api, cmd).await?;
         }
-        Commands::ConfigPath => unreachable!("handled above"),
+        Commands::ConfigPath
+        | Commands::Config { .. }
+        | Commands::Doctor
+        | Commands::Cache { .. } => {
+            unreachable!("handled above")
+        }
+        Commands::Repeat | Commands::History { .. } => unreachable!("handled in main"),
     }
 
     Ok(())
 }
 
-fn resolve_token(token_override: Option<&str>, cfg: &config::Config) -> Result<String> {
-    if let Some(t) = token_override {
+/// Path arguments: expand `$VAR` / `${VAR}` and a leading `~` / `~user` for cases the
+/// shell didn't (quoted values, env-provided paths, Windows shells).
+fn parse_path(s: &str) -> Result<PathBuf, String> {
+    expand_tilde(&expand_env_vars(s)?)
+}
+
+fn expand_env_vars(s: &str) -> Result<String, String> {
+    expand_env_vars_with(s, |name| std::env::var(name).ok())
+}
+
+/// `expand_env_vars` with the variable lookup passed in. Unset variables stay literal, so
+/// names that merely contain a `$` still work.
+fn expand_env_vars_with(
+    s: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, tail) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| format!("unclosed ${{ in {s:?}"))?;
+                if end == 0 {
+                    return Err(format!("empty ${{}} in {s:?}"));
+                }
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => out.push_str(&value),
+            // A `$` not followed by a set variable's name stays literal.
+            None => out.push_str(&rest[i..rest.len() - tail.len()]),
+        }
+        rest = tail;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn expand_tilde(s: &str) -> Result<PathBuf, String> {
+    let Some(rest) = s.strip_prefix('~') else {
+        return Ok(PathBuf::from(s));
+    };
+    let (user, tail) = match rest.find(['/', '\\']) {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    let home = if user.is_empty() {
+        directories::BaseDirs::new()
+            .map(|d| d.home_dir().to_path_buf())
+            .ok_or_else(|| format!("could not determine the home directory for {s:?}"))?
+    } else {
+        user_home(user).ok_or_else(|| format!("unknown user '{user}' in {s:?}"))?
+    };
+    Ok(if tail.is_empty() {
+        home
+    } else {
+        home.join(tail)
+    })
+}
+
+/// Home directory of another local user (`~user`), looked up in `/etc/passwd`.
+fn user_home(user: &str) -> Option<PathBuf> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() >= 6 && fields[0] == user).then(|| PathBuf::from(fields[5]))
+    })
+}
+
+fn parse_header_arg(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected \"Key: Value\", got {s:?}"))?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("invalid header name in {s:?}"));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// A JSON object from an inline flag or a file, plus `key=value` string overrides:
+/// memory `context` (`--context` / `--context-file` / `--context-kv`) and asset
+/// `metadata` (`--meta-file` / `--meta`). Defaults to `{}`.
+fn build_json_object(
+    what: &str,
+    inline: Option<String>,
+    file: Option<&std::path::Path>,
+    kv: &[(String, String)],
+) -> Result<serde_json::Value> {
+    let (text, source) = match (inline, file) {
+        (Some(text), _) => (Some(text), format!("--{what}")),
+        (None, Some(path)) => (
+            Some(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("read {}", path.display()))?,
+            ),
+            path.display().to_string(),
+        ),
+        (None, None) => (None, String::new()),
+    };
+    let mut obj = match text {
+        Some(text) => match serde_json::from_str::<serde_json::Value>(&text)
+            .with_context(|| format!("parse {what} JSON from {source}"))?
+        {
+            serde_json::Value::Object(map) => map,
+            other => {
+                return Err(anyhow!(
+                    "{what} from {source} must be a JSON object, got {}",
+                    json_kind(&other)
+                ));
+            }
+        },
+        None => serde_json::Map::new(),
+    };
+    for (k, v) in kv {
+        obj.insert(k.clone(), serde_json::Value::String(v.clone()));
+    }
+    Ok(serde_json::Value::Object(obj))
+}
+
+/// `--meta-filter`: every `key=value` must equal the asset's metadata entry. Non-string
+/// values match their JSON form (`build=1234` matches `1234` and `"1234"`).
+fn metadata_matches(metadata: Option<&serde_json::Value>, filters: &[(String, String)]) -> bool {
+    filters
+        .iter()
+        .all(|(k, want)| match metadata.and_then(|m| m.get(k)) {
+            Some(serde_json::Value::String(s)) => s == want,
+            None | Some(serde_json::Value::Null) => false,
+            Some(other) => {
+                serde_json::from_str::<serde_json::Value>(want).is_ok_and(|w| &w == other)
+            }
+        })
+}
+
+fn json_kind(v: &serde_json::Value) -> &'static str {
+    match v {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Bytes read from the start of an upload for `--sniff`.
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// `--sniff`: magic bytes first, then the file extension, then `application/octet-stream`.
+/// The second value says which of those decided, for the log line.
+fn sniff_content_type(prefix: &[u8], file_name: &str) -> (String, &'static str) {
+    if let Some(kind) = infer::get(prefix) {
+        return (kind.mime_type().to_string(), "magic bytes");
+    }
+    if let Some(ct) = content_type_for_extension(file_name) {
+        return (ct.to_string(), "file extension");
+    }
+    let source = if prefix.is_empty() {
+        "empty file"
+    } else {
+        "unrecognized"
+    };
+    ("application/octet-stream".to_string(), source)
+}
+
+/// Extension fallback for `--sniff`, mostly text formats that have no magic bytes.
+fn content_type_for_extension(file_name: &str) -> Option<&'static str> {
+    let (_, ext) = file_name.rsplit_once('.')?;
+    Some(match ext.to_ascii_lowercase().as_str() {
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "jsonl" | "ndjson" => "application/x-ndjson",
+        "yaml" | "yml" => "application/yaml",
+        "toml" => "application/toml",
+        "xml" => "application/xml",
+        "html" | "htm" => "text/html",
+        "svg" => "image/svg+xml",
+        "gltf" => "model/gltf+json",
+        "glb" => "model/gltf-binary",
+        "obj" => "model/obj",
+        "ini" | "cfg" | "uproject" | "uplugin" => "text/plain",
+        _ => return None,
+    })
+}
+
+/// `assets upload --checksum`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Checksum {
+    Auto,
+    /// Lowercase hex SHA-256 given by the user.
+    Hex(String),
+    None,
+}
+
+fn parse_checksum(s: &str) -> Result<Checksum, String> {
+    match s.trim() {
+        "auto" => Ok(Checksum::Auto),
+        "none" => Ok(Checksum::None),
+        hex => parse_sha256(hex)
+            .map(Checksum::Hex)
+            .map_err(|_| format!("expected auto, none, or a 64-character hex SHA-256, got {s:?}")),
+    }
+}
+
+/// A hex SHA-256 digest, lowercased.
+fn parse_sha256(s: &str) -> Result<String, String> {
+    let hex = s.trim();
+    if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(hex.to_ascii_lowercase())
+    } else {
+        Err(format!("expected a 64-character hex SHA-256, got {s:?}"))
+    }
+}
+
+/// Signed asset links live between one minute and seven days (the server enforces the same).
+const DOWNLOAD_URL_TTL_SECS: std::ops::RangeInclusive<u64> = 60..=7 * 24 * 60 * 60;
+
+/// `--expires` values: seconds, or a number with an `s`, `m`, `h` or `d` suffix.
+fn parse_expiry(s: &str) -> Result<u64, String> {
+    let spec = s.trim();
+    let (number, unit) = spec.split_at(
+        spec.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(spec.len()),
+    );
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("expected a duration like 15m, 1h or 7d, got {s:?}"))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => {
+            return Err(format!(
+                "unknown unit '{other}' in {s:?} (use s, m, h or d)"
+            ));
+        }
+    };
+    number
+        .checked_mul(multiplier)
+        .filter(|secs| DOWNLOAD_URL_TTL_SECS.contains(secs))
+        .ok_or_else(|| format!("expiry must be between 1m and 7d, got {s:?}"))
+}
+
+fn parse_query_arg(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected \"key=value\", got {s:?}"))?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!("invalid query key in {s:?}"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Apply `--raw-query` pairs: a key already set by a typed flag is replaced, new keys
+/// are appended (repeating a new key sends it repeatedly).
+fn merge_raw_query<'a>(query: &mut Vec<(&'a str, String)>, raw: &'a [(String, String)]) {
+    let typed: Vec<&str> = raw
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .filter(|k| query.iter().any(|(q, _)| q == k))
+        .collect();
+    query.retain(|(k, _)| !typed.contains(k));
+    query.extend(raw.iter().map(|(k, v)| (k.as_str(), v.clone())));
+}
+
+/// Token sources that outrank `PAJAMA_TOKEN` and the saved login.
+#[derive(Debug, Default)]
+struct TokenOverride {
+    /// `--token`, or what `--token-stdin` read
+    value: Option<String>,
+    /// `--token-file` / `PAJAMA_TOKEN_FILE`
+    file: Option<PathBuf>,
+}
+
+/// `memories create --stdin-json`: the request body read from stdin, checked and
+/// normalized like the flag path (title and tags scrubbed, tags capped, confidence
+/// clamped). A missing `project_id` comes from `--project-id` or the default project.
+fn memory_from_stdin(
+    project_id: Option<String>,
+    cfg: &config::Config,
+    strict: bool,
+) -> Result<(String, serde_json::Value)> {
+    use std::io::{IsTerminal, Read};
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err(anyhow!(
+            "--stdin-json expects a JSON object on a pipe, e.g. `pajama memories create --stdin-json < memory.json`"
+        ));
+    }
+    let mut text = String::new();
+    stdin
+        .read_to_string(&mut text)
+        .context("read memory JSON from stdin")?;
+    memory_from_json(&text, project_id, cfg, strict)
+}
+
+/// The checks behind `memory_from_stdin`, on text already read.
+fn memory_from_json(
+    text: &str,
+    project_id: Option<String>,
+    cfg: &config::Config,
+    strict: bool,
+) -> Result<(String, serde_json::Value)> {
+    let mut body: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(text).context("--stdin-json: stdin is not a JSON object")?;
+
+    let unknown: Vec<&str> = body
+        .keys()
+        .map(String::as_str)
+        .filter(|k| !MEMORY_CREATE_FIELDS.contains(k))
+        .collect();
+    if strict && !unknown.is_empty() {
+        return Err(anyhow!(
+            "--stdin-json: unknown field(s) {} (allowed: {})",
+            unknown.join(", "),
+            MEMORY_CREATE_FIELDS.join(", ")
+        ));
+    }
+
+    let project_id = match body.get("project_id") {
+        Some(serde_json::Value::String(id)) if !id.trim().is_empty() => id.trim().to_string(),
+        None | Some(serde_json::Value::Null) => resolve_project_id(project_id, cfg)?,
+        Some(_) => {
+            return Err(anyhow!(
+                "--stdin-json: project_id must be a non-empty string"
+            ));
+        }
+    };
+    body.insert("project_id".into(), project_id.clone().into());
+    for field in ["category", "title", "content"] {
+        let ok = body
+            .get(field)
+            .and_then(|v| v.as_str())
+            .is_some_and(|v| !v.trim().is_empty());
+        if !ok {
+            return Err(anyhow!(
+                "--stdin-json: {field} is required (a non-empty string)"
+            ));
+        }
+    }
+    let title = body["title"].as_str().unwrap_or_default();
+    let title = clean_line_arg("title", title, strict)?;
+    if title.is_empty() {
+        return Err(anyhow!("--stdin-json: title is empty"));
+    }
+    body.insert("title".into(), title.into());
+
+    match body.get("source_type") {
+        None | Some(serde_json::Value::Null) => {}
+        Some(serde_json::Value::String(t)) if SOURCE_TYPES.contains(&t.as_str()) => {}
+        Some(_) => {
+            return Err(anyhow!(
+                "--stdin-json: source_type must be one of {}",
+                SOURCE_TYPES.join(", ")
+            ));
+        }
+    }
+    if let Some(tags) = body.get("tags").filter(|v| !v.is_null()) {
+        let tags = tags
+            .as_array()
+            .ok_or_else(|| anyhow!("--stdin-json: tags must be an array of strings"))?;
+        let mut clean = Vec::new();
+        for tag in tags {
+            let tag = tag
+                .as_str()
+                .ok_or_else(|| anyhow!("--stdin-json: tags must be an array of strings"))?;
+            let tag = clean_line_arg("tags", tag, strict)?;
+            if !tag.is_empty() {
+                clean.push(serde_json::Value::from(tag));
+            }
+        }
+        if clean.len() > MAX_TAGS {
+            log_warn!("keeping the first {MAX_TAGS} of {} tags", clean.len());
+            clean.truncate(MAX_TAGS);
+        }
+        body.insert("tags".into(), clean.into());
+    }
+    if let Some(confidence) = body.get("confidence").filter(|v| !v.is_null()) {
+        let confidence = confidence
+            .as_f64()
+            .ok_or_else(|| anyhow!("--stdin-json: confidence must be a number"))?;
+        body.insert("confidence".into(), clamp_0_1(confidence).into());
+    }
+    if body
+        .get("context")
+        .is_some_and(|v| !v.is_object() && !v.is_null())
+    {
+        return Err(anyhow!("--stdin-json: context must be a JSON object"));
+    }
+    Ok((project_id, body.into()))
+}
+
+/// `--token-stdin`: everything on stdin, trimmed. Refuses a terminal so a forgotten pipe
+/// doesn't silently wait for input.
+fn read_token_stdin() -> Result<String> {
+    use std::io::{IsTerminal, Read};
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err(anyhow!(
+            "--token-stdin expects the token on a pipe, e.g. `cat token.txt | pajama --token-stdin ...`"
+        ));
+    }
+    let mut text = String::new();
+    stdin
+        .read_to_string(&mut text)
+        .context("read token from stdin")?;
+    let t = text.trim();
+    if t.is_empty() {
+        return Err(anyhow!("--token-stdin: stdin was empty"));
+    }
+    Ok(t.to_string())
+}
+
+/// Bearer token, first match wins: `--token`/`--token-stdin`, `--token-file` (or
+/// `PAJAMA_TOKEN_FILE`), `PAJAMA_TOKEN`, then the token saved by `pajama login`.
+fn resolve_token(token_override: &TokenOverride, cfg: &config::Config) -> Result<String> {
+    resolve_token_with(token_override, cfg, config::config_disabled(), |name| {
+        std::env::var(name).ok()
+    })
+}
+
+/// `resolve_token` with `--no-config` and the environment passed in.
+fn resolve_token_with(
+    token_override: &TokenOverride,
+    cfg: &config::Config,
+    config_disabled: bool,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    if let Some(t) = token_override.value.as_deref() {
         let t = t.trim();
         if !t.is_empty() {
             return Ok(t.to_string());
         }
     }
-    if let Ok(env_t) = std::env::var("PAJAMA_TOKEN") {
+    if let Some(path) = token_override.file.as_deref() {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read token file {}", path.display()))?;
+        let t = text.trim();
+        if t.is_empty() {
+            return Err(anyhow!("token file {} is empty", path.display()));
+        }
+        return Ok(t.to_string());
+    }
+    if let Some(env_t) = env("PAJAMA_TOKEN") {
         let t = env_t.trim().to_string();
         if !t.is_empty() {
             return Ok(t);
         }
     }
+    if config_disabled {
+        return Err(anyhow!(
+            "missing access token; --no-config needs --token, --token-stdin, --token-file, PAJAMA_TOKEN_FILE or PAJAMA_TOKEN"
+        ));
+    }
     cfg.access_token
         .clone()
         .filter(|t| !t.trim().is_empty())
         .ok_or_else(|| {
-            anyhow!("missing access token; run `pajama login` (or pass --token / set PAJAMA_TOKEN)")
+            anyhow!("missing access token; run `pajama login` (or pass --token / --token-file / set PAJAMA_TOKEN)")
         })
 }
 
-fn authed_api(token_override: Option<&str>, cfg: &config::Config) -> Result<ApiClient> {
+/// One `pajama doctor` line: a failing `critical` check makes the command exit nonzero.
+struct Check {
+    name: &'static str,
+    ok: bool,
+    critical: bool,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: true,
+            critical: false,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(
+        name: &'static str,
+        critical: bool,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            ok: false,
+            critical,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+async fn run_doctor(
+    api_url: Option<&str>,
+    oauth_metadata_url: Option<&str>,
+    token_override: &TokenOverride,
+    opts: &ClientOptions,
+) -> Result<()> {
+    let mut checks = Vec::new();
+
+    // Config file: missing is fine (defaults apply), unparseable is not.
+    let path = config::config_path()?;
+    let mut cfg = config::Config::default();
+    if !path.exists() {
+        checks.push(Check::fail(
+            "config file",
+            false,
+            format!("{} does not exist", path.display()),
+            "run `pajama login` to create it",
+        ));
+    } else {
+        match config::read_config_file(&path) {
+            Ok(loaded) => {
+                checks.push(Check::pass("config file", path.display().to_string()));
+                cfg = loaded;
+            }
+            Err(err) => checks.push(Check::fail(
+                "config file",
+                true,
+                format!("{err:#}"),
+                "fix or delete the file, then run `pajama login`",
+            )),
+        }
+    }
+
+    // Config dir: present and writable (checked without touching it), and the token
+    // file not readable by others.
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    match std::fs::metadata(dir) {
+        Ok(meta) if !meta.is_dir() => checks.push(Check::fail(
+            "config dir",
+            true,
+            format!("{} is not a directory", dir.display()),
+            "remove it or pass --config <path>",
+        )),
+        Ok(meta) if meta.permissions().readonly() => checks.push(Check::fail(
+            "config dir",
+            true,
+            format!("{} is not writable", dir.display()),
+            "fix the directory permissions or pass --config <path>",
+        )),
+        Ok(_) => checks.push(Check::pass("config dir", dir.display().to_string())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => checks.push(Check::fail(
+            "config dir",
+            false,
+            format!("{} does not exist", dir.display()),
+            "run `pajama login` to create it",
+        )),
+        Err(err) => checks.push(Check::fail(
+            "config dir",
+            true,
+            format!("cannot read {}: {err}", dir.display()),
+            "fix the directory permissions or pass --config <path>",
+        )),
+    }
+    if let Some(mode) = config::permissive_mode(&path) {
+        checks.push(Check::fail(
+            "config permissions",
+            false,
+            format!("mode {mode:o} lets other users read your token"),
+            format!("chmod 600 {}", path.display()),
+        ));
+    }
+
+    let raw_url = api_url.unwrap_or(&cfg.api_base_url).to_string();
+    match config::normalize_api_url(&raw_url, "api url") {
+        Ok(url) => {
+            cfg.api_base_url = url;
+            checks.push(Check::pass("api url", cfg.api_base_url.clone()));
+        }
+        Err(err) => {
+            checks.push(Check::fail(
+                "api url",
+                true,
+                format!("{err:#}"),
+                "pass --api-url or set PAJAMA_API_URL",
+            ));
+            return finish_doctor(&checks);
+        }
+    }
+
+    // Reachability: /health needs no auth, so no token is sent.
+    let probe = ApiClient::new(&cfg.api_base_url, "", opts)?;
+    match probe.get_json::<serde_json::Value>("/health", &[]).await {
+        Ok(_) => checks.push(Check::pass("api reachable", "GET /health ok")),
+        Err(err) => checks.push(Check::fail(
+            "api reachable",
+            true,
+            format!("{err:#}"),
+            "check the API URL, network, and any --header gateway credentials",
+        )),
+    }
+
+    match api::clock_skew() {
+        Some(skew) if skew.abs() > api::CLOCK_SKEW_WARN_SECS => checks.push(Check::fail(
+            "clock skew",
+            false,
+            api::describe_clock_skew(skew),
+            "sync the system clock (e.g. enable NTP); token expiry checks depend on it",
+        )),
+        Some(skew) => checks.push(Check::pass(
+            "clock skew",
+            format!("{skew:+}s vs server Date"),
+        )),
+        None => {}
+    }
+
+    let metadata_url = match oauth_metadata_url {
+        Some(url) => Some((url.to_string(), "--oauth-metadata-url")),
+        None => cfg
+            .oauth_metadata_url
+            .clone()
+            .map(|url| (url, "oauth_metadata_url (from config)")),
+    };
+    let discovery = match metadata_url {
+        Some((url, source)) => match config::normalize_api_url(&url, source) {
+            Ok(url) => discover_oauth(&cfg.api_base_url, Some(&url), opts.ip_preference).await,
+            Err(err) => Err(err),
+        },
+        None => discover_oauth(&cfg.api_base_url, None, opts.ip_preference).await,
+    };
+    match discovery {
+        Ok(meta) => checks.push(Check::pass("oauth discovery", meta.token_endpoint)),
+        Err(err) => checks.push(Check::fail(
+            "oauth discovery",
+            false,
+            format!("{err:#}"),
+            "`pajama login` needs this; --token / --token-file / PAJAMA_TOKEN still work",
+        )),
+    }
+
+    match resolve_token(token_override, &cfg) {
+        Ok(token) => match verify_token(&cfg, &token, opts).await {
+            Ok(n) => {
+                checks.push(Check::pass(
+                    "token",
+                    format!("accepted ({n} project(s) visible)"),
+                ));
+                // Only known for the token `pajama login` saved.
+                if let Some(scopes) = cfg
+                    .granted_scopes
+                    .as_deref()
+                    .filter(|_| cfg.access_token.as_deref() == Some(token.as_str()))
+                {
+                    checks.push(Check::pass("token scopes", scopes));
+                }
+            }
+            Err(err) => checks.push(Check::fail(
+                "token",
+                true,
+                format!("{err:#}"),
+                "the token may be expired or lack scopes; run `pajama login`",
+            )),
+        },
+        Err(err) => checks.push(Check::fail(
+            "token",
+            true,
+            format!("{err:#}"),
+            "run `pajama login` (or pass --token / --token-file / set PAJAMA_TOKEN)",
+        )),
+    }
+
+    finish_doctor(&checks)
+}
+
+fn finish_doctor(checks: &[Check]) -> Result<()> {
+    for c in checks {
+        let mark = match (c.ok, c.critical) {
+            (true, _) => "ok  ",
+            (false, false) => "warn",
+            (false, true) => "FAIL",
+        };
+        println!("[{mark}] {:<19} {}", c.name, c.detail);
+        if let Some(hint) = &c.hint {
+            println!("       {:<19} -> {hint}", "");
+        }
+    }
+    let failed = checks.iter().filter(|c| !c.ok && c.critical).count();
+    if failed > 0 {
+        return Err(anyhow!("{failed} critical check(s) failed"));
+    }
+    Ok(())
+}
+
+fn authed_api(
+    token_override: &TokenOverride,
+    cfg: &config::Config,
+    opts: &ClientOptions,
+) -> Result<ApiClient> {
     let token = resolve_token(token_override, cfg)?;
-    ApiClient::new(&cfg.api_base_url, &token)
+    ApiClient::new(&cfg.api_base_url, &token, opts)
+}
+
+/// `projects list --facets`: project counts per engine, plus memory totals per project
+/// and per engine when `memory_counts` is given.
+fn project_facets(
+    projects: &[ProjectRow],
+    memory_counts: Option<&BTreeMap<String, u64>>,
+) -> serde_json::Value {
+    let mut by_engine: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut memories_by_engine: BTreeMap<&str, u64> = BTreeMap::new();
+    for p in projects {
+        *by_engine.entry(p.engine.as_str()).or_default() += 1;
+        if let Some(n) = memory_counts.and_then(|c| c.get(&p.id)) {
+            *memories_by_engine.entry(p.engine.as_str()).or_default() += n;
+        }
+    }
+    let mut facets = serde_json::json!({ "engine": by_engine });
+    if let Some(counts) = memory_counts {
+        facets["memories_by_project"] = serde_json::json!(counts);
+        facets["memories_by_engine"] = serde_json::json!(memories_by_engine);
+    }
+    facets
+}
+
+/// Memory totals per project id (the summed `memory_stats` of `GET /api/projects/{id}`),
+/// fetched at most `concurrency` at a time.
+async fn project_memory_counts(
+    api: &ApiClient,
+    projects: &[ProjectRow],
+    concurrency: usize,
+) -> Result<BTreeMap<String, u64>> {
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(concurrency.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    for p in projects {
+        let api = api.clone();
+        let semaphore = semaphore.clone();
+        let id = p.id.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let res: serde_json::Value = api
+                .get_json(&format!("/api/projects/{id}"), &[])
+                .await
+                .with_context(|| format!("count memories for project {id}"))?;
+            let total = memory_stats(&res).values().sum::<u64>();
+            Ok::<_, anyhow::Error>((id, total))
+        });
+    }
+
+    let mut counts = BTreeMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (id, total) = joined.context("memory count task panicked")??;
+        counts.insert(id, total);
+    }
+    Ok(counts)
+}
+
+/// Memories per category from the `memory_stats` rows of `GET /api/projects/:id`, which
+/// the server counts over every memory of the project.
+fn memory_stats(project: &serde_json::Value) -> BTreeMap<String, u64> {
+    let mut by_category = BTreeMap::new();
+    for row in project
+        .get("memory_stats")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let category = row.get("category").and_then(|v| v.as_str()).unwrap_or("");
+        let count = row.get("count").and_then(|c| c.as_u64()).unwrap_or(0);
+        *by_category.entry(category.to_string()).or_default() += count;
+    }
+    by_category
+}
+
+/// Cheap authenticated call to prove a freshly issued token works; returns the project count.
+async fn verify_token(cfg: &config::Config, token: &str, opts: &ClientOptions) -> Result<usize> {
+    let api = ApiClient::new(&cfg.api_base_url, token, opts)?;
+    let res: ProjectsListResponse = api.get_json("/api/projects", &[]).await?;
+    Ok(res.projects.len())
+}
+
+fn report_token_check(cfg: &config::Config, verified: Result<usize>, strict: bool) -> Result<()> {
+    match verified {
+        Ok(n) => {
+            log_info!(
+                "Token verified against {} ({n} project(s) visible).",
+                cfg.api_base_url
+            );
+            Ok(())
+        }
+        Err(err) if strict => Err(err.context("post-login token check failed")),
+        Err(err) => {
+            log_warn!("post-login token check failed: {err:#}");
+            log_info!(
+                "The token may lack the needed scopes, or the API URL ({}) may be wrong.",
+                cfg.api_base_url
+            );
+            Ok(())
+        }
+    }
+}
+
+fn resolve_project_id(project_override: Option<String>, cfg: &config::Config) -> Result<String> {
+    if let Some(p) = project_override {
+        let p = p.trim();
+        if !p.is_empty() {
+            return Ok(p.to_string());
+        }
+    }
+    let p = cfg
+        .default_project_id
+        .clone()
+        .filter(|p| !p.trim().is_empty())
+        .ok_or_else(|| {
+            anyhow!(
+                "missing --project-id; pass one or set a default with `pajama projects use <id>`"
+            )
+        })?;
+    log_info!("Using default project {p}");
+    Ok(p)
 }
 
-async fn handle_projects(api: ApiClient, cmd: ProjectsCmd) -> Result<()> {
+async fn handle_projects(api: ApiClient, cfg: &config::Config, cmd: ProjectsCmd) -> Result<()> {
     match cmd {
-        ProjectsCmd::List { json } => {
+        ProjectsCmd::List {
+            fields,
+            output_template,
+            fail_if_empty,
+            facets,
+            with_memory_counts,
+            concurrency,
+            only_ids,
+            json,
+        } => {
+            let fields = output::parse_fields(fields.as_deref(), PROJECT_FIELDS)?;
+            let template =
+                output::parse_output_template(output_template.as_deref(), PROJECT_FIELDS)?;
             let res: ProjectsListResponse = api.get_json("/api/projects", &[]).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            let memory_counts = if with_memory_counts {
+                Some(project_memory_counts(&api, &res.projects, concurrency).await?)
+            } else {
+                None
+            };
+            let structured = output::structured(json) && !only_ids;
+            if structured {
+                if facets {
+                    let mut out = serde_json::to_value(&res)?;
+                    out["facets"] = project_facets(&res.projects, memory_counts.as_ref());
+                    output::print_structured(&out)?;
+                } else {
+                    output::print_structured(&res)?;
+                }
+            }
+            if res.projects.is_empty() {
+                report_empty("projects", structured, fail_if_empty);
+            }
+            if structured {
                 return Ok(());
             }
-            for p in res.projects {
-                println!("{}\t{}\t({})", p.id, p.name, p.engine);
+            if only_ids {
+                for p in &res.projects {
+                    println!("{}", p.id);
+                }
+                return Ok(());
+            }
+            if let Some(template) = &template {
+                for p in &res.projects {
+                    output::print_template(p, template)?;
+                }
+                return Ok(());
+            }
+            if let Some(fields) = fields {
+                for p in &res.projects {
+                    output::print_fields(p, &fields)?;
+                }
+                return Ok(());
+            }
+            for p in &res.projects {
+                match memory_counts.as_ref().and_then(|c| c.get(&p.id)) {
+                    Some(n) => println!("{}\t{}\t({})\t{n} memories", p.id, p.name, p.engine),
+                    None => println!("{}\t{}\t({})", p.id, p.name, p.engine),
+                }
+            }
+            if facets && !res.projects.is_empty() {
+                let summary = project_facets(&res.projects, memory_counts.as_ref());
+                println!();
+                println!("By engine:");
+                for (engine, n) in summary["engine"].as_object().into_iter().flatten() {
+                    match summary["memories_by_engine"].get(engine) {
+                        Some(m) => println!("  {engine:<10} {n:>4} project(s)  {m} memories"),
+                        None => println!("  {engine:<10} {n:>4} project(s)"),
+                    }
+                }
+            }
+        }
+        ProjectsCmd::Use { .. } | ProjectsCmd::Engines => unreachable!("handled in main"),
+        ProjectsCmd::Get {
+            id,
+            output_template,
+            only_id,
+            json,
+        } => {
+            let template =
+                output::parse_output_template(output_template.as_deref(), PROJECT_FIELDS)?;
+            let res: serde_json::Value = api.get_json(&format!("/api/projects/{id}"), &[]).await?;
+            if only_id {
+                return print_only_id(&res);
+            }
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+            if let Some(template) = &template {
+                return output::print_template(&res, template);
+            }
+            let field = |k: &str| {
+                res.get(k)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("-")
+                    .to_string()
+            };
+            println!("id          {}", field("id"));
+            println!("name        {}", field("name"));
+            println!("engine      {}", field("engine"));
+            println!("description {}", field("description"));
+            if let Some(stats) = res.get("memory_stats") {
+                println!("memory_stats {}", stats);
+            }
+        }
+        ProjectsCmd::Stats { project_id, json } => {
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let res: serde_json::Value = api
+                .get_json(&format!("/api/projects/{project_id}"), &[])
+                .await?;
+            let by_category = memory_stats(&res);
+            let total: u64 = by_category.values().sum();
+
+            if output::structured(json) {
+                output::print_structured(&serde_json::json!({
+                    "project_id": project_id,
+                    "total_memories": total,
+                    "by_category": by_category,
+                }))?;
+                return Ok(());
+            }
+
+            println!("project_id      {}", project_id);
+            println!("total_memories  {}", total);
+            for (category, count) in &by_category {
+                println!("  {}\t{}", category, count);
             }
         }
         ProjectsCmd::Create {
             name,
             engine,
             description,
+            seed,
+            force,
         } => {
+            let engine = match normalize_engine(&engine) {
+                Some(canonical) => canonical.to_string(),
+                None if force => engine.trim().to_lowercase(),
+                None => {
+                    let known: Vec<&str> = ENGINE_PRESETS.iter().map(|p| p.engine).collect();
+                    return Err(anyhow!(
+                        "unknown engine '{}' (valid: {}; pass --force to use it anyway)",
+                        engine.trim(),
+                        known.join(", ")
+                    ));
+                }
+            };
+            if engine.is_empty() {
+                return Err(anyhow!("--engine must not be empty"));
+            }
+            let preset = ENGINE_PRESETS.iter().find(|p| p.engine == engine);
+
             let req = CreateProjectRequest {
                 name: &name,
                 engine: &engine,
@@ -787,12 +3261,59 @@ async fn handle_projects(api: ApiClient, cmd: ProjectsCmd) -> Result<()> {
             };
             let res: CreateProjectResponse = api.post_json("/api/projects", &req).await?;
             println!("{}", res.id);
+
+            if seed {
+                let categories = preset.map_or(&[][..], |p| p.categories);
+                if categories.is_empty() {
+                    log_info!("No starter set for engine '{engine}'; nothing seeded.");
+                }
+                let context = serde_json::json!({ "seed": "engine-preset", "engine": engine });
+                for (category, title, content) in categories {
+                    let req = CreateMemoryRequest {
+                        project_id: &res.id,
+                        session_id: None,
+                        category,
+                        source_type: "manual",
+                        title,
+                        content,
+                        tags: vec![engine.clone(), "starter".to_string()],
+                        context: context.clone(),
+                        confidence: 0.5,
+                    };
+                    let created: CreateMemoryResponse = api
+                        .post_json("/api/memories", &req)
+                        .await
+                        .with_context(|| format!("seed '{category}' memory"))?;
+                    log_info!("Seeded {category}: {} ({})", title, created.id);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_templates(cmd: TemplatesCmd) -> Result<()> {
+    match cmd {
+        TemplatesCmd::List => {
+            let names = templates::list_templates()?;
+            if names.is_empty() {
+                log_info!("No templates in {}", templates::templates_dir()?.display());
+            }
+            for name in names {
+                println!("{name}");
+            }
+        }
+        TemplatesCmd::Show { name } => {
+            print!("{}", templates::read_template_text(&name)?);
+        }
+        TemplatesCmd::Path => {
+            println!("{}", templates::templates_dir()?.display());
         }
     }
     Ok(())
 }
 
-async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
+async fn handle_memories(api: ApiClient, cfg: &config::Config, cmd: MemoriesCmd) -> Result<()> {
     match cmd {
         MemoriesCmd::List {
             project_id,
@@ -800,9 +3321,20 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             q,
             tag,
             limit,
+            offset,
+            no_cache,
+            raw_query,
+            fields,
+            output_template,
+            fail_if_empty,
+            only_ids,
             json,
         } => {
-            let mut query: Vec<(&str, String)> = vec![("limit", limit.to_string())];
+            let fields = output::parse_fields(fields.as_deref(), MEMORY_FIELDS)?;
+            let template =
+                output::parse_output_template(output_template.as_deref(), MEMORY_FIELDS)?;
+            let limit = effective_limit(limit, MEMORIES_LIST_MAX);
+            let mut query = page_query(limit, offset);
             if let Some(v) = project_id {
                 query.push(("project_id", v));
             }
@@ -815,55 +3347,615 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             if let Some(v) = tag {
                 query.push(("tag", v));
             }
+            merge_raw_query(&mut query, &raw_query);
 
-            let res: MemoriesListResponse = api.get_json("/api/memories", &query).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
+            let res: MemoriesListResponse = if no_cache {
+                api.get_json_streamed("/api/memories", &query).await?
+            } else {
+                api.get_json_cached("/api/memories", &query).await?
+            };
+            ensure_offset_applied(offset, res.meta.as_ref())?;
+            let structured = output::structured(json) && !only_ids;
+            if structured {
+                output::print_structured(&res)?;
             }
-
-            for m in res.memories {
-                println!(
-                    "{}\t{}\t{}\t(conf={:.2})\t{}",
-                    m.id, m.project_id, m.category, m.confidence, m.title
-                );
+            if res.memories.is_empty() {
+                report_empty("memories", structured, fail_if_empty);
             }
-        }
-        MemoriesCmd::Get { id, json } => {
-            let res: MemoryGetResponse = api.get_json(&format!("/api/memories/{id}"), &[]).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if structured || res.memories.is_empty() {
                 return Ok(());
             }
-            println!(
-                "{}\n{}\n\n{}",
-                res.title,
-                format!("[{}] conf={:.2}", res.category, res.confidence),
-                res.content
+            report_list_count("memories", res.memories.len(), limit);
+            if only_ids {
+                for m in &res.memories {
+                    println!("{}", m.id);
+                }
+                return Ok(());
+            }
+            if let Some(template) = &template {
+                for m in &res.memories {
+                    output::print_template(m, template)?;
+                }
+                return Ok(());
+            }
+            if let Some(fields) = fields {
+                for m in &res.memories {
+                    output::print_fields(m, &fields)?;
+                }
+                return Ok(());
+            }
+
+            for m in res.memories {
+                println!(
+                    "{}\t{}\t{}\t(conf={:.2})\t{}",
+                    m.id, m.project_id, m.category, m.confidence, m.title
+                );
+            }
+        }
+        MemoriesCmd::Count {
+            project_id,
+            category,
+            q,
+            tag,
+            json,
+        } => {
+            // No count endpoint: list previews (no content) up to the cap and count them.
+            let mut query: Vec<(&str, String)> = vec![
+                ("limit", MEMORIES_LIST_MAX.to_string()),
+                ("include_content", "false".to_string()),
+            ];
+            if let Some(v) = project_id {
+                query.push(("project_id", v));
+            }
+            if let Some(v) = category {
+                query.push(("category", v));
+            }
+            if let Some(v) = q {
+                query.push(("q", v));
+            }
+            if let Some(v) = tag {
+                query.push(("tag", v));
+            }
+
+            let res: serde_json::Value = api.get_json_streamed("/api/memories", &query).await?;
+            let count = res
+                .get("memories")
+                .and_then(|v| v.as_array())
+                .map_or(0, |a| a.len());
+            print_count(count, MEMORIES_LIST_MAX, json)?;
+        }
+        MemoriesCmd::Watch {
+            project_id,
+            category,
+            tag,
+            interval,
+            limit,
+            fields,
+            output_template,
+            json,
+        } => {
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let fields = output::parse_fields(fields.as_deref(), MEMORY_FIELDS)?;
+            let template =
+                output::parse_output_template(output_template.as_deref(), MEMORY_FIELDS)?;
+            let limit = effective_limit(limit, MEMORIES_LIST_MAX);
+            let structured = output::structured(json);
+            let mut query: Vec<(&str, String)> = vec![
+                ("limit", limit.to_string()),
+                ("project_id", project_id.clone()),
+                ("include_content", "false".to_string()),
+            ];
+            if let Some(v) = category {
+                query.push(("category", v));
+            }
+            if let Some(v) = tag {
+                query.push(("tag", v));
+            }
+
+            // There is no change feed, so poll the newest page and diff it against the
+            // `updated_at` last seen for each id.
+            let mut seen: std::collections::HashMap<String, String> =
+                std::collections::HashMap::new();
+            let mut baseline = true;
+            log_info!(
+                "Watching memories in project {project_id} every {interval}s (Ctrl+C to stop)"
             );
+            let watch = async {
+                loop {
+                    let res: Result<MemoriesListResponse> =
+                        api.get_json_streamed("/api/memories", &query).await;
+                    let res = match res {
+                        Ok(res) => res,
+                        Err(err) if !baseline => {
+                            log_warn!("poll failed: {err:#}; retrying in {interval}s");
+                            tokio::time::sleep(Duration::from_secs(interval)).await;
+                            continue;
+                        }
+                        Err(err) => return Err(err),
+                    };
+                    let polled = res.memories.len();
+                    let mut created = 0;
+                    let mut changes = Vec::new();
+                    // The list is newest first; print oldest first, like a log.
+                    for m in res.memories.into_iter().rev() {
+                        let event = match seen.insert(m.id.clone(), m.updated_at.clone()) {
+                            None => "created",
+                            Some(prev) if prev != m.updated_at => "updated",
+                            Some(_) => continue,
+                        };
+                        if event == "created" {
+                            created += 1;
+                        }
+                        changes.push((event, m));
+                    }
+                    if baseline {
+                        baseline = false;
+                    } else {
+                        if polled > 0 && created == polled && polled as u64 >= limit as u64 {
+                            log_warn!(
+                                "{created} new memories since the last poll fill the whole page; some may have been missed (raise --limit or lower --interval)"
+                            );
+                        }
+                        for (event, m) in &changes {
+                            if structured {
+                                let line = serde_json::json!({ "event": event, "memory": m });
+                                println!("{line}");
+                            } else if let Some(template) = &template {
+                                output::print_template(m, template)?;
+                            } else if let Some(fields) = &fields {
+                                output::print_fields(m, fields)?;
+                            } else {
+                                let marker = if *event == "created" { "+" } else { "~" };
+                                println!(
+                                    "{marker} {}\t{}\t{}\t(conf={:.2})\t{}",
+                                    m.updated_at, m.id, m.category, m.confidence, m.title
+                                );
+                            }
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_secs(interval)).await;
+                }
+            };
+            tokio::select! {
+                res = watch => res?,
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        MemoriesCmd::Tags {
+            project_id,
+            category,
+            json,
+        } => {
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            let mut scanned = 0usize;
+            let mut offset = 0u32;
+            loop {
+                let mut query: Vec<(&str, String)> = vec![
+                    ("limit", MEMORIES_LIST_MAX.to_string()),
+                    ("project_id", project_id.clone()),
+                    ("include_content", "false".to_string()),
+                ];
+                if offset > 0 {
+                    query.push(("offset", offset.to_string()));
+                }
+                if let Some(v) = &category {
+                    query.push(("category", v.clone()));
+                }
+
+                let res: serde_json::Value = api.get_json_streamed("/api/memories", &query).await?;
+                if ensure_offset_applied(offset, res.get("meta")).is_err() {
+                    log_warn!(
+                        "the server does not support offset paging; only the first {MEMORIES_LIST_MAX} memories were counted."
+                    );
+                    break;
+                }
+                let page = res
+                    .get("memories")
+                    .and_then(|v| v.as_array())
+                    .map(|a| a.as_slice())
+                    .unwrap_or_default();
+                for m in page {
+                    let mut tags =
+                        tags_from_value(m.get("tags").unwrap_or(&serde_json::Value::Null));
+                    // Count each tag once per memory even if it was stored twice.
+                    tags.sort();
+                    tags.dedup();
+                    for t in tags {
+                        *counts.entry(t).or_default() += 1;
+                    }
+                }
+                scanned += page.len();
+                if (page.len() as u32) < MEMORIES_LIST_MAX {
+                    break;
+                }
+                offset += MEMORIES_LIST_MAX;
+            }
+
+            let mut tags: Vec<(String, usize)> = counts.into_iter().collect();
+            tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+            if output::structured(json) {
+                let rows: Vec<serde_json::Value> = tags
+                    .iter()
+                    .map(|(tag, count)| serde_json::json!({ "tag": tag, "count": count }))
+                    .collect();
+                output::print_structured(&serde_json::json!({
+                    "scanned": scanned,
+                    "tags": rows,
+                }))?;
+                return Ok(());
+            }
+
+            if tags.is_empty() {
+                report_empty("tags", false, false);
+                return Ok(());
+            }
+            for (tag, count) in &tags {
+                println!("{count}\t{tag}");
+            }
+            if !output::quiet() {
+                log_info!("{} tags across {} memories", tags.len(), scanned);
+            }
+        }
+        MemoriesCmd::Get {
+            ids,
+            no_pager,
+            output_template,
+            only_id,
+            json,
+        } => {
+            let template =
+                output::parse_output_template(output_template.as_deref(), MEMORY_FIELDS)?;
+            let [id] = ids.as_slice() else {
+                let fetched = fetch_each::<MemoryGetResponse>(&api, "/api/memories", ids).await;
+                if only_id || output::structured(json) || template.is_some() {
+                    return print_fetched(&fetched, only_id, template.as_ref());
+                }
+                let mut text = String::new();
+                for (id, res) in &fetched {
+                    match res {
+                        Ok(m) => text.push_str(&format!("==> {id} <==\n{}\n", memory_text(m))),
+                        Err(err) => log_warn!("{id}: {err:#}"),
+                    }
+                }
+                output::page(&text, no_pager)?;
+                return fetch_failures(&fetched);
+            };
+            let res: MemoryGetResponse = api.get_json(&format!("/api/memories/{id}"), &[]).await?;
+            if only_id {
+                println!("{}", res.id);
+                return Ok(());
+            }
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+            if let Some(template) = &template {
+                return output::print_template(&res, template);
+            }
+            output::page(&memory_text(&res), no_pager)?;
+        }
+        MemoriesCmd::Diff {
+            a,
+            b,
+            no_color,
+            json,
+        } => {
+            let (path_a, path_b) = (format!("/api/memories/{a}"), format!("/api/memories/{b}"));
+            let (left, right) = tokio::try_join!(
+                api.get_json::<MemoryGetResponse>(&path_a, &[]),
+                api.get_json::<MemoryGetResponse>(&path_b, &[]),
+            )?;
+
+            let fields: Vec<(&str, serde_json::Value, serde_json::Value)> = vec![
+                (
+                    "title",
+                    left.title.as_str().into(),
+                    right.title.as_str().into(),
+                ),
+                (
+                    "category",
+                    left.category.as_str().into(),
+                    right.category.as_str().into(),
+                ),
+                (
+                    "tags",
+                    tags_from_value(&left.tags).into(),
+                    tags_from_value(&right.tags).into(),
+                ),
+                (
+                    "confidence",
+                    left.confidence.into(),
+                    right.confidence.into(),
+                ),
+                (
+                    "source_type",
+                    left.source_type.clone().into(),
+                    right.source_type.clone().into(),
+                ),
+            ];
+            let content_diff = similar::TextDiff::from_lines(&left.content, &right.content);
+            let same_content = left.content == right.content;
+            let identical = same_content && fields.iter().all(|(_, l, r)| l == r);
+
+            if output::structured(json) {
+                let fields_json: Vec<serde_json::Value> = fields
+                    .iter()
+                    .map(|(name, l, r)| {
+                        serde_json::json!({ "field": name, "a": l, "b": r, "equal": l == r })
+                    })
+                    .collect();
+                output::print_structured(&serde_json::json!({
+                    "a": a,
+                    "b": b,
+                    "identical": identical,
+                    "fields": fields_json,
+                    "content_diff": content_diff
+                        .unified_diff()
+                        .context_radius(3)
+                        .header(&a, &b)
+                        .to_string(),
+                }))?;
+                return Ok(());
+            }
+
+            let color = color_enabled(no_color);
+            let paint = |code: &str, text: &str| paint(color, code, text);
+
+            for (name, l, r) in &fields {
+                if l == r {
+                    println!("  {name}: {}", output::cell(l));
+                } else {
+                    let line = format!("~ {name}: {} -> {}", output::cell(l), output::cell(r));
+                    println!("{}", paint("33", &line));
+                }
+            }
+            println!();
+            if same_content {
+                println!("content identical");
+                return Ok(());
+            }
+            println!("{}", paint("1", &format!("--- {a}")));
+            println!("{}", paint("1", &format!("+++ {b}")));
+            for hunk in content_diff.unified_diff().context_radius(3).iter_hunks() {
+                println!("{}", paint("36", &hunk.header().to_string()));
+                for change in hunk.iter_changes() {
+                    let text = change.value().trim_end_matches(['\r', '\n']);
+                    match change.tag() {
+                        similar::ChangeTag::Delete => {
+                            println!("{}", paint("31", &format!("-{text}")))
+                        }
+                        similar::ChangeTag::Insert => {
+                            println!("{}", paint("32", &format!("+{text}")))
+                        }
+                        similar::ChangeTag::Equal => println!(" {text}"),
+                    }
+                    if change.missing_newline() {
+                        println!("\\ No newline at end of file");
+                    }
+                }
+            }
         }
+        MemoriesCmd::Templates { .. } => unreachable!("handled in main"),
         MemoriesCmd::Create {
             project_id,
             category,
             title,
             content,
+            template,
             tags,
             confidence,
+            strict,
+            stdin_json,
+            source_type,
+            session_id,
+            context,
+            context_file,
+            context_kv,
+            idempotency_key,
+            full,
+            attach,
+            json,
         } => {
-            let tags = parse_tags_csv(&tags);
-            let req = CreateMemoryRequest {
-                project_id: &project_id,
-                session_id: None,
-                category: &category,
-                source_type: "manual",
-                title: &title,
-                content: &content,
-                tags,
-                context: serde_json::json!({}),
-                confidence: clamp_0_1(confidence),
+            // Catch a mistyped path before the memory exists, not after.
+            for path in &attach {
+                let meta = tokio::fs::metadata(path)
+                    .await
+                    .with_context(|| format!("--attach {}", path.display()))?;
+                if !meta.is_file() {
+                    return Err(anyhow!("--attach path is not a file: {}", path.display()));
+                }
+            }
+            let (project_id, req) = if stdin_json {
+                memory_from_stdin(project_id, cfg, strict)?
+            } else {
+                let context =
+                    build_json_object("context", context, context_file.as_deref(), &context_kv)?;
+                let project_id = resolve_project_id(project_id, cfg)?;
+                let title = clean_line_arg("--title", &title.unwrap_or_default(), strict)?;
+                if title.is_empty() {
+                    return Err(anyhow!("--title is empty"));
+                }
+
+                // Flags win over template defaults.
+                let tpl = match template.as_deref() {
+                    Some(name) => templates::load_template(name)?,
+                    None => templates::Template::default(),
+                };
+                let category = category
+                    .or(tpl.category)
+                    .ok_or_else(|| anyhow!("--category is required (or set it in the template)"))?;
+                let content = match content {
+                    Some(c) => c,
+                    None if template.is_some() => templates::render(
+                        &tpl.body,
+                        &[
+                            ("title", title.as_str()),
+                            ("project", project_id.as_str()),
+                            ("category", category.as_str()),
+                        ],
+                    ),
+                    None => return Err(anyhow!("--content is required (or pass --template)")),
+                };
+                let tags = tags.or(tpl.tags).unwrap_or_default();
+                let confidence = confidence.or(tpl.confidence).unwrap_or(0.5);
+
+                let tags = parse_tags_csv(&tags)
+                    .iter()
+                    .map(|t| clean_line_arg("--tags", t, strict))
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter()
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                let req = CreateMemoryRequest {
+                    project_id: &project_id,
+                    session_id: session_id
+                        .as_deref()
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty()),
+                    category: &category,
+                    source_type: &source_type,
+                    title: &title,
+                    content: &content,
+                    tags,
+                    context,
+                    confidence: clamp_0_1(confidence),
+                };
+                let req = serde_json::to_value(&req)?;
+                (project_id, req)
             };
-            let res: CreateMemoryResponse = api.post_json("/api/memories", &req).await?;
-            println!("{}", res.id);
+            let key = match idempotency_key.as_deref().map(str::trim) {
+                Some("") => return Err(anyhow!("--idempotency-key must not be empty")),
+                Some(key) => key.to_string(),
+                None => new_uuid(),
+            };
+            let res: serde_json::Value = api
+                .post_json_idempotent("/api/memories", &req, &key)
+                .await?;
+            let created: CreateMemoryResponse =
+                serde_json::from_value(res.clone()).context("parse create response")?;
+            let attachments = attach_files(&api, cfg, &project_id, &created.id, attach).await;
+
+            let mut out = if !full {
+                res
+            } else {
+                // Older servers answer with just the id; fetch what was stored.
+                let memory: MemoryGetResponse = match serde_json::from_value(res) {
+                    Ok(memory) => memory,
+                    Err(_) => api
+                        .get_json(&format!("/api/memories/{}", created.id), &[])
+                        .await
+                        .with_context(|| format!("fetch created memory {}", created.id))?,
+                };
+                if !output::structured(json) {
+                    print!("{}", memory_text(&memory));
+                }
+                serde_json::to_value(&memory)?
+            };
+            if output::structured(json) {
+                if !attachments.is_empty() {
+                    out["attachments"] = attachments
+                        .iter()
+                        .map(|a| {
+                            serde_json::json!({
+                                "path": a.path.display().to_string(),
+                                "asset_id": a.result.as_ref().ok(),
+                                "error": a.result.as_ref().err().map(|e| format!("{e:#}")),
+                            })
+                        })
+                        .collect();
+                }
+                output::print_structured(&out)?;
+            } else if !full {
+                println!("{}", created.id);
+            }
+            report_attachments(&created.id, &attachments)?;
+        }
+        MemoriesCmd::Update {
+            id,
+            title,
+            content,
+            category,
+            tags,
+            confidence,
+            strict,
+            if_version,
+            force,
+            json,
+        } => {
+            if title.is_none()
+                && content.is_none()
+                && category.is_none()
+                && tags.is_none()
+                && confidence.is_none()
+            {
+                return Err(anyhow!(
+                    "nothing to update; pass --title, --content, --category, --tags, or --confidence"
+                ));
+            }
+            let title = title
+                .map(|t| clean_line_arg("--title", &t, strict))
+                .transpose()?;
+            if title.as_deref().is_some_and(str::is_empty) {
+                return Err(anyhow!("--title is empty"));
+            }
+            let tags = tags
+                .map(|t| {
+                    parse_tags_csv(&t)
+                        .iter()
+                        .map(|t| clean_line_arg("--tags", t, strict))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .transpose()?
+                .map(|t| t.into_iter().filter(|t| !t.is_empty()).collect());
+
+            let path = format!("/api/memories/{id}");
+            let current: MemoryGetResponse = api.get_json(&path, &[]).await?;
+            let stale = if_version.zip(current.version).filter(|(e, a)| e != a);
+            if let Some((expected, actual)) = stale {
+                return Err(memory_changed(&id, expected, Some(actual)));
+            }
+            // The version just read guards the write, so a concurrent edit between
+            // the GET and the PUT is caught too.
+            let expected = if force {
+                None
+            } else {
+                if_version.or(current.version)
+            };
+
+            let req = UpdateMemoryRequest {
+                category: category.as_deref().unwrap_or(&current.category),
+                source_type: current.source_type.as_deref().unwrap_or("manual"),
+                title: title.as_deref().unwrap_or(&current.title),
+                content: content.as_deref().unwrap_or(&current.content),
+                tags: tags.unwrap_or_else(|| tags_from_value(&current.tags)),
+                context: current
+                    .context
+                    .clone()
+                    .unwrap_or_else(|| serde_json::json!({})),
+                confidence: clamp_0_1(confidence.unwrap_or(current.confidence)),
+            };
+            let if_match = expected.map(|v| format!("\"{v}\""));
+            let res: serde_json::Value = match api
+                .put_json_if_match(&path, &req, if_match.as_deref())
+                .await?
+            {
+                Ok(res) => res,
+                Err(body) => {
+                    let now = body.get("current_version").and_then(|v| v.as_i64());
+                    return Err(memory_changed(&id, expected.unwrap_or_default(), now));
+                }
+            };
+
+            if output::structured(json) {
+                output::print_structured(&res)?;
+            } else {
+                println!("{id}");
+                if let Some(version) = res.get("version").and_then(|v| v.as_i64()) {
+                    log_info!("Updated memory {id} (version {version})");
+                }
+            }
         }
         MemoriesCmd::SearchIndex {
             project_id,
@@ -878,8 +3970,10 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             limit,
             json,
         } => {
-            let mut query: Vec<(&str, String)> =
-                vec![("limit", limit.to_string()), ("memory_mode", memory_mode)];
+            let mut query: Vec<(&str, String)> = vec![
+                ("limit", limit_param(limit, SEARCH_INDEX_MAX)),
+                ("memory_mode", memory_mode),
+            ];
             if let Some(v) = project_id {
                 query.push(("project_id", v));
             }
@@ -906,8 +4000,8 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             }
 
             let res: serde_json::Value = api.get_json("/api/memories/search-index", &query).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
 
@@ -952,8 +4046,8 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
                 "include_content": !no_content
             });
             let res: serde_json::Value = api.post_json("/api/memories/batch-get", &payload).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
 
@@ -989,7 +4083,7 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             limit,
             json,
         } => {
-            let mut query: Vec<(&str, String)> = vec![("limit", limit.to_string())];
+            let mut query: Vec<(&str, String)> = vec![("limit", limit_param(limit, TIMELINE_MAX))];
             if let Some(v) = project_id {
                 query.push(("project_id", v));
             }
@@ -1013,8 +4107,8 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             }
 
             let res: serde_json::Value = api.get_json("/api/memories/timeline", &query).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
             let total = res.get("total").and_then(|v| v.as_u64()).unwrap_or(0);
@@ -1045,7 +4139,7 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             json,
         } => {
             let mut query: Vec<(&str, String)> = vec![
-                ("limit", limit.to_string()),
+                ("limit", limit_param(limit, FORESIGHT_MAX)),
                 ("within_days", within_days.to_string()),
             ];
             if let Some(v) = project_id {
@@ -1067,8 +4161,8 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             let res: serde_json::Value = api
                 .get_json("/api/memories/foresight/active", &query)
                 .await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
 
@@ -1122,8 +4216,8 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
             let res: serde_json::Value = api
                 .post_json(&format!("/api/memories/{id}/derive"), &payload)
                 .await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
 
@@ -1179,699 +4273,2872 @@ async fn handle_memories(api: ApiClient, cmd: MemoriesCmd) -> Result<()> {
                 println!("planned_foresight {}", foresight_plan);
             }
         }
-    }
-    Ok(())
-}
+        MemoriesCmd::Move {
+            id,
+            to_project,
+            yes,
+            json,
+        } => {
+            let to_project = to_project.trim().to_string();
+            let memory: MemoryGetResponse =
+                api.get_json(&format!("/api/memories/{id}"), &[]).await?;
+            if memory.project_id == to_project {
+                println!("memory {} is already in project {}", id, to_project);
+                return Ok(());
+            }
 
-async fn handle_assets(api: ApiClient, cmd: AssetsCmd) -> Result<()> {
-    match cmd {
-        AssetsCmd::Get { id, json } => {
-            let res: serde_json::Value = api.get_json(&format!("/api/assets/{id}"), &[]).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            let _project: serde_json::Value = api
+                .get_json(&format!("/api/projects/{to_project}"), &[])
+                .await
+                .with_context(|| format!("look up target project {to_project}"))?;
+
+            // Attached assets keep their own project_id; flag links that will now cross projects.
+            let linked: AssetsListResponse = api
+                .get_json(
+                    "/api/assets",
+                    &[
+                        ("memory_id", id.clone()),
+                        ("limit", ASSETS_LIST_MAX.to_string()),
+                    ],
+                )
+                .await?;
+            let cross: Vec<&AssetRow> = linked
+                .assets
+                .iter()
+                .filter(|a| a.project_id != to_project)
+                .collect();
+            if !cross.is_empty() {
+                log_warn!(
+                    "{} linked asset(s) stay in project {} and will point across projects:",
+                    cross.len(),
+                    memory.project_id
+                );
+                for a in &cross {
+                    log_info!(
+                        "  {}\t{}",
+                        a.id,
+                        a.original_name.as_deref().unwrap_or(&a.r2_key)
+                    );
+                }
+            }
+
+            if !yes
+                && !confirm(&format!(
+                    "Move memory {} (\"{}\") from project {} to {}?",
+                    id, memory.title, memory.project_id, to_project
+                ))?
+            {
+                return Err(anyhow!("aborted"));
+            }
+
+            let res: serde_json::Value = api
+                .post_json(
+                    &format!("/api/memories/{id}/move"),
+                    &serde_json::json!({ "project_id": to_project }),
+                )
+                .await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
-            println!("{}", serde_json::to_string_pretty(&res)?);
+            println!("{}\t{} -> {}", id, memory.project_id, to_project);
         }
-        AssetsCmd::List {
+        MemoriesCmd::Dedupe {
             project_id,
-            memory_id,
-            status,
-            limit,
+            by,
+            delete,
+            dry_run,
+            yes,
             json,
         } => {
-            let mut query: Vec<(&str, String)> = vec![("limit", limit.to_string())];
-            if let Some(v) = project_id {
-                query.push(("project_id", v));
-            }
-            if let Some(v) = memory_id {
-                query.push(("memory_id", v));
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let query: Vec<(&str, String)> = vec![
+                ("limit", MEMORIES_LIST_MAX.to_string()),
+                ("project_id", project_id),
+            ];
+            let res: MemoriesListResponse = api.get_json_streamed("/api/memories", &query).await?;
+            let scanned = res.memories.len();
+
+            let mut groups: BTreeMap<String, Vec<&MemoryRow>> = BTreeMap::new();
+            for m in &res.memories {
+                groups.entry(dedupe_hash(m, by)).or_default().push(m);
             }
-            if let Some(v) = status {
-                query.push(("status", v));
+            let mut clusters: Vec<(String, Vec<&MemoryRow>)> =
+                groups.into_iter().filter(|(_, ms)| ms.len() > 1).collect();
+            for (_, ms) in clusters.iter_mut() {
+                // Keeper first: highest confidence, then most recently updated, then id for stability.
+                ms.sort_by(|a, b| {
+                    b.confidence
+                        .partial_cmp(&a.confidence)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                        .then_with(|| b.updated_at.cmp(&a.updated_at))
+                        .then_with(|| a.id.cmp(&b.id))
+                });
             }
+            let removable: Vec<&MemoryRow> = clusters
+                .iter()
+                .flat_map(|(_, ms)| ms.iter().skip(1).copied())
+                .collect();
 
-            let res: AssetsListResponse = api.get_json("/api/assets", &query).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
+            if !json {
+                for (hash, ms) in &clusters {
+                    println!("cluster {} ({} memories)", &hash[..12], ms.len());
+                    for (i, m) in ms.iter().enumerate() {
+                        println!(
+                            "  {}\t{}\t(conf={:.2})\t{}\t{}",
+                            if i == 0 { "keep  " } else { "remove" },
+                            m.id,
+                            m.confidence,
+                            m.updated_at,
+                            m.title
+                        );
+                    }
+                }
             }
 
-            for a in res.assets {
-                println!(
-                    "{}\t{}\t{}\t{} bytes\t{}",
-                    a.id,
-                    a.project_id,
-                    a.status,
-                    a.byte_size,
-                    a.original_name.unwrap_or_else(|| a.r2_key)
-                );
+            let mut removed: Vec<&str> = Vec::new();
+            if delete && !dry_run && !removable.is_empty() {
+                if !yes && !confirm(&format!("Delete {} duplicate memories?", removable.len()))? {
+                    return Err(anyhow!("aborted"));
+                }
+                for m in &removable {
+                    let _res: serde_json::Value = api
+                        .delete_json(&format!("/api/memories/{}", m.id))
+                        .await
+                        .with_context(|| format!("delete memory {}", m.id))?;
+                    removed.push(&m.id);
+                }
             }
-        }
-        AssetsCmd::Download { id, out } => {
-            let query: Vec<(&str, String)> = vec![];
-            let mut res = api
-                .raw_get(&format!("/api/assets/{id}/object"), &query)
-                .await?;
-            let status = res.status();
-            if !status.is_success() {
-                let text = res.text().await.unwrap_or_default();
-                return Err(anyhow!("download failed (HTTP {status}): {text}"));
+
+            if output::structured(json) {
+                let clusters_json: Vec<serde_json::Value> = clusters
+                    .iter()
+                    .map(|(hash, ms)| {
+                        serde_json::json!({
+                            "hash": hash,
+                            "keep": ms[0].id,
+                            "duplicates": ms.iter().skip(1).map(|m| m.id.as_str()).collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect();
+                output::print_structured(&serde_json::json!({
+                    "scanned": scanned,
+                    "clusters": clusters_json,
+                    "removable": removable.len(),
+                    "removed": removed,
+                    "dry_run": dry_run || !delete,
+                }))?;
+                return Ok(());
             }
 
-            let mut f = tokio::fs::File::create(&out)
-                .await
-                .with_context(|| format!("create {}", out.display()))?;
-            while let Some(chunk) = res.chunk().await.context("read download chunk")? {
-                tokio::io::AsyncWriteExt::write_all(&mut f, &chunk)
-                    .await
-                    .context("write download chunk")?;
+            println!("scanned    {}", scanned);
+            println!("clusters   {}", clusters.len());
+            println!("removable  {}", removable.len());
+            println!("removed    {}", removed.len());
+            if scanned as u32 >= MEMORIES_LIST_MAX {
+                log_warn!(
+                    "scan hit the {MEMORIES_LIST_MAX}-row list cap; duplicates beyond it were not compared."
+                );
             }
-            println!("{}", out.display());
         }
-        AssetsCmd::Upload {
+        MemoriesCmd::Retag {
             project_id,
-            path,
-            memory_id,
-            content_type,
-            part_size_mb,
+            from,
+            to,
+            remove,
+            add,
+            tag,
+            dry_run,
             json,
         } => {
-            let meta = tokio::fs::metadata(&path)
-                .await
-                .with_context(|| format!("stat {}", path.display()))?;
-            if !meta.is_file() {
-                return Err(anyhow!("path is not a file: {}", path.display()));
-            }
-            let byte_size = meta.len();
-
-            let file_name = path
-                .file_name()
-                .and_then(|s| s.to_str())
-                .ok_or_else(|| anyhow!("invalid filename (non-utf8)"))?;
-
-            let content_type =
-                content_type.unwrap_or_else(|| "application/octet-stream".to_string());
-            let mut part_size = choose_part_size(byte_size, part_size_mb);
-
-            // Ensure we stay <= 10k parts.
-            let parts = div_ceil(byte_size, part_size);
-            if parts > 10_000 {
-                let min_part = div_ceil(byte_size, 10_000);
-                part_size = clamp_part_size(min_part);
-            }
-
-            let req = CreateAssetRequest {
-                project_id: &project_id,
-                original_name: file_name,
-                content_type: &content_type,
-                byte_size,
-                part_size,
-                memory_id: memory_id.as_deref(),
-                relation: Some("attachment"),
-                metadata: serde_json::json!({}),
-            };
-
-            let created: CreateAssetResponse = api.post_json("/api/assets", &req).await?;
-            if json {
-                eprintln!(
-                    "{}",
-                    serde_json::to_string_pretty(&serde_json::json!({
-                        "id": created.id,
-                        "upload_part_size": created.upload_part_size
-                    }))?
-                );
-            } else {
-                eprintln!("[pajama] Asset created: {}", created.id);
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let from = from.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+            let to = to.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+            let remove = remove
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty());
+            let add = add.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+            if from.is_none() && remove.is_none() && add.is_none() {
+                return Err(anyhow!(
+                    "nothing to do; pass --from/--to, --remove, or --add"
+                ));
             }
 
-            let mut f = tokio::fs::File::open(&path)
-                .await
-                .with_context(|| format!("open {}", path.display()))?;
-
-            let mut remaining = byte_size;
-            let mut part_number: u32 = 1;
-            let part_size_u64 = created.upload_part_size;
-
-            while remaining > 0 {
-                let this_size = std::cmp::min(part_size_u64, remaining) as usize;
-                let mut buf = vec![0u8; this_size];
-                f.read_exact(&mut buf)
-                    .await
-                    .with_context(|| format!("read part {part_number}"))?;
+            let mut query: Vec<(&str, String)> = vec![
+                ("limit", MEMORIES_LIST_MAX.to_string()),
+                ("project_id", project_id),
+                ("include_inactive", "true".to_string()),
+            ];
+            if let Some(v) = tag.or_else(|| from.clone()).or_else(|| remove.clone()) {
+                query.push(("tag", v));
+            }
 
-                let _resp: serde_json::Value = api
-                    .put_bytes(
-                        &format!("/api/assets/{}/parts/{}", created.id, part_number),
-                        "application/octet-stream",
-                        buf,
-                    )
-                    .await
-                    .with_context(|| format!("upload part {part_number}"))?;
+            let res: MemoriesListResponse = api.get_json_streamed("/api/memories", &query).await?;
+            let scanned = res.memories.len();
+            let mut changes: Vec<serde_json::Value> = Vec::new();
+
+            for m in &res.memories {
+                let before = tags_from_value(&m.tags);
+                let after = rewrite_tags(
+                    &before,
+                    from.as_deref().zip(to.as_deref()),
+                    remove.as_deref(),
+                    add.as_deref(),
+                );
+                if after == before {
+                    continue;
+                }
 
-                remaining -= this_size as u64;
                 if !json {
-                    let uploaded = byte_size - remaining;
-                    eprintln!(
-                        "[pajama] Uploaded part {} ({} / {} bytes)",
-                        part_number, uploaded, byte_size
+                    println!(
+                        "{}\t[{}] -> [{}]\t{}",
+                        m.id,
+                        before.join(","),
+                        after.join(","),
+                        m.title
                     );
                 }
-                part_number += 1;
-            }
 
-            let completed: serde_json::Value = api
-                .post_json(
-                    &format!("/api/assets/{}/complete", created.id),
-                    &serde_json::json!({}),
-                )
-                .await
-                .context("complete multipart upload")?;
+                if !dry_run {
+                    let req = UpdateMemoryRequest {
+                        category: &m.category,
+                        source_type: m.source_type.as_deref().unwrap_or("manual"),
+                        title: &m.title,
+                        content: &m.content,
+                        tags: after.clone(),
+                        context: m.context.clone().unwrap_or_else(|| serde_json::json!({})),
+                        confidence: m.confidence,
+                    };
+                    let _res: serde_json::Value = api
+                        .put_json(&format!("/api/memories/{}", m.id), &req)
+                        .await
+                        .with_context(|| format!("update memory {}", m.id))?;
+                }
 
-            if json {
-                println!("{}", serde_json::to_string_pretty(&completed)?);
-            } else {
-                println!("{}", created.id);
+                changes.push(serde_json::json!({
+                    "id": m.id,
+                    "before": before,
+                    "after": after,
+                }));
             }
-        }
-    }
 
-    Ok(())
+            if output::structured(json) {
+                output::print_structured(&serde_json::json!({
+                    "dry_run": dry_run,
+                    "scanned": scanned,
+                    "changed": changes.len(),
+                    "changes": changes,
+                }))?;
+                return Ok(());
+            }
+
+            println!("scanned  {}", scanned);
+            println!(
+                "changed  {}{}",
+                changes.len(),
+                if dry_run { " (dry run)" } else { "" }
+            );
+            if scanned as u32 >= MEMORIES_LIST_MAX {
+                log_warn!(
+                    "scan hit the {MEMORIES_LIST_MAX}-row list cap; re-run to process the rest."
+                );
+            }
+        }
+    }
+    Ok(())
 }
 
-async fn handle_evolve(api: ApiClient, cmd: EvolveCmd) -> Result<()> {
+async fn handle_assets(api: ApiClient, cfg: &config::Config, cmd: AssetsCmd) -> Result<()> {
     match cmd {
-        EvolveCmd::Policy { project_id, json } => {
-            let query = vec![("project_id", project_id)];
-            let res: serde_json::Value =
-                api.get_json("/api/evolve/retrieval-policy", &query).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
+        AssetsCmd::Get {
+            ids,
+            download_url: true,
+            expires,
+            json,
+            ..
+        } => {
+            let [id] = ids.as_slice() else {
+                return Err(anyhow!("--download-url takes a single asset id"));
+            };
+            let mut body = serde_json::json!({});
+            if let Some(secs) = expires {
+                body["expires_in"] = serde_json::json!(secs);
             }
-
-            let recommendation = res
-                .get("recommendation")
-                .cloned()
-                .unwrap_or(serde_json::Value::Null);
-            if recommendation.is_null() {
-                println!("no retrieval policy found for project");
+            let res: serde_json::Value = api
+                .post_json(&format!("/api/assets/{id}/download-url"), &body)
+                .await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
-
-            let arm_id = recommendation
-                .get("arm_id")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let memory_mode = recommendation
-                .get("memory_mode")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let retrieval_mode = recommendation
-                .get("retrieval_mode")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let source = recommendation
-                .get("source")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let selected_at = recommendation
-                .get("selected_at")
+            let url = res
+                .get("url")
                 .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let confidence = recommendation
-                .get("confidence")
-                .and_then(|v| v.as_f64())
-                .unwrap_or(-1.0);
-
-            println!("arm_id         {}", arm_id);
-            println!("memory_mode    {}", memory_mode);
-            println!("retrieval_mode {}", retrieval_mode);
-            println!("source         {}", source);
-            println!("selected_at    {}", selected_at);
-            if confidence >= 0.0 {
-                println!("confidence     {:.6}", confidence);
+                .ok_or_else(|| anyhow!("server response has no url"))?;
+            println!("{url}");
+            if let Some(at) = res.get("expires_at").and_then(|v| v.as_str()) {
+                log_info!("Expires at {at}.");
             }
         }
-        EvolveCmd::ArenaLatest { project_id, json } => {
-            let mut query: Vec<(&str, String)> = vec![];
-            if let Some(v) = project_id {
-                if !v.trim().is_empty() {
-                    query.push(("project_id", v));
+        AssetsCmd::Get {
+            ids,
+            output_template,
+            only_id,
+            json,
+            ..
+        } => {
+            let template = output::parse_output_template(output_template.as_deref(), ASSET_FIELDS)?;
+            let [id] = ids.as_slice() else {
+                let fetched = fetch_each::<serde_json::Value>(&api, "/api/assets", ids).await;
+                if only_id || output::structured(json) || template.is_some() {
+                    return print_fetched(&fetched, only_id, template.as_ref());
                 }
+                for (id, res) in &fetched {
+                    match res {
+                        Ok(asset) => {
+                            println!("==> {id} <==");
+                            output::print_structured(asset)?;
+                        }
+                        Err(err) => log_warn!("{id}: {err:#}"),
+                    }
+                }
+                return fetch_failures(&fetched);
+            };
+            let res: serde_json::Value = api.get_json(&format!("/api/assets/{id}"), &[]).await?;
+            if only_id {
+                return print_only_id(&res);
             }
-
-            let res: serde_json::Value = api
-                .get_json("/api/evolve/memory-arena/latest", &query)
-                .await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
-
-            let latest = res
-                .get("latest")
-                .cloned()
-                .unwrap_or(serde_json::Value::Null);
-            if latest.is_null() {
-                println!("no arena snapshot found");
-                return Ok(());
+            if let Some(template) = &template {
+                return output::print_template(&res, template);
             }
-
-            let created_at = latest
-                .get("created_at")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            let arena = latest
-                .get("arena")
-                .cloned()
-                .unwrap_or(serde_json::Value::Null);
-            let selected_next = arena
-                .get("selected_next")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let winner_current = arena
-                .get("winner_current")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let winner_bandit = arena
-                .get("winner_bandit")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let episodes_total = arena
-                .get("dataset")
-                .and_then(|d| d.get("episodes_total"))
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-
-            println!("created_at      {}", created_at);
-            println!("selected_next   {}", selected_next);
-            println!("winner_current  {}", winner_current);
-            println!("winner_bandit   {}", winner_bandit);
-            println!("episodes_total  {}", episodes_total);
+            output::print_structured(&res)?;
         }
-        EvolveCmd::ArenaRun {
+        AssetsCmd::List {
             project_id,
-            closed_only,
-            limit_sessions,
-            limit_episodes,
-            memory_limit,
-            document_limit,
+            memory_id,
+            status,
+            status_filter,
+            limit,
+            offset,
+            no_cache,
+            raw_query,
+            meta_filter,
+            orphans,
+            fields,
+            output_template,
+            fail_if_empty,
+            only_ids,
+            watch,
+            interval,
+            no_color,
             json,
         } => {
-            let payload = serde_json::json!({
-                "project_id": project_id,
-                "include_open_sessions": !closed_only,
-                "limit_sessions": limit_sessions,
-                "limit_episodes": limit_episodes,
-                "memory_limit": memory_limit,
-                "document_limit": document_limit
-            });
-
-            let res: serde_json::Value = api
-                .post_json("/api/evolve/memory-arena/run", &payload)
-                .await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
+            let fields = output::parse_fields(fields.as_deref(), ASSET_FIELDS)?;
+            let template = output::parse_output_template(output_template.as_deref(), ASSET_FIELDS)?;
+            let limit = effective_limit(limit, ASSETS_LIST_MAX);
+            let mut query = page_query(limit, offset);
+            if let Some(v) = project_id {
+                query.push(("project_id", v));
+            }
+            if let Some(v) = memory_id {
+                query.push(("memory_id", v));
+            }
+            // A single --status-filter value is the same as --status, so the server applies it.
+            let status = match status_filter.as_slice() {
+                [only] => Some(only.clone()),
+                _ => status,
+            };
+            if let Some(v) = status {
+                query.push(("status", v));
+            }
+            if orphans {
+                query.push(("include_memory_links", "true".to_string()));
+            }
+            merge_raw_query(&mut query, &raw_query);
+
+            use std::io::IsTerminal;
+            let view = AssetListView {
+                structured: output::structured(json) && !only_ids,
+                fail_if_empty,
+                only_ids,
+                limit,
+                fields,
+                template,
+                color: color_enabled(no_color),
+            };
+            loop {
+                let mut res: AssetsListResponse = if no_cache {
+                    api.get_json_streamed("/api/assets", &query).await?
+                } else {
+                    api.get_json_cached("/api/assets", &query).await?
+                };
+                ensure_offset_applied(offset, res.meta.as_ref())?;
+                if status_filter.len() > 1 {
+                    res.assets.retain(|a| status_filter.contains(&a.status));
+                }
+                if !meta_filter.is_empty() {
+                    res.assets
+                        .retain(|a| metadata_matches(a.metadata.as_ref(), &meta_filter));
+                }
+                if orphans {
+                    if res.assets.iter().any(|a| a.linked_memory_count.is_none()) {
+                        return Err(anyhow!(
+                            "the server did not return memory link counts; --orphans needs include_memory_links support"
+                        ));
+                    }
+                    res.assets.retain(|a| a.linked_memory_count == Some(0));
+                }
+                if watch && std::io::stdout().is_terminal() {
+                    // Clear the screen and move the cursor home before each redraw.
+                    print!("\x1b[2J\x1b[H");
+                }
+                print_asset_list(&res, &view)?;
+                if !watch {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(interval)).await;
             }
-
-            let arena = res.get("arena").cloned().unwrap_or(serde_json::Value::Null);
-            let winner_current = arena
-                .get("winner_current")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let selected_next = arena
-                .get("selected_next")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let episodes_total = arena
-                .get("dataset")
-                .and_then(|d| d.get("episodes_total"))
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let sessions_considered = arena
-                .get("dataset")
-                .and_then(|d| d.get("sessions_considered"))
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-
-            println!("winner_current      {}", winner_current);
-            println!("selected_next       {}", selected_next);
-            println!("sessions_considered {}", sessions_considered);
-            println!("episodes_total      {}", episodes_total);
         }
-        EvolveCmd::ArenaIterate {
+        AssetsCmd::Count {
             project_id,
-            iterations,
-            time_budget_ms,
-            closed_only,
-            continue_when_empty,
-            limit_sessions,
-            limit_episodes,
-            memory_limit,
-            document_limit,
+            memory_id,
+            status,
             json,
         } => {
-            let payload = serde_json::json!({
-                "project_id": project_id,
-                "iterations": iterations,
-                "time_budget_ms": time_budget_ms,
-                "include_open_sessions": !closed_only,
-                "stop_when_no_episodes": !continue_when_empty,
-                "limit_sessions": limit_sessions,
-                "limit_episodes": limit_episodes,
-                "memory_limit": memory_limit,
-                "document_limit": document_limit
-            });
-
-            let res: serde_json::Value = api
-                .post_json("/api/evolve/memory-arena/iterate", &payload)
-                .await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
+            let mut query: Vec<(&str, String)> = vec![("limit", ASSETS_LIST_MAX.to_string())];
+            if let Some(v) = project_id {
+                query.push(("project_id", v));
+            }
+            if let Some(v) = memory_id {
+                query.push(("memory_id", v));
+            }
+            if let Some(v) = status {
+                query.push(("status", v));
             }
 
-            let batch = res.get("batch").cloned().unwrap_or(serde_json::Value::Null);
-            let requested = batch
-                .get("requested_iterations")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let completed = batch
-                .get("completed_iterations")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let elapsed_ms = batch
-                .get("elapsed_ms")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let stopped = batch
-                .get("stopped_reason")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-
-            let best_arm = batch
-                .get("average_scores")
+            let res: serde_json::Value = api.get_json_streamed("/api/assets", &query).await?;
+            let count = res
+                .get("assets")
                 .and_then(|v| v.as_array())
-                .and_then(|arr| arr.first())
-                .cloned()
-                .unwrap_or(serde_json::Value::Null);
-            let best_arm_id = best_arm
-                .get("arm_id")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let best_score = best_arm
-                .get("avg_score")
-                .and_then(|v| v.as_f64())
-                .unwrap_or(0.0);
-
-            println!("requested_iterations {}", requested);
-            println!("completed_iterations {}", completed);
-            println!("elapsed_ms           {}", elapsed_ms);
-            println!("stopped_reason       {}", stopped);
-            println!("best_arm             {} ({:.6})", best_arm_id, best_score);
+                .map_or(0, |a| a.len());
+            print_count(count, ASSETS_LIST_MAX, json)?;
         }
-        EvolveCmd::ArenaCampaign {
+        AssetsCmd::Stats {
             project_id,
-            iterations_per_project,
-            max_projects,
-            time_budget_ms,
-            closed_only,
-            continue_when_empty,
-            persist_each_iteration,
-            limit_sessions,
-            limit_episodes,
-            memory_limit,
-            document_limit,
+            status,
             json,
         } => {
-            let project_ids: Vec<String> = project_id
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let mut query: Vec<(&str, String)> = vec![
+                ("limit", ASSETS_LIST_MAX.to_string()),
+                ("project_id", project_id.clone()),
+            ];
+            if let Some(v) = status {
+                query.push(("status", v));
+            }
+            query.push(("include_memory_links", "true".to_string()));
+
+            let res: AssetsListResponse = api.get_json_streamed("/api/assets", &query).await?;
+            let total = res.assets.len();
+            let total_bytes: u64 = res.assets.iter().map(|a| a.byte_size).sum();
+            // `None` when the server doesn't report link counts.
+            let orphaned = res
+                .assets
+                .iter()
+                .try_fold((0u64, 0u64), |(count, bytes), a| {
+                    Some(match a.linked_memory_count? {
+                        0 => (count + 1, bytes + a.byte_size),
+                        _ => (count, bytes),
+                    })
+                });
+            let mut by_status: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+            let mut by_type: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+            for a in &res.assets {
+                let e = by_status.entry(a.status.clone()).or_default();
+                e.0 += 1;
+                e.1 += a.byte_size;
+                let e = by_type.entry(a.content_type.clone()).or_default();
+                e.0 += 1;
+                e.1 += a.byte_size;
+            }
+            let capped = total as u32 >= ASSETS_LIST_MAX;
+
+            if output::structured(json) {
+                let to_json = |m: &BTreeMap<String, (u64, u64)>| -> serde_json::Value {
+                    m.iter()
+                        .map(|(k, (count, bytes))| {
+                            (
+                                k.clone(),
+                                serde_json::json!({ "count": count, "bytes": bytes }),
+                            )
+                        })
+                        .collect::<serde_json::Map<String, serde_json::Value>>()
+                        .into()
+                };
+                output::print_structured(&serde_json::json!({
+                    "project_id": project_id,
+                    "total_assets": total,
+                    "total_bytes": total_bytes,
+                    "by_status": to_json(&by_status),
+                    "by_content_type": to_json(&by_type),
+                    "orphaned": orphaned.map(|(count, bytes)| serde_json::json!({ "count": count, "bytes": bytes })),
+                    "truncated": capped,
+                }))?;
+                return Ok(());
+            }
+
+            println!("project_id    {}", project_id);
+            println!("total_assets  {}", total);
+            println!("total_size    {}", format_bytes(total_bytes));
+            if let Some((count, bytes)) = orphaned {
+                println!("orphaned      {}\t{}", count, format_bytes(bytes));
+            }
+            println!("by_status");
+            for (k, (count, bytes)) in &by_status {
+                println!("  {}\t{}\t{}", k, count, format_bytes(*bytes));
+            }
+            println!("by_content_type");
+            for (k, (count, bytes)) in &by_type {
+                println!("  {}\t{}\t{}", k, count, format_bytes(*bytes));
+            }
+            if capped {
+                log_warn!("totals cover the newest {ASSETS_LIST_MAX} assets only (list cap).");
+            }
+        }
+        AssetsCmd::Wait {
+            id,
+            wait_timeout,
+            verbose,
+        } => {
+            let status = wait_for_asset(&api, &id, wait_timeout, verbose).await?;
+            println!("{id}\t{status}");
+        }
+        AssetsCmd::Complete { id, sha256, json } => {
+            let body = match &sha256 {
+                Some(sum) => serde_json::json!({ "sha256": sum }),
+                None => serde_json::json!({}),
+            };
+            let completed = complete_upload(&api, &id, &body).await?;
+            if output::structured(json) {
+                output::print_structured(&completed)?;
+            } else {
+                let status = completed
+                    .get("status")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("ready");
+                println!("{id}\t{status}");
+            }
+        }
+        AssetsCmd::Exists { id } => {
+            let size = head_asset_object(&api, &id).await?;
+            match size {
+                Some(n) => println!("{}\t{} bytes", id, n),
+                None => println!("{}", id),
+            }
+        }
+        AssetsCmd::Rename {
+            id,
+            name,
+            set_meta,
+            json,
+        } => {
+            let original_name = match name {
+                Some(n) if n.trim().is_empty() => {
+                    return Err(anyhow!("--name must not be empty"));
+                }
+                n => n.map(|n| n.trim().to_string()),
+            };
+            let metadata = set_meta
                 .into_iter()
-                .map(|v| v.trim().to_string())
-                .filter(|v| !v.is_empty())
+                .map(|(k, v)| (k, serde_json::Value::String(v)))
                 .collect();
-            let payload = serde_json::json!({
-                "project_ids": if project_ids.is_empty() { serde_json::Value::Null } else { serde_json::json!(project_ids) },
-                "iterations_per_project": iterations_per_project,
-                "max_projects": max_projects,
-                "time_budget_ms": time_budget_ms,
-                "include_open_sessions": !closed_only,
-                "stop_when_no_episodes": !continue_when_empty,
-                "persist_each_iteration": persist_each_iteration,
-                "limit_sessions": limit_sessions,
-                "limit_episodes": limit_episodes,
-                "memory_limit": memory_limit,
-                "document_limit": document_limit
-            });
-
+            let req = UpdateAssetRequest {
+                original_name,
+                metadata,
+            };
             let res: serde_json::Value = api
-                .post_json("/api/evolve/memory-arena/campaign", &payload)
+                .patch_json(&format!("/api/assets/{id}"), &req)
+                .await
+                .with_context(|| format!("update asset {id}"))?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+            println!(
+                "{}\t{}",
+                id,
+                res.get("original_name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("-")
+            );
+        }
+        AssetsCmd::Copy {
+            id,
+            to_project,
+            link_memory,
+            json,
+        } => {
+            let req = serde_json::json!({
+                "project_id": to_project,
+                "memory_id": link_memory,
+            });
+            let res = api
+                .raw_post_json(&format!("/api/assets/{id}/copy"), &req)
                 .await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
+            let status = res.status();
+            if matches!(status.as_u16(), 404 | 405 | 413) {
+                let text = res.text().await.unwrap_or_default();
+                // A JSON 404 is a real "asset/project not found"; a plain one means the
+                // server has no copy endpoint. 413: too large to copy in one request.
+                if status.as_u16() == 404 && text.trim_start().starts_with('{') {
+                    return Err(anyhow!("copy failed (HTTP {status}): {text}"));
+                }
+                log_warn!(
+                    "server-side copy unavailable (HTTP {status}); downloading and re-uploading {id}, so the bytes make a round trip through this machine"
+                );
+                return Box::pin(copy_asset_via_download(
+                    api,
+                    cfg,
+                    &id,
+                    to_project,
+                    link_memory,
+                    json,
+                ))
+                .await;
+            }
+            let copied: serde_json::Value = api
+                .parse_json(res)
+                .await
+                .with_context(|| format!("copy asset {id}"))?;
+            if output::structured(json) {
+                output::print_structured(&copied)?;
+            } else {
+                let new_id = copied.get("id").and_then(|v| v.as_str()).unwrap_or("-");
+                log_info!("Copied {id} -> {new_id} (server-side)");
+                println!("{new_id}");
+            }
+        }
+        AssetsCmd::Download {
+            id,
+            out,
+            to_stdout,
+            part_concurrency,
+            sha256,
+            rate_limit,
+        } => {
+            let limiter = rate_limit.map(RateLimiter::new);
+            // Cheap pre-check so a missing/unready asset fails before we create the output file.
+            let total = head_asset_object(&api, &id).await?;
+
+            let out = out.filter(|_| !to_stdout);
+            let path = format!("/api/assets/{id}/object");
+            let ranges = match total {
+                Some(total) if out.is_some() => split_ranges(total, part_concurrency),
+                _ => Vec::new(),
+            };
+            // The first range doubles as the probe: a 200 means the server ignored `Range`
+            // and is sending the whole object, which is then streamed as usual.
+            let mut res = match ranges.first() {
+                Some(&(first, last)) if ranges.len() > 1 => {
+                    api.raw_get_range(&path, first, last).await?
+                }
+                _ => api.raw_get(&path, &[]).await?,
+            };
+            let status = res.status();
+            if !status.is_success() {
+                let text = res.text().await.unwrap_or_default();
+                return Err(anyhow!("download failed (HTTP {status}): {text}"));
+            }
+            let ranged = ranges.len() > 1 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+            if ranges.len() > 1 && !ranged {
+                log_info!("The server doesn't support ranged downloads; using a single stream.");
+            }
+
+            // Bytes go to `<out>.partial` and are renamed over `out` only once complete, so an
+            // existing file is never left half-overwritten.
+            let partial = out.as_deref().map(partial_path);
+            let mut f: Box<dyn AsyncWrite + Unpin + Send> = match &partial {
+                Some(partial) => {
+                    let file = tokio::fs::File::create(partial)
+                        .await
+                        .with_context(|| format!("create {}", partial.display()))?;
+                    if ranged {
+                        // Ranges land at their offsets, so the file needs its full length first.
+                        file.set_len(total.unwrap_or_default())
+                            .await
+                            .with_context(|| format!("size {}", partial.display()))?;
+                    }
+                    Box::new(file)
+                }
+                None => Box::new(tokio::io::stdout()),
+            };
+            // Human progress would mix into the bytes on stdout; JSON events have their own stream.
+            let report = out.is_some() || progress::enabled();
+            let mut downloaded: u64 = 0;
+            let mut last_reported: u64 = 0;
+            let transfer = async {
+                if let Some(partial) = partial.as_deref().filter(|_| ranged) {
+                    let tracker = std::sync::Arc::new(RangeProgress {
+                        id: id.clone(),
+                        total: total.unwrap_or_default(),
+                        report,
+                        bytes: Default::default(),
+                        reported: Default::default(),
+                    });
+                    download_ranges(
+                        &api,
+                        &path,
+                        res,
+                        partial,
+                        &ranges,
+                        limiter.clone(),
+                        &tracker,
+                    )
+                    .await?;
+                    downloaded = tracker.bytes.load(Ordering::Relaxed);
+                    last_reported = tracker.reported.load(Ordering::Relaxed);
+                    return Ok(());
+                }
+                while let Some(chunk) = res.chunk().await.context("read download chunk")? {
+                    if let Some(limiter) = &limiter {
+                        limiter.take(chunk.len()).await;
+                    }
+                    f.write_all(&chunk).await.context("write download chunk")?;
+                    downloaded += chunk.len() as u64;
+                    if report && downloaded - last_reported >= DOWNLOAD_PROGRESS_STEP {
+                        report_download_progress(&id, downloaded, total);
+                        last_reported = downloaded;
+                    }
+                }
+                f.flush().await.context("flush download output")?;
+                Ok::<(), anyhow::Error>(())
+            };
+            // Downloads are not resumable yet, so a partial file is just noise.
+            let transferred = tokio::select! {
+                res = transfer => res.and_then(|()| match total.filter(|&n| n > 0) {
+                    Some(expected) if downloaded != expected => Err(anyhow!(
+                        "download incomplete: got {downloaded} of {expected} bytes"
+                    )),
+                    _ => Ok(()),
+                }),
+                _ = tokio::signal::ctrl_c() => {
+                    drop(f);
+                    if let Some(partial) = &partial {
+                        let _ = tokio::fs::remove_file(partial).await;
+                        log_info!(
+                            "Interrupted; removed partial file {}",
+                            partial.display()
+                        );
+                    }
+                    std::process::exit(EXIT_INTERRUPTED);
+                }
+            };
+            drop(f);
+            let verified = match (&partial, &sha256) {
+                (Some(partial), Some(expected)) if transferred.is_ok() => {
+                    match sha256_file(partial).await {
+                        Ok(actual) if actual == *expected => Ok(()),
+                        Ok(actual) => Err(anyhow!(
+                            "checksum mismatch: expected sha256 {expected}, got {actual}"
+                        )),
+                        Err(err) => Err(err.context(format!("hash {}", partial.display()))),
+                    }
+                }
+                _ => Ok(()),
+            };
+            if let Err(err) = transferred.and(verified) {
+                if let Some(partial) = &partial {
+                    let _ = tokio::fs::remove_file(partial).await;
+                }
+                return Err(err);
+            }
+            if let (Some(partial), Some(out)) = (&partial, &out) {
+                tokio::fs::rename(partial, out).await.with_context(|| {
+                    format!("rename {} -> {}", partial.display(), out.display())
+                })?;
+            }
+            if report && downloaded != last_reported {
+                report_download_progress(&id, downloaded, total);
+            }
+            progress::emit(serde_json::json!({
+                "event": "done",
+                "op": "download",
+                "id": id,
+                "bytes": downloaded,
+                "path": out,
+            }));
+            if let Some(out) = out {
+                println!("{}", out.display());
+            }
+        }
+        AssetsCmd::DownloadAll {
+            project_id,
+            out_dir,
+            concurrency,
+            rate_limit,
+        } => {
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let query: Vec<(&str, String)> = vec![
+                ("project_id", project_id),
+                ("status", "ready".to_string()),
+                ("limit", ASSETS_LIST_MAX.to_string()),
+            ];
+            let res: AssetsListResponse = api.get_json_streamed("/api/assets", &query).await?;
+            if res.assets.len() as u32 >= ASSETS_LIST_MAX {
+                log_warn!("only the newest {ASSETS_LIST_MAX} assets are listed (list cap).");
+            }
+            tokio::fs::create_dir_all(&out_dir)
+                .await
+                .with_context(|| format!("create {}", out_dir.display()))?;
+
+            let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(concurrency.max(1)));
+            let limiter = rate_limit.map(RateLimiter::new);
+            let mut tasks = tokio::task::JoinSet::new();
+            let mut taken = std::collections::HashSet::new();
+            let (mut skipped, mut downloaded, mut failed) = (0usize, 0usize, 0usize);
+            for a in res.assets {
+                let name = unique_file_name(
+                    &mut taken,
+                    a.original_name.as_deref().unwrap_or(&a.id),
+                    &a.id,
+                );
+                let dest = out_dir.join(&name);
+                let existing = tokio::fs::metadata(&dest).await.ok();
+                if existing.is_some_and(|m| m.is_file() && m.len() == a.byte_size) {
+                    log_info!("skipped\t{}\t(already present)", dest.display());
+                    skipped += 1;
+                    continue;
+                }
+
+                let api = api.clone();
+                let semaphore = semaphore.clone();
+                let limiter = limiter.clone();
+                tasks.spawn(async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    let result =
+                        download_asset_file(&api, &a.id, &dest, a.byte_size, limiter.as_deref())
+                            .await;
+                    (a.id, dest, result)
+                });
+            }
+
+            while let Some(joined) = tasks.join_next().await {
+                let (id, dest, result) = joined.context("download task panicked")?;
+                match result {
+                    Ok(n) => {
+                        progress::emit(serde_json::json!({
+                            "event": "done",
+                            "op": "download",
+                            "id": id,
+                            "bytes": n,
+                            "path": dest,
+                        }));
+                        log_info!("downloaded\t{}\t({})", dest.display(), format_bytes(n));
+                        downloaded += 1;
+                    }
+                    Err(err) => {
+                        progress::emit(serde_json::json!({
+                            "event": "failed",
+                            "op": "download",
+                            "id": id,
+                            "error": format!("{err:#}"),
+                        }));
+                        log_info!("failed\t{id}\t{err:#}");
+                        failed += 1;
+                    }
+                }
+            }
+
+            log_info!(
+                "{downloaded} downloaded, {skipped} skipped, {failed} failed -> {}",
+                out_dir.display()
+            );
+            if failed > 0 {
+                return Err(anyhow!("{failed} asset download(s) failed"));
+            }
+        }
+        AssetsCmd::Upload(args) => {
+            let json = args.json;
+            let uploaded = upload_asset(&api, cfg, args).await?;
+            if output::structured(json) {
+                output::print_structured(&uploaded.output)?;
+            } else {
+                println!("{}", uploaded.id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// One `memories create --attach` file and the asset it became.
+struct Attachment {
+    path: PathBuf,
+    result: Result<String>,
+}
+
+/// Upload each file as an attachment of `memory_id`. A failed file doesn't stop the
+/// others, so every outcome is returned for `report_attachments`.
+async fn attach_files(
+    api: &ApiClient,
+    cfg: &config::Config,
+    project_id: &str,
+    memory_id: &str,
+    files: Vec<PathBuf>,
+) -> Vec<Attachment> {
+    let mut attachments = Vec::with_capacity(files.len());
+    for path in files {
+        let args = UploadArgs {
+            project_id: Some(project_id.to_string()),
+            path: Some(path.clone()),
+            stdin: false,
+            name: None,
+            size: None,
+            memory_id: Some(memory_id.to_string()),
+            content_type: None,
+            // Screenshots and logs are what gets attached; label them by their bytes.
+            sniff: true,
+            meta: Vec::new(),
+            meta_file: None,
+            part_size_mb: None,
+            skip_existing: false,
+            force: false,
+            checksum: Checksum::None,
+            rate_limit: None,
+            wait: false,
+            wait_timeout: 300,
+            json: false,
+        };
+        let result = upload_asset(api, cfg, args)
+            .await
+            .map(|uploaded| uploaded.id);
+        match &result {
+            Ok(id) => log_info!("Attached {} as asset {id}", path.display()),
+            Err(err) => log_warn!("attaching {} failed: {err:#}", path.display()),
+        }
+        attachments.push(Attachment { path, result });
+    }
+    attachments
+}
+
+/// Fail when any attachment failed, naming the memory and what did get attached so a
+/// re-run with `assets upload --memory-id` can finish the job.
+fn report_attachments(memory_id: &str, attachments: &[Attachment]) -> Result<()> {
+    let failed: Vec<String> = attachments
+        .iter()
+        .filter(|a| a.result.is_err())
+        .map(|a| a.path.display().to_string())
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let attached: Vec<String> = attachments
+        .iter()
+        .filter_map(|a| {
+            let id = a.result.as_ref().ok()?;
+            Some(format!("{} ({id})", a.path.display()))
+        })
+        .collect();
+    Err(anyhow!(
+        "memory {memory_id} was created, but {} of {} attachment(s) failed: {}; attached: {}. Retry with `pajama assets upload --memory-id {memory_id} --path <file>`",
+        failed.len(),
+        attachments.len(),
+        failed.join(", "),
+        if attached.is_empty() {
+            "none".to_string()
+        } else {
+            attached.join(", ")
+        }
+    ))
+}
+
+/// An uploaded (or, with `--skip-existing`, matched) asset and what `--json` prints for it.
+struct UploadedAsset {
+    id: String,
+    output: serde_json::Value,
+}
+
+/// The multipart flow behind `assets upload`: create the asset, send the parts, complete.
+/// Progress goes to stderr; the caller prints the result.
+async fn upload_asset(
+    api: &ApiClient,
+    cfg: &config::Config,
+    args: UploadArgs,
+) -> Result<UploadedAsset> {
+    let UploadArgs {
+        project_id,
+        path,
+        stdin,
+        name,
+        size,
+        memory_id,
+        content_type,
+        sniff,
+        meta,
+        meta_file,
+        part_size_mb,
+        skip_existing,
+        force,
+        checksum,
+        rate_limit,
+        wait,
+        wait_timeout,
+        json,
+    } = args;
+    let project_id = resolve_project_id(project_id, cfg)?;
+    let metadata = build_json_object("metadata", None, meta_file.as_deref(), &meta)?;
+    let (byte_size, file_name, mut f): (u64, String, Box<dyn AsyncRead + Unpin + Send>) =
+        match &path {
+            Some(path) if !stdin => {
+                let meta = tokio::fs::metadata(&path)
+                    .await
+                    .with_context(|| format!("stat {}", path.display()))?;
+                if !meta.is_file() {
+                    return Err(anyhow!("path is not a file: {}", path.display()));
+                }
+                let file_name = path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .ok_or_else(|| anyhow!("invalid filename (non-utf8)"))?
+                    .to_string();
+                let f = tokio::fs::File::open(&path)
+                    .await
+                    .with_context(|| format!("open {}", path.display()))?;
+                (meta.len(), file_name, Box::new(f))
+            }
+            _ => {
+                let name = name.unwrap_or_default().trim().to_string();
+                if name.is_empty() {
+                    return Err(anyhow!("--name must not be empty"));
+                }
+                let size = size.unwrap_or(0);
+                if size == 0 {
+                    return Err(anyhow!("--size must be greater than 0"));
+                }
+                (size, name, Box::new(tokio::io::stdin()))
+            }
+        };
+
+    // Known before the upload starts, so it is sent (and recorded) at create.
+    let sha256 = match (&checksum, &path) {
+        (Checksum::Hex(hex), _) => Some(hex.clone()),
+        (_, Some(path)) if skip_existing => Some(
+            sha256_file(path)
+                .await
+                .with_context(|| format!("hash {}", path.display()))?,
+        ),
+        _ => None,
+    };
+    if let Some(sum) = sha256.as_ref().filter(|_| skip_existing) {
+        let query: Vec<(&str, String)> = vec![
+            ("project_id", project_id.clone()),
+            ("sha256", sum.clone()),
+            ("status", "ready".to_string()),
+            ("limit", "1".to_string()),
+        ];
+        let res: AssetsListResponse = api.get_json_streamed("/api/assets", &query).await?;
+        // Servers without the sha256 filter ignore it, so compare the checksum here too.
+        let existing = res.assets.into_iter().find(|a| {
+            a.sha256
+                .as_deref()
+                .is_some_and(|s| s.eq_ignore_ascii_case(sum))
+        });
+        match existing {
+            Some(existing) if !force => {
+                log_info!(
+                    "Skipping upload: {file_name} matches existing asset {} (sha256 {sum})",
+                    existing.id
+                );
+                progress::emit(serde_json::json!({
+                    "event": "done",
+                    "op": "upload",
+                    "id": existing.id,
+                    "skipped": true,
+                }));
+                let output = serde_json::json!({
+                    "id": existing.id,
+                    "sha256": sum,
+                    "skipped": true,
+                });
+                return Ok(UploadedAsset {
+                    id: existing.id,
+                    output,
+                });
+            }
+            Some(existing) => log_info!(
+                "{file_name} matches existing asset {}; uploading anyway (--force)",
+                existing.id
+            ),
+            None => {}
+        }
+    }
+
+    let content_type = match content_type {
+        Some(ct) => ct,
+        None if sniff => {
+            let mut prefix = Vec::with_capacity(SNIFF_BYTES);
+            (&mut f)
+                .take(SNIFF_BYTES as u64)
+                .read_to_end(&mut prefix)
+                .await
+                .with_context(|| format!("read the start of {file_name} for --sniff"))?;
+            let (ct, source) = sniff_content_type(&prefix, &file_name);
+            log_info!("Detected content type {ct} ({source})");
+            // Hand the sniffed bytes back to the part reader.
+            f = Box::new(std::io::Cursor::new(prefix).chain(f));
+            ct
+        }
+        None => "application/octet-stream".to_string(),
+    };
+    let part_size = upload_part_size(&file_name, byte_size, part_size_mb)?;
+
+    let req = CreateAssetRequest {
+        project_id: &project_id,
+        original_name: &file_name,
+        content_type: &content_type,
+        byte_size,
+        part_size,
+        memory_id: memory_id.as_deref(),
+        relation: Some("attachment"),
+        sha256: sha256.as_deref(),
+        metadata,
+    };
+
+    let created: CreateAssetResponse = api.post_json("/api/assets", &req).await?;
+    if json {
+        eprintln!(
+            "{}",
+            output::json_string(&serde_json::json!({
+                "id": created.id,
+                "upload_part_size": created.upload_part_size
+            }))?
+        );
+    } else {
+        log_info!("Asset created: {}", created.id);
+    }
+
+    let mut remaining = byte_size;
+    let mut part_number: u32 = 1;
+    let part_size_u64 = created.upload_part_size;
+    let limiter = rate_limit.map(RateLimiter::new);
+    // `--checksum auto` hashes the parts as they go out instead of reading the file twice.
+    let mut hasher = (checksum == Checksum::Auto && sha256.is_none()).then(Sha256::new);
+
+    let transfer = async {
+        while remaining > 0 {
+            let this_size = std::cmp::min(part_size_u64, remaining) as usize;
+            let mut buf = vec![0u8; this_size];
+            f.read_exact(&mut buf).await.with_context(|| {
+                if stdin {
+                    format!(
+                        "read part {part_number} (stdin ended before --size {byte_size} bytes?)"
+                    )
+                } else {
+                    format!("read part {part_number}")
+                }
+            })?;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&buf);
+            }
+
+            let body = match &limiter {
+                Some(limiter) => throttle::body(buf, limiter.clone()),
+                None => buf.into(),
+            };
+            let _resp: serde_json::Value = api
+                .put_body(
+                    &format!("/api/assets/{}/parts/{}", created.id, part_number),
+                    "application/octet-stream",
+                    body,
+                    this_size as u64,
+                )
+                .await
+                .with_context(|| format!("upload part {part_number}"))?;
+
+            remaining -= this_size as u64;
+            let uploaded = byte_size - remaining;
+            progress::emit(serde_json::json!({
+                "event": "progress",
+                "op": "upload",
+                "id": created.id,
+                "part": part_number,
+                "uploaded": uploaded,
+                "total": byte_size,
+            }));
+            if !json && !progress::enabled() {
+                log_info!(
+                    "Uploaded part {} ({} / {} bytes)",
+                    part_number,
+                    uploaded,
+                    byte_size
+                );
+            }
+            part_number += 1;
+        }
+        if stdin && f.read(&mut [0u8; 1]).await.context("read stdin")? > 0 {
+            return Err(anyhow!("stdin has more than --size {byte_size} bytes"));
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    tokio::select! {
+        res = transfer => res?,
+        _ = tokio::signal::ctrl_c() => {
+            // No resume support yet: abort the multipart upload so the server
+            // doesn't keep a half-written asset around.
+            log_info!("Interrupted; aborting upload of asset {}", created.id);
+            let aborted: Result<serde_json::Value> = api
+                .post_json(
+                    &format!("/api/assets/{}/abort", created.id),
+                    &serde_json::json!({}),
+                )
+                .await;
+            match aborted {
+                Ok(_) => log_info!("Upload aborted; re-run `pajama assets upload` to start over."),
+                Err(err) => log_info!(
+                    "Failed to abort asset {}: {err:#}",
+                    created.id
+                ),
+            }
+            // `memories create --attach` has already created the memory; without its id
+            // there is no way to finish attaching.
+            if let Some(memory_id) = &memory_id {
+                log_warn!(
+                    "memory {memory_id} was kept; attach the file later with `pajama assets upload --memory-id {memory_id} --path <file>`"
+                );
+            }
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    }
+
+    // The server only verifies a hash sent with `complete`; one sent at create is just
+    // recorded. `--checksum none` with `--skip-existing` records the file's hash that way.
+    let requested = hasher
+        .map(|h| format!("{:x}", h.finalize()))
+        .or(sha256)
+        .filter(|_| checksum != Checksum::None);
+    let complete_body = match &requested {
+        Some(sum) => serde_json::json!({ "sha256": sum }),
+        None => serde_json::json!({}),
+    };
+    // A mismatch comes back as HTTP 422 and the server marks the asset failed.
+    let completed = complete_upload(api, &created.id, &complete_body).await?;
+    if let Some(sum) = requested {
+        if completed.get("sha256_verified").and_then(|v| v.as_bool()) == Some(true) {
+            log_info!("Server verified sha256 {sum}");
+        } else {
+            log_warn!("the server did not confirm that it verified sha256 {sum}");
+        }
+    }
+
+    if wait {
+        wait_for_asset(api, &created.id, wait_timeout, !json).await?;
+    }
+    progress::emit(serde_json::json!({
+        "event": "done",
+        "op": "upload",
+        "id": created.id,
+        "bytes": byte_size,
+    }));
+
+    Ok(UploadedAsset {
+        id: created.id,
+        output: completed,
+    })
+}
+
+async fn handle_evolve(api: ApiClient, cfg: &config::Config, cmd: EvolveCmd) -> Result<()> {
+    match cmd {
+        EvolveCmd::Policy { project_id, json } => {
+            let project_id = resolve_project_id(project_id, cfg)?;
+            let query = vec![("project_id", project_id)];
+            let res: serde_json::Value =
+                api.get_json("/api/evolve/retrieval-policy", &query).await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
                 return Ok(());
             }
 
-            let campaign = res
-                .get("campaign")
+            let recommendation = res
+                .get("recommendation")
                 .cloned()
                 .unwrap_or(serde_json::Value::Null);
-            if campaign.is_null() {
-                println!("no campaign result");
+            if recommendation.is_null() {
+                println!("no retrieval policy found for project");
                 return Ok(());
             }
 
-            let requested_projects = campaign
-                .get("requested_projects")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let processed_projects = campaign
-                .get("processed_projects")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let total_completed_iterations = campaign
-                .get("total_completed_iterations")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let elapsed_ms = campaign
-                .get("elapsed_ms")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0);
-            let stopped_reason = campaign
-                .get("stopped_reason")
+            let arm_id = recommendation
+                .get("arm_id")
                 .and_then(|v| v.as_str())
                 .unwrap_or("-");
-
-            let best_project = campaign
-                .get("projects")
-                .and_then(|v| v.as_array())
-                .and_then(|arr| {
-                    arr.iter().max_by(|a, b| {
-                        let sa = a
-                            .get("best_arm_score")
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(-1.0);
-                        let sb = b
-                            .get("best_arm_score")
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(-1.0);
-                        sa.partial_cmp(&sb).unwrap_or(std::cmp::Ordering::Equal)
-                    })
-                })
+            let memory_mode = recommendation
+                .get("memory_mode")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let retrieval_mode = recommendation
+                .get("retrieval_mode")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let source = recommendation
+                .get("source")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let selected_at = recommendation
+                .get("selected_at")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let confidence = recommendation
+                .get("confidence")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(-1.0);
+
+            println!("arm_id         {}", arm_id);
+            println!("memory_mode    {}", memory_mode);
+            println!("retrieval_mode {}", retrieval_mode);
+            println!("source         {}", source);
+            println!("selected_at    {}", selected_at);
+            if confidence >= 0.0 {
+                println!("confidence     {:.6}", confidence);
+            }
+        }
+        EvolveCmd::ArenaLatest { project_id, json } => {
+            let mut query: Vec<(&str, String)> = vec![];
+            if let Some(v) = project_id {
+                if !v.trim().is_empty() {
+                    query.push(("project_id", v));
+                }
+            }
+
+            let res: serde_json::Value = api
+                .get_json("/api/evolve/memory-arena/latest", &query)
+                .await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+
+            let latest = res
+                .get("latest")
                 .cloned()
                 .unwrap_or(serde_json::Value::Null);
+            if latest.is_null() {
+                println!("no arena snapshot found");
+                return Ok(());
+            }
 
-            println!("requested_projects        {}", requested_projects);
-            println!("processed_projects        {}", processed_projects);
-            println!("total_completed_iterations {}", total_completed_iterations);
-            println!("elapsed_ms                {}", elapsed_ms);
-            println!("stopped_reason            {}", stopped_reason);
+            let created_at = latest
+                .get("created_at")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let arena = latest
+                .get("arena")
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+            let selected_next = arena
+                .get("selected_next")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let winner_current = arena
+                .get("winner_current")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let winner_bandit = arena
+                .get("winner_bandit")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let episodes_total = arena
+                .get("dataset")
+                .and_then(|d| d.get("episodes_total"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
 
-            if !best_project.is_null() {
-                let project = best_project
-                    .get("project_id")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("-");
-                let arm = best_project
-                    .get("best_arm_id")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("-");
-                let score = best_project
-                    .get("best_arm_score")
-                    .and_then(|v| v.as_f64())
-                    .unwrap_or(0.0);
-                println!(
-                    "best_project             {} :: {} ({:.6})",
-                    project, arm, score
-                );
+            println!("created_at      {}", created_at);
+            println!("selected_next   {}", selected_next);
+            println!("winner_current  {}", winner_current);
+            println!("winner_bandit   {}", winner_bandit);
+            println!("episodes_total  {}", episodes_total);
+        }
+        EvolveCmd::ArenaRun {
+            project_id,
+            closed_only,
+            limit_sessions,
+            limit_episodes,
+            memory_limit,
+            document_limit,
+            json,
+        } => {
+            let payload = serde_json::json!({
+                "project_id": project_id,
+                "include_open_sessions": !closed_only,
+                "limit_sessions": limit_sessions,
+                "limit_episodes": limit_episodes,
+                "memory_limit": memory_limit,
+                "document_limit": document_limit
+            });
+
+            let res: serde_json::Value = api
+                .post_json("/api/evolve/memory-arena/run", &payload)
+                .await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+
+            let arena = res.get("arena").cloned().unwrap_or(serde_json::Value::Null);
+            let winner_current = arena
+                .get("winner_current")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let selected_next = arena
+                .get("selected_next")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let episodes_total = arena
+                .get("dataset")
+                .and_then(|d| d.get("episodes_total"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let sessions_considered = arena
+                .get("dataset")
+                .and_then(|d| d.get("sessions_considered"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+
+            println!("winner_current      {}", winner_current);
+            println!("selected_next       {}", selected_next);
+            println!("sessions_considered {}", sessions_considered);
+            println!("episodes_total      {}", episodes_total);
+        }
+        EvolveCmd::ArenaIterate {
+            project_id,
+            iterations,
+            time_budget_ms,
+            closed_only,
+            continue_when_empty,
+            limit_sessions,
+            limit_episodes,
+            memory_limit,
+            document_limit,
+            json,
+        } => {
+            let payload = serde_json::json!({
+                "project_id": project_id,
+                "iterations": iterations,
+                "time_budget_ms": time_budget_ms,
+                "include_open_sessions": !closed_only,
+                "stop_when_no_episodes": !continue_when_empty,
+                "limit_sessions": limit_sessions,
+                "limit_episodes": limit_episodes,
+                "memory_limit": memory_limit,
+                "document_limit": document_limit
+            });
+
+            let res: serde_json::Value = api
+                .post_json("/api/evolve/memory-arena/iterate", &payload)
+                .await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
             }
+
+            let batch = res.get("batch").cloned().unwrap_or(serde_json::Value::Null);
+            let requested = batch
+                .get("requested_iterations")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let completed = batch
+                .get("completed_iterations")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let elapsed_ms = batch
+                .get("elapsed_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let stopped = batch
+                .get("stopped_reason")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+
+            let best_arm = batch
+                .get("average_scores")
+                .and_then(|v| v.as_array())
+                .and_then(|arr| arr.first())
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+            let best_arm_id = best_arm
+                .get("arm_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let best_score = best_arm
+                .get("avg_score")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0);
+
+            println!("requested_iterations {}", requested);
+            println!("completed_iterations {}", completed);
+            println!("elapsed_ms           {}", elapsed_ms);
+            println!("stopped_reason       {}", stopped);
+            println!("best_arm             {} ({:.6})", best_arm_id, best_score);
+        }
+        EvolveCmd::ArenaCampaign {
+            project_id,
+            iterations_per_project,
+            max_projects,
+            time_budget_ms,
+            closed_only,
+            continue_when_empty,
+            persist_each_iteration,
+            limit_sessions,
+            limit_episodes,
+            memory_limit,
+            document_limit,
+            json,
+        } => {
+            let project_ids: Vec<String> = project_id
+                .into_iter()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect();
+            let payload = serde_json::json!({
+                "project_ids": if project_ids.is_empty() { serde_json::Value::Null } else { serde_json::json!(project_ids) },
+                "iterations_per_project": iterations_per_project,
+                "max_projects": max_projects,
+                "time_budget_ms": time_budget_ms,
+                "include_open_sessions": !closed_only,
+                "stop_when_no_episodes": !continue_when_empty,
+                "persist_each_iteration": persist_each_iteration,
+                "limit_sessions": limit_sessions,
+                "limit_episodes": limit_episodes,
+                "memory_limit": memory_limit,
+                "document_limit": document_limit
+            });
+
+            let res: serde_json::Value = api
+                .post_json("/api/evolve/memory-arena/campaign", &payload)
+                .await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+
+            let campaign = res
+                .get("campaign")
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+            if campaign.is_null() {
+                println!("no campaign result");
+                return Ok(());
+            }
+
+            let requested_projects = campaign
+                .get("requested_projects")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let processed_projects = campaign
+                .get("processed_projects")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let total_completed_iterations = campaign
+                .get("total_completed_iterations")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let elapsed_ms = campaign
+                .get("elapsed_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            let stopped_reason = campaign
+                .get("stopped_reason")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+
+            let best_project = campaign
+                .get("projects")
+                .and_then(|v| v.as_array())
+                .and_then(|arr| {
+                    arr.iter().max_by(|a, b| {
+                        let sa = a
+                            .get("best_arm_score")
+                            .and_then(|v| v.as_f64())
+                            .unwrap_or(-1.0);
+                        let sb = b
+                            .get("best_arm_score")
+                            .and_then(|v| v.as_f64())
+                            .unwrap_or(-1.0);
+                        sa.partial_cmp(&sb).unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                })
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+
+            println!("requested_projects        {}", requested_projects);
+            println!("processed_projects        {}", processed_projects);
+            println!("total_completed_iterations {}", total_completed_iterations);
+            println!("elapsed_ms                {}", elapsed_ms);
+            println!("stopped_reason            {}", stopped_reason);
+
+            if !best_project.is_null() {
+                let project = best_project
+                    .get("project_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("-");
+                let arm = best_project
+                    .get("best_arm_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("-");
+                let score = best_project
+                    .get("best_arm_score")
+                    .and_then(|v| v.as_f64())
+                    .unwrap_or(0.0);
+                println!(
+                    "best_project             {} :: {} ({:.6})",
+                    project, arm, score
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_agent(api: ApiClient, cmd: AgentCmd) -> Result<()> {
+    match cmd {
+        AgentCmd::Status { json } => {
+            let res: serde_json::Value = api.get_json("/api/agent/status", &[]).await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+
+            let ok = res.get("ok").and_then(|v| v.as_bool()).unwrap_or(false);
+            let service = res.get("service").and_then(|v| v.as_str()).unwrap_or("-");
+            let has_llm = res
+                .get("llm")
+                .and_then(|v| v.get("anthropic_configured"))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let model = res
+                .get("llm")
+                .and_then(|v| v.get("model"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+
+            println!("ok               {}", ok);
+            println!("service          {}", service);
+            println!("anthropic_ready  {}", has_llm);
+            println!("model            {}", model);
+        }
+        AgentCmd::Ask {
+            query,
+            project_id,
+            limit,
+            document_limit,
+            memory_mode,
+            retrieval_mode,
+            dry_run,
+            diagnostics,
+            no_cache,
+            cache_ttl_ms,
+            json,
+        } => {
+            let payload = serde_json::json!({
+                "query": query,
+                "project_id": project_id,
+                "limit": limit,
+                "document_limit": document_limit,
+                "memory_mode": memory_mode,
+                "retrieval_mode": retrieval_mode,
+                "include_assets": true,
+                "include_documents": true,
+                "dry_run": dry_run,
+                "include_diagnostics": diagnostics,
+                "no_cache": no_cache,
+                "cache_ttl_ms": cache_ttl_ms
+            });
+
+            let res: serde_json::Value = api.post_json("/api/agent/ask", &payload).await?;
+            if output::structured(json) {
+                output::print_structured(&res)?;
+                return Ok(());
+            }
+
+            let answer = res.get("answer").and_then(|v| v.as_str()).unwrap_or("");
+            let memory_count = res
+                .get("retrieved")
+                .and_then(|v| v.get("memories"))
+                .and_then(|v| v.as_array())
+                .map(|v| v.len())
+                .unwrap_or(0);
+            let doc_count = res
+                .get("retrieved")
+                .and_then(|v| v.get("documents"))
+                .and_then(|v| v.as_array())
+                .map(|v| v.len())
+                .unwrap_or(0);
+            let mode = res
+                .get("memory_mode")
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+            let provider = res
+                .get("provider")
+                .and_then(|v| v.get("kind"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("-");
+
+            println!("memory_mode      {}", mode);
+            println!("provider         {}", provider);
+            println!(
+                "evidence         {} memories, {} documents",
+                memory_count, doc_count
+            );
+
+            if diagnostics {
+                if let Some(diag) = res.get("diagnostics") {
+                    let cache_enabled = diag
+                        .get("cache")
+                        .and_then(|v| v.get("enabled"))
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false);
+                    let cache_retrieval = diag
+                        .get("cache")
+                        .and_then(|v| v.get("retrieval"))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("-");
+                    let cache_plan = diag
+                        .get("cache")
+                        .and_then(|v| v.get("plan"))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("-");
+                    let cache_arena = diag
+                        .get("cache")
+                        .and_then(|v| v.get("arena"))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("-");
+
+                    let timings = diag.get("timings_ms").unwrap_or(&serde_json::Value::Null);
+                    let as_ms = |k: &str| -> i64 {
+                        timings
+                            .get(k)
+                            .and_then(|v| v.as_i64().or_else(|| v.as_u64().map(|u| u as i64)))
+                            .unwrap_or(-1)
+                    };
+
+                    println!(
+                        "diagnostics      cache={} retrieval={} plan={} arena={} total={}ms retrieval={}ms synthesis={}ms",
+                        cache_enabled,
+                        cache_retrieval,
+                        cache_plan,
+                        cache_arena,
+                        as_ms("total"),
+                        as_ms("retrieval"),
+                        as_ms("synthesis")
+                    );
+                }
+            }
+
+            println!();
+            println!("{}", answer);
+
+            if let Some(notes) = res.get("notes").and_then(|v| v.as_array()) {
+                if !notes.is_empty() {
+                    println!();
+                    println!("notes:");
+                    for n in notes.iter().filter_map(|v| v.as_str()) {
+                        println!("- {}", n);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Known `projects create --engine` values and the starter notes `--seed` creates.
+struct EnginePreset {
+    engine: &'static str,
+    /// Other spellings normalized to `engine` (compared case-insensitively).
+    aliases: &'static [&'static str],
+    /// `(category, title, content)` for each seeded memory.
+    categories: &'static [(&'static str, &'static str, &'static str)],
+}
+
+const ENGINE_PRESETS: &[EnginePreset] = &[
+    EnginePreset {
+        engine: "unreal",
+        aliases: &[
+            "ue",
+            "ue4",
+            "ue5",
+            "unreal-engine",
+            "unrealengine",
+            "unreal engine",
+        ],
+        categories: &[
+            (
+                "build",
+                "Build & cook notes",
+                "Record UBT/UAT flags, cook failures, and packaging fixes here.",
+            ),
+            (
+                "blueprint",
+                "Blueprint conventions",
+                "Record Blueprint patterns, naming rules, and C++/BP boundaries here.",
+            ),
+            (
+                "shader",
+                "Shader & material notes",
+                "Record shader compile issues, material permutations, and PSO caching here.",
+            ),
+            (
+                "perf",
+                "Performance findings",
+                "Record Unreal Insights captures, stat commands, and budget decisions here.",
+            ),
+        ],
+    },
+    EnginePreset {
+        engine: "unity",
+        aliases: &["unity3d", "unity-engine", "unity engine"],
+        categories: &[
+            (
+                "build",
+                "Build pipeline notes",
+                "Record player settings, build targets, and IL2CPP issues here.",
+            ),
+            (
+                "scripting",
+                "Scripting conventions",
+                "Record MonoBehaviour patterns, assembly definitions, and serialization gotchas here.",
+            ),
+            (
+                "assets",
+                "Asset & addressables notes",
+                "Record import settings, addressable groups, and bundle issues here.",
+            ),
+            (
+                "perf",
+                "Performance findings",
+                "Record Profiler captures, GC spikes, and batching decisions here.",
+            ),
+        ],
+    },
+    EnginePreset {
+        engine: "godot",
+        aliases: &["godot3", "godot4", "godot-engine", "godot engine"],
+        categories: &[
+            (
+                "build",
+                "Export notes",
+                "Record export presets, templates, and platform quirks here.",
+            ),
+            (
+                "scripting",
+                "GDScript conventions",
+                "Record node/scene patterns, signals, and autoload usage here.",
+            ),
+            (
+                "perf",
+                "Performance findings",
+                "Record profiler results and rendering budget decisions here.",
+            ),
+        ],
+    },
+    EnginePreset {
+        engine: "custom",
+        aliases: &["other", "none"],
+        categories: &[],
+    },
+];
+
+/// Canonical engine name for `raw` (case-insensitive, aliases included).
+fn normalize_engine(raw: &str) -> Option<&'static str> {
+    let key = raw.trim().to_lowercase();
+    ENGINE_PRESETS
+        .iter()
+        .find(|p| p.engine == key || p.aliases.contains(&key.as_str()))
+        .map(|p| p.engine)
+}
+
+const DOWNLOAD_PROGRESS_STEP: u64 = 16 * 1024 * 1024;
+
+/// Tries of the multipart `complete` call before giving up; the parts stay stored.
+const COMPLETE_ATTEMPTS: u32 = 4;
+
+/// Ceiling for the `assets wait` polling backoff.
+const WAIT_POLL_MAX: Duration = Duration::from_secs(15);
+
+/// Ids a multi-id `get` fetches at once.
+const GET_MANY_CONCURRENCY: usize = 8;
+
+/// Exit code for any other failure reported by `main`.
+const EXIT_FAILURE: i32 = 1;
+
+/// Smallest range `assets download --part-concurrency` splits a file into.
+const MIN_RANGE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Exit code after Ctrl-C (128 + SIGINT, matching shell convention).
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Exit code for `--fail-if-empty` when a list matched nothing.
+const EXIT_EMPTY: i32 = 3;
+
+/// Server-side clamp on `GET /api/memories?limit=`.
+const MEMORIES_LIST_MAX: u32 = 200;
+
+/// Server-side clamp on `GET /api/assets?limit=`.
+const ASSETS_LIST_MAX: u32 = 200;
+
+/// Server-side clamp on `GET /api/memories/search-index?limit=`.
+const SEARCH_INDEX_MAX: u32 = 100;
+
+/// Server-side clamp on `GET /api/memories/timeline?limit=`.
+const TIMELINE_MAX: u32 = 500;
+
+/// Server-side clamp on `GET /api/memories/foresight/active?limit=`.
+const FORESIGHT_MAX: u32 = 300;
+
+/// HEAD the asset object and return its size (from Content-Length) if the server reports one.
+async fn head_asset_object(api: &ApiClient, id: &str) -> Result<Option<u64>> {
+    let res = api.head(&format!("/api/assets/{id}/object"), &[]).await?;
+    let status = res.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Err(anyhow!("asset not found: {id}"));
+    }
+    if !status.is_success() {
+        // HEAD responses carry no body, so the status is all we can report.
+        return Err(anyhow!("asset {id} is not downloadable (HTTP {status})"));
+    }
+
+    Ok(res
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok()))
+}
+
+/// `POST /api/assets/:id/complete`, retried with backoff on connection errors, 408, 429
+/// and 5xx: by this point every part is stored, so a blip shouldn't cost the upload.
+/// If an earlier attempt went through but its response was lost, the retry finds the
+/// asset no longer uploading; a `ready` asset then counts as completed.
+async fn complete_upload(
+    api: &ApiClient,
+    id: &str,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let path = format!("/api/assets/{id}/complete");
+    let mut attempt = 1;
+    loop {
+        let (status, failure) = match api.raw_post_json(&path, body).await {
+            Ok(res) if res.status().is_success() => return api.parse_json(res).await,
+            Ok(res) => {
+                let status = res.status();
+                let err = api
+                    .parse_json::<serde_json::Value>(res)
+                    .await
+                    .err()
+                    .unwrap_or_else(|| anyhow!("HTTP {status}"));
+                (Some(status), err)
+            }
+            Err(err) => (None, err),
+        };
+        match complete_step(status, attempt) {
+            CompleteStep::CheckReady => {
+                return match already_completed(api, id).await {
+                    Some(done) => Ok(done),
+                    None => Err(failure.context("complete multipart upload")),
+                };
+            }
+            CompleteStep::Fail => return Err(failure.context("complete multipart upload")),
+            CompleteStep::GiveUp => {
+                return Err(failure.context(format!(
+                    "complete multipart upload failed {attempt} times; the parts of asset {id} are stored, so finish it later with `pajama assets complete {id}`"
+                )));
+            }
+            CompleteStep::Retry(delay) => {
+                log_warn!(
+                    "completing asset {id} failed ({failure:#}); retrying in {}s",
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// What `complete_upload` does after attempt `attempt` failed.
+#[derive(Debug, PartialEq, Eq)]
+enum CompleteStep {
+    /// A 400 on a retry: a lost earlier attempt may have finalized the asset already.
+    CheckReady,
+    /// Try again after this delay.
+    Retry(Duration),
+    /// Not worth retrying.
+    Fail,
+    /// Transient, but out of attempts.
+    GiveUp,
+}
+
+/// `status` is `None` for a connection error.
+fn complete_step(status: Option<reqwest::StatusCode>, attempt: u32) -> CompleteStep {
+    match status {
+        Some(reqwest::StatusCode::BAD_REQUEST) if attempt > 1 => CompleteStep::CheckReady,
+        Some(status) if !(matches!(status.as_u16(), 408 | 429) || status.is_server_error()) => {
+            CompleteStep::Fail
+        }
+        _ if attempt >= COMPLETE_ATTEMPTS => CompleteStep::GiveUp,
+        _ => CompleteStep::Retry(Duration::from_secs(1 << (attempt - 1))),
+    }
+}
+
+/// The asset row shaped like a `complete` response, when a lost earlier attempt already
+/// finalized it. A current server only marks an asset with a checksum `ready` after
+/// verifying it.
+async fn already_completed(api: &ApiClient, id: &str) -> Option<serde_json::Value> {
+    let asset: serde_json::Value = api.get_json(&format!("/api/assets/{id}"), &[]).await.ok()?;
+    if asset.get("status").and_then(|v| v.as_str()) != Some("ready") {
+        return None;
+    }
+    log_info!("Asset {id} was already completed by an earlier attempt.");
+    let sha256 = asset
+        .get("sha256")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null);
+    Some(serde_json::json!({
+        "ok": true,
+        "asset_id": id,
+        "status": "ready",
+        "byte_size": asset.get("byte_size"),
+        "sha256_verified": sha256.is_string(),
+        "sha256": sha256,
+    }))
+}
+
+/// Poll `GET /api/assets/:id` with backoff until the status is terminal.
+///
+/// Returns the final status on success; failure statuses and timeouts are errors.
+async fn wait_for_asset(
+    api: &ApiClient,
+    id: &str,
+    timeout_secs: u64,
+    verbose: bool,
+) -> Result<String> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout_secs);
+    let mut delay = Duration::from_secs(1);
+    let mut last: Option<String> = None;
+    loop {
+        let asset: serde_json::Value = api
+            .get_json_fresh(&format!("/api/assets/{id}"), &[])
+            .await?;
+        let status = asset
+            .get("status")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+        if verbose && last.as_deref() != Some(status.as_str()) {
+            log_info!("Asset {id}: {status}");
+        }
+        match status.as_str() {
+            "ready" | "completed" => return Ok(status),
+            "failed" | "aborted" | "error" => {
+                return Err(anyhow!("asset {id} ended in status '{status}'"));
+            }
+            _ => {}
+        }
+        last = Some(status);
+
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return Err(anyhow!(
+                "timed out after {timeout_secs}s waiting for asset {id} (status: {})",
+                last.as_deref().unwrap_or("unknown")
+            ));
+        }
+        tokio::time::sleep(delay.min(deadline - now)).await;
+        delay = (delay * 2).min(WAIT_POLL_MAX);
+    }
+}
+
+/// `assets copy` fallback for servers without `/copy`: download into a temp dir, then
+/// upload as a new asset with the same name, content type, and metadata.
+async fn copy_asset_via_download(
+    api: ApiClient,
+    cfg: &config::Config,
+    id: &str,
+    to_project: String,
+    link_memory: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let source: serde_json::Value = api.get_json(&format!("/api/assets/{id}"), &[]).await?;
+    let field = |name: &str| {
+        source
+            .get(name)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    if field("status").as_deref() != Some("ready") {
+        return Err(anyhow!("asset {id} is not ready, so it can't be copied"));
+    }
+    let name = field("original_name")
+        .as_deref()
+        .and_then(|n| Path::new(n).file_name())
+        .and_then(|n| n.to_str())
+        .map_or_else(|| id.to_string(), str::to_string);
+    let byte_size = source
+        .get("byte_size")
+        .and_then(|v| v.as_u64().or_else(|| v.as_str()?.parse().ok()))
+        .unwrap_or(0);
+
+    let dir = std::env::temp_dir().join(format!("pajama-copy-{}", new_uuid()));
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("create {}", dir.display()))?;
+    let result = async {
+        let dest = dir.join(&name);
+        download_asset_file(&api, id, &dest, byte_size, None).await?;
+        let meta_file = dir.join("metadata.json");
+        let metadata = source
+            .get("metadata")
+            .filter(|m| m.is_object())
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        tokio::fs::write(&meta_file, serde_json::to_vec(&metadata)?)
+            .await
+            .with_context(|| format!("write {}", meta_file.display()))?;
+        let upload = AssetsCmd::Upload(UploadArgs {
+            project_id: Some(to_project),
+            path: Some(dest),
+            stdin: false,
+            name: None,
+            size: None,
+            memory_id: link_memory,
+            content_type: field("content_type"),
+            sniff: false,
+            meta: vec![("copied_from".to_string(), id.to_string())],
+            meta_file: Some(meta_file),
+            part_size_mb: None,
+            skip_existing: false,
+            force: false,
+            checksum: Checksum::None,
+            rate_limit: None,
+            wait: false,
+            wait_timeout: 300,
+            json,
+        });
+        Box::pin(handle_assets(api.clone(), cfg, upload)).await
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+/// Stream one asset into `dest` via a `.partial` file, renamed into place once the size checks out.
+async fn download_asset_file(
+    api: &ApiClient,
+    id: &str,
+    dest: &std::path::Path,
+    expected: u64,
+    limiter: Option<&RateLimiter>,
+) -> Result<u64> {
+    let mut res = api
+        .raw_get(&format!("/api/assets/{id}/object"), &[])
+        .await?;
+    let status = res.status();
+    if !status.is_success() {
+        let text = res.text().await.unwrap_or_default();
+        return Err(anyhow!("download failed (HTTP {status}): {text}"));
+    }
+
+    let tmp = partial_path(dest);
+    let mut f = tokio::fs::File::create(&tmp)
+        .await
+        .with_context(|| format!("create {}", tmp.display()))?;
+    let mut written: u64 = 0;
+    let mut last_reported: u64 = 0;
+    let transfer = async {
+        while let Some(chunk) = res.chunk().await.context("read download chunk")? {
+            if let Some(limiter) = limiter {
+                limiter.take(chunk.len()).await;
+            }
+            f.write_all(&chunk).await.context("write download chunk")?;
+            written += chunk.len() as u64;
+            // Only JSON events: interleaved human lines from parallel downloads are noise.
+            if progress::enabled() && written - last_reported >= DOWNLOAD_PROGRESS_STEP {
+                report_download_progress(id, written, Some(expected).filter(|&n| n > 0));
+                last_reported = written;
+            }
+        }
+        f.flush().await.context("flush download")?;
+        if expected > 0 && written != expected {
+            return Err(anyhow!(
+                "download incomplete: got {written} of {expected} bytes"
+            ));
+        }
+        Ok(())
+    };
+    let transferred = transfer.await;
+    drop(f);
+    if let Err(err) = transferred {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(err);
+    }
+    tokio::fs::rename(&tmp, dest)
+        .await
+        .with_context(|| format!("rename {} -> {}", tmp.display(), dest.display()))?;
+    Ok(written)
+}
+
+/// Byte ranges (inclusive) for `assets download --part-concurrency`: up to `parts` of
+/// them, none smaller than `MIN_RANGE_BYTES` unless the file is.
+fn split_ranges(total: u64, parts: usize) -> Vec<(u64, u64)> {
+    let parts = (parts.max(1) as u64).min(total / MIN_RANGE_BYTES).max(1);
+    let size = total.div_ceil(parts).max(1);
+    (0..total)
+        .step_by(size as usize)
+        .map(|first| (first, (first + size).min(total) - 1))
+        .collect()
+}
+
+/// Bytes fetched so far by the ranges of one download, for progress lines.
+struct RangeProgress {
+    id: String,
+    total: u64,
+    report: bool,
+    bytes: std::sync::atomic::AtomicU64,
+    /// Byte count at the last progress line.
+    reported: std::sync::atomic::AtomicU64,
+}
+
+impl RangeProgress {
+    fn add(&self, n: u64) {
+        let now = self.bytes.fetch_add(n, Ordering::Relaxed) + n;
+        let last = self.reported.load(Ordering::Relaxed);
+        // Another task may have reported a larger total since our fetch_add; only move forward.
+        if self.report
+            && now > last
+            && now.saturating_sub(last) >= DOWNLOAD_PROGRESS_STEP
+            && self
+                .reported
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            report_download_progress(&self.id, now, Some(self.total).filter(|&n| n > 0));
+        }
+    }
+}
+
+/// Fetch `ranges` of `path` concurrently into `partial`, which already has the full
+/// length. `first` is the response to the first range, already requested as the probe.
+async fn download_ranges(
+    api: &ApiClient,
+    path: &str,
+    first: reqwest::Response,
+    partial: &Path,
+    ranges: &[(u64, u64)],
+    limiter: Option<std::sync::Arc<RateLimiter>>,
+    progress: &std::sync::Arc<RangeProgress>,
+) -> Result<()> {
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut first = Some(first);
+    for &range in ranges {
+        let res = first.take();
+        let api = api.clone();
+        let path = path.to_string();
+        let partial = partial.to_path_buf();
+        let limiter = limiter.clone();
+        let progress = progress.clone();
+        tasks.spawn(async move {
+            fetch_range(
+                &api,
+                &path,
+                res,
+                &partial,
+                range,
+                limiter.as_deref(),
+                &progress,
+            )
+            .await
+            .with_context(|| format!("bytes {}-{}", range.0, range.1))
+        });
+    }
+    // Returning early drops the set, which aborts the ranges still running.
+    while let Some(joined) = tasks.join_next().await {
+        joined.context("range download task panicked")??;
+    }
+    Ok(())
+}
+
+/// One range of `download_ranges`, written at its offset through its own file handle.
+async fn fetch_range(
+    api: &ApiClient,
+    path: &str,
+    res: Option<reqwest::Response>,
+    partial: &Path,
+    (first, last): (u64, u64),
+    limiter: Option<&RateLimiter>,
+    progress: &RangeProgress,
+) -> Result<()> {
+    let mut res = match res {
+        Some(res) => res,
+        None => api.raw_get_range(path, first, last).await?,
+    };
+    let status = res.status();
+    if status != reqwest::StatusCode::PARTIAL_CONTENT {
+        let text = res.text().await.unwrap_or_default();
+        return Err(anyhow!("download failed (HTTP {status}): {text}"));
+    }
+    // The server may only start where asked; anything else would land at the wrong offset.
+    let start = res
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("bytes "))
+        .and_then(|v| v.split_once('-'))
+        .and_then(|(start, _)| start.parse::<u64>().ok());
+    if start != Some(first) {
+        return Err(anyhow!("server answered a different range than requested"));
+    }
+
+    let mut f = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(partial)
+        .await
+        .with_context(|| format!("open {}", partial.display()))?;
+    f.seek(std::io::SeekFrom::Start(first))
+        .await
+        .with_context(|| format!("seek {}", partial.display()))?;
+    let expected = last - first + 1;
+    let mut written: u64 = 0;
+    while let Some(chunk) = res.chunk().await.context("read download chunk")? {
+        if written + chunk.len() as u64 > expected {
+            return Err(anyhow!("server sent more than the requested range"));
+        }
+        if let Some(limiter) = limiter {
+            limiter.take(chunk.len()).await;
+        }
+        f.write_all(&chunk).await.context("write download chunk")?;
+        written += chunk.len() as u64;
+        progress.add(chunk.len() as u64);
+    }
+    f.flush().await.context("flush download")?;
+    if written != expected {
+        return Err(anyhow!(
+            "range incomplete: got {written} of {expected} bytes"
+        ));
+    }
+    Ok(())
+}
+
+/// Sibling `<dest>.partial` that a download is written to before it is renamed into place.
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut tmp = dest.as_os_str().to_owned();
+    tmp.push(".partial");
+    PathBuf::from(tmp)
+}
+
+/// File name for `download-all`: the base name of `original`, made unique among `taken`
+/// by appending `-<id prefix>` (then a counter) before the extension.
+fn unique_file_name(
+    taken: &mut std::collections::HashSet<String>,
+    original: &str,
+    id: &str,
+) -> String {
+    // Never trust server-provided names to stay inside the output directory.
+    let base = original
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or("")
+        .trim()
+        .trim_start_matches('.');
+    let base = if base.is_empty() { id } else { base };
+    let (stem, ext) = match base.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{ext}")),
+        _ => (base, String::new()),
+    };
+
+    let short_id: String = id.chars().take(8).collect();
+    let mut candidate = base.to_string();
+    let mut n = 1;
+    while !taken.insert(candidate.to_lowercase()) {
+        candidate = if n == 1 {
+            format!("{stem}-{short_id}{ext}")
+        } else {
+            format!("{stem}-{short_id}-{n}{ext}")
+        };
+        n += 1;
+    }
+    candidate
+}
+
+fn report_download_progress(id: &str, downloaded: u64, total: Option<u64>) {
+    if progress::enabled() {
+        progress::emit(serde_json::json!({
+            "event": "progress",
+            "op": "download",
+            "id": id,
+            "downloaded": downloaded,
+            "total": total,
+        }));
+        return;
+    }
+    match total {
+        Some(total) if total > 0 => {
+            let pct = downloaded as f64 * 100.0 / total as f64;
+            log_info!("Downloaded {} / {} bytes ({:.1}%)", downloaded, total, pct);
+        }
+        _ => log_info!("Downloaded {} bytes", downloaded),
+    }
+}
+
+/// Hex SHA-256 of a file, read in chunks so large assets aren't loaded into memory.
+async fn sha256_file(path: &Path) -> Result<String> {
+    let mut f = tokio::fs::File::open(path).await?;
+    let mut h = Sha256::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = f.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        h.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", h.finalize()))
+}
+
+fn parse_tags_csv(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .take(MAX_TAGS)
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// `--limit` as sent to the server: 0 means "as many as allowed" and is never sent
+/// literally, and values above the server's clamp are capped up front.
+fn limit_param(limit: u32, server_max: u32) -> String {
+    effective_limit(limit, server_max).to_string()
+}
+
+/// Numeric form of `limit_param`; warns when an explicit `--limit` gets capped.
+fn effective_limit(limit: u32, server_max: u32) -> u32 {
+    if limit > server_max {
+        log_warn!(
+            "--limit {limit} exceeds the server maximum of {server_max}; using {server_max}."
+        );
+    }
+    if limit == 0 || limit > server_max {
+        server_max
+    } else {
+        limit
+    }
+}
+
+/// `limit` and, past the first page, `offset` for a list request.
+fn page_query(limit: u32, offset: u32) -> Vec<(&'static str, String)> {
+    let mut query = vec![("limit", limit.to_string())];
+    if offset > 0 {
+        query.push(("offset", offset.to_string()));
+    }
+    query
+}
+
+/// Output for the `count` commands: the bare number, or `{"count": N}` with `--json`.
+///
+/// Counts come from a capped list, so hitting `cap` means "at least" and is flagged.
+fn print_count(count: usize, cap: u32, json: bool) -> Result<()> {
+    let truncated = count as u64 >= cap as u64;
+    if output::structured(json) {
+        output::print_structured(&serde_json::json!({
+            "count": count,
+            "truncated": truncated,
+        }))?;
+    } else {
+        println!("{count}");
+    }
+    if truncated {
+        log_warn!("count hit the {cap}-row list cap; the real total may be higher.");
+    }
+    Ok(())
+}
+
+/// Offset paging is only honored when the server echoes `meta.offset` back (older
+/// servers and ranked `--q` searches don't), so a silently ignored offset is an error.
+fn ensure_offset_applied(offset: u32, meta: Option<&serde_json::Value>) -> Result<()> {
+    if offset == 0 {
+        return Ok(());
+    }
+    let applied = meta.and_then(|m| m.get("offset")).and_then(|v| v.as_u64());
+    if applied != Some(offset as u64) {
+        return Err(anyhow!(
+            "the server ignored --offset {offset} (offset paging is not supported for this query, e.g. with --q)"
+        ));
+    }
+    Ok(())
+}
+
+/// Zero-row list result: say so on stderr (unless quiet or structured output) and,
+/// with `--fail-if-empty`, exit with `EXIT_EMPTY`.
+fn report_empty(noun: &str, structured: bool, fail_if_empty: bool) {
+    if !structured && !output::quiet() {
+        eprintln!("No {noun} found.");
+    }
+    if fail_if_empty {
+        std::process::exit(EXIT_EMPTY);
+    }
+}
+
+/// `GET <collection>/<id>` for every id, at most `GET_MANY_CONCURRENCY` at a time. Results
+/// keep the order of `ids`; a failed id doesn't stop the others.
+async fn fetch_each<T: serde::de::DeserializeOwned + Send + 'static>(
+    api: &ApiClient,
+    collection: &str,
+    ids: Vec<String>,
+) -> Vec<(String, Result<T>)> {
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(GET_MANY_CONCURRENCY));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (i, id) in ids.iter().enumerate() {
+        let api = api.clone();
+        let semaphore = semaphore.clone();
+        let path = format!("{collection}/{id}");
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (i, api.get_json::<T>(&path, &[]).await)
+        });
+    }
+    let mut results: Vec<Option<Result<T>>> = ids.iter().map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((i, res)) => results[i] = Some(res),
+            Err(err) => log_warn!("fetch task failed: {err}"),
+        }
+    }
+    ids.into_iter()
+        .zip(results)
+        .map(|(id, res)| {
+            let res = res.unwrap_or_else(|| Err(anyhow!("fetch task failed")));
+            (id, res)
+        })
+        .collect()
+}
+
+/// Machine-readable output of a multi-id `get`: `--only-id` prints the ids that were found,
+/// a template one line per record, and JSON an array with an `{"id","error"}` entry in
+/// place of each record that couldn't be fetched.
+fn print_fetched<T: Serialize>(
+    fetched: &[(String, Result<T>)],
+    only_id: bool,
+    template: Option<&output::LineTemplate>,
+) -> Result<()> {
+    if only_id || template.is_some() {
+        for (id, res) in fetched {
+            match (res, template) {
+                (Ok(_), None) => println!("{id}"),
+                (Ok(row), Some(template)) => output::print_template(row, template)?,
+                (Err(err), _) => log_warn!("{id}: {err:#}"),
+            }
+        }
+        return fetch_failures(fetched);
+    }
+    let mut rows = Vec::with_capacity(fetched.len());
+    for (id, res) in fetched {
+        rows.push(match res {
+            Ok(row) => serde_json::to_value(row)?,
+            Err(err) => serde_json::json!({ "id": id, "error": error_object(err) }),
+        });
+    }
+    output::print_structured(&rows)?;
+    fetch_failures(fetched)
+}
+
+/// The exit status of a multi-id `get`, once everything that could be printed was.
+fn fetch_failures<T>(fetched: &[(String, Result<T>)]) -> Result<()> {
+    match fetched.iter().filter(|(_, res)| res.is_err()).count() {
+        0 => Ok(()),
+        failed => Err(anyhow!(
+            "{failed} of {} ids could not be fetched",
+            fetched.len()
+        )),
+    }
+}
+
+/// `--only-id` for `get` commands that keep the server response as JSON.
+fn print_only_id(res: &serde_json::Value) -> Result<()> {
+    let id = res
+        .get("id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("server response has no id"))?;
+    println!("{id}");
+    Ok(())
+}
+
+/// Row count footer for human list output (stderr, so piped rows stay clean).
+fn report_list_count(noun: &str, shown: usize, limit: u32) {
+    if output::quiet() {
+        return;
+    }
+    if shown as u64 >= limit as u64 {
+        log_info!("Showing {shown} {noun} (limit {limit} reached; more may exist)");
+    } else {
+        log_info!("Showing {shown} {noun}");
+    }
+}
+
+/// How `assets list` renders a fetched page; kept across `--watch` redraws.
+struct AssetListView {
+    structured: bool,
+    fail_if_empty: bool,
+    only_ids: bool,
+    limit: u32,
+    fields: Option<Vec<String>>,
+    template: Option<output::LineTemplate>,
+    color: bool,
+}
+
+fn print_asset_list(res: &AssetsListResponse, view: &AssetListView) -> Result<()> {
+    if view.structured {
+        output::print_structured(res)?;
+    }
+    if res.assets.is_empty() {
+        report_empty("assets", view.structured, view.fail_if_empty);
+    }
+    if view.structured || res.assets.is_empty() {
+        return Ok(());
+    }
+    report_list_count("assets", res.assets.len(), view.limit);
+    if view.only_ids {
+        for a in &res.assets {
+            println!("{}", a.id);
         }
+        return Ok(());
+    }
+    if let Some(template) = &view.template {
+        for a in &res.assets {
+            output::print_template(a, template)?;
+        }
+        return Ok(());
+    }
+    if let Some(fields) = &view.fields {
+        for a in &res.assets {
+            output::print_fields(a, fields)?;
+        }
+        return Ok(());
     }
 
+    for a in &res.assets {
+        // Unknown statuses stay uncolored.
+        let status = match a.status.as_str() {
+            "ready" => paint(view.color, "32", &a.status),
+            "pending" | "uploading" => paint(view.color, "33", &a.status),
+            "failed" => paint(view.color, "31", &a.status),
+            other => other.to_string(),
+        };
+        println!(
+            "{}\t{}\t{status}\t{} bytes\t{}",
+            a.id,
+            a.project_id,
+            a.byte_size,
+            a.original_name.as_deref().unwrap_or(&a.r2_key)
+        );
+    }
     Ok(())
 }
 
-async fn handle_agent(api: ApiClient, cmd: AgentCmd) -> Result<()> {
-    match cmd {
-        AgentCmd::Status { json } => {
-            let res: serde_json::Value = api.get_json("/api/agent/status", &[]).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
-            }
-
-            let ok = res.get("ok").and_then(|v| v.as_bool()).unwrap_or(false);
-            let service = res.get("service").and_then(|v| v.as_str()).unwrap_or("-");
-            let has_llm = res
-                .get("llm")
-                .and_then(|v| v.get("anthropic_configured"))
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            let model = res
-                .get("llm")
-                .and_then(|v| v.get("model"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
+/// Whether human output may use ANSI colors: not with `--no-color`, when stdout isn't a
+/// terminal, or when NO_COLOR is set.
+fn color_enabled(no_color: bool) -> bool {
+    use std::io::IsTerminal;
+    !no_color && std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
 
-            println!("ok               {}", ok);
-            println!("service          {}", service);
-            println!("anthropic_ready  {}", has_llm);
-            println!("model            {}", model);
-        }
-        AgentCmd::Ask {
-            query,
-            project_id,
-            limit,
-            document_limit,
-            memory_mode,
-            retrieval_mode,
-            dry_run,
-            diagnostics,
-            no_cache,
-            cache_ttl_ms,
-            json,
-        } => {
-            let payload = serde_json::json!({
-                "query": query,
-                "project_id": project_id,
-                "limit": limit,
-                "document_limit": document_limit,
-                "memory_mode": memory_mode,
-                "retrieval_mode": retrieval_mode,
-                "include_assets": true,
-                "include_documents": true,
-                "dry_run": dry_run,
-                "include_diagnostics": diagnostics,
-                "no_cache": no_cache,
-                "cache_ttl_ms": cache_ttl_ms
-            });
+/// Wrap `text` in the SGR color `code` when `color` is on.
+fn paint(color: bool, code: &str, text: &str) -> String {
+    if color {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
 
-            let res: serde_json::Value = api.post_json("/api/agent/ask", &payload).await?;
-            if json {
-                println!("{}", serde_json::to_string_pretty(&res)?);
-                return Ok(());
-            }
+/// Stable hash of the normalized dedupe key (case-folded, whitespace collapsed).
+fn dedupe_hash(m: &MemoryRow, by: DedupeKey) -> String {
+    let norm = |s: &str| {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let key = match by {
+        DedupeKey::Content => norm(&m.content),
+        DedupeKey::Title => norm(&m.title),
+        DedupeKey::Both => format!("{}\n{}", norm(&m.title), norm(&m.content)),
+    };
+    let mut h = Sha256::new();
+    h.update(key.as_bytes());
+    format!("{:x}", h.finalize())
+}
 
-            let answer = res.get("answer").and_then(|v| v.as_str()).unwrap_or("");
-            let memory_count = res
-                .get("retrieved")
-                .and_then(|v| v.get("memories"))
-                .and_then(|v| v.as_array())
-                .map(|v| v.len())
-                .unwrap_or(0);
-            let doc_count = res
-                .get("retrieved")
-                .and_then(|v| v.get("documents"))
-                .and_then(|v| v.as_array())
-                .map(|v| v.len())
-                .unwrap_or(0);
-            let mode = res
-                .get("memory_mode")
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
-            let provider = res
-                .get("provider")
-                .and_then(|v| v.get("kind"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("-");
+/// Tags as returned for a memory: normally a JSON array of strings, but older rows may
+/// hold a JSON-encoded array or a comma-separated string. Trimmed, empties dropped.
+fn tags_from_value(v: &serde_json::Value) -> Vec<String> {
+    match v {
+        serde_json::Value::Array(arr) => arr
+            .iter()
+            .filter_map(|t| match t {
+                serde_json::Value::String(s) => Some(s.trim().to_string()),
+                serde_json::Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .filter(|t| !t.is_empty())
+            .collect(),
+        serde_json::Value::String(s) => match serde_json::from_str::<serde_json::Value>(s) {
+            Ok(inner @ serde_json::Value::Array(_)) => tags_from_value(&inner),
+            _ => parse_tags_csv(s),
+        },
+        _ => Vec::new(),
+    }
+}
 
-            println!("memory_mode      {}", mode);
-            println!("provider         {}", provider);
-            println!(
-                "evidence         {} memories, {} documents",
-                memory_count, doc_count
-            );
+/// Apply rename, then remove, then add; keeps order and drops duplicates.
+fn rewrite_tags(
+    tags: &[String],
+    rename: Option<(&str, &str)>,
+    remove: Option<&str>,
+    add: Option<&str>,
+) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(tags.len() + 1);
+    for t in tags {
+        let t = match rename {
+            Some((from, to)) if t == from => to,
+            _ => t.as_str(),
+        };
+        if Some(t) == remove || out.iter().any(|o| o == t) {
+            continue;
+        }
+        out.push(t.to_string());
+    }
+    if let Some(a) = add.filter(|a| !out.iter().any(|o| o == a)) {
+        out.push(a.to_string());
+    }
+    out
+}
 
-            if diagnostics {
-                if let Some(diag) = res.get("diagnostics") {
-                    let cache_enabled = diag
-                        .get("cache")
-                        .and_then(|v| v.get("enabled"))
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false);
-                    let cache_retrieval = diag
-                        .get("cache")
-                        .and_then(|v| v.get("retrieval"))
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("-");
-                    let cache_plan = diag
-                        .get("cache")
-                        .and_then(|v| v.get("plan"))
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("-");
-                    let cache_arena = diag
-                        .get("cache")
-                        .and_then(|v| v.get("arena"))
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("-");
+/// Random (version 4) UUID.
+fn new_uuid() -> String {
+    let mut b = rand::random::<[u8; 16]>();
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex: String = b.iter().map(|x| format!("{x:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
 
-                    let timings = diag.get("timings_ms").unwrap_or(&serde_json::Value::Null);
-                    let as_ms = |k: &str| -> i64 {
-                        timings
-                            .get(k)
-                            .and_then(|v| v.as_i64().or_else(|| v.as_u64().map(|u| u as i64)))
-                            .unwrap_or(-1)
-                    };
+/// Human layout of one memory: title, a metadata line, then the content.
+fn memory_text(m: &MemoryGetResponse) -> String {
+    let mut meta = format!(
+        "[{}] conf={:.2} source={}",
+        m.category,
+        m.confidence,
+        m.source_type.as_deref().unwrap_or("-")
+    );
+    let tags = tags_from_value(&m.tags);
+    if !tags.is_empty() {
+        meta.push_str(&format!(" tags={}", tags.join(",")));
+    }
+    if let Some(session) = m.session_id.as_deref() {
+        meta.push_str(&format!(" session={session}"));
+    }
+    if let Some(version) = m.version {
+        meta.push_str(&format!(" version={version}"));
+    }
+    format!("{}\n{}\n\n{}\n", m.title, meta, m.content)
+}
 
-                    println!(
-                        "diagnostics      cache={} retrieval={} plan={} arena={} total={}ms retrieval={}ms synthesis={}ms",
-                        cache_enabled,
-                        cache_retrieval,
-                        cache_plan,
-                        cache_arena,
-                        as_ms("total"),
-                        as_ms("retrieval"),
-                        as_ms("synthesis")
-                    );
-                }
-            }
+/// The error for a rejected conditional update (`If-Match` no longer matches).
+fn memory_changed(id: &str, read: i64, now: Option<i64>) -> anyhow::Error {
+    let now = now.map(|v| format!(", now {v}")).unwrap_or_default();
+    anyhow!(
+        "memory {id} changed since you read it (version {read}{now}); re-read it with `pajama memories get {id}` or pass --force to overwrite"
+    )
+}
 
-            println!();
-            println!("{}", answer);
+fn format_bytes(n: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if n < 1024 {
+        return format!("{n} B");
+    }
+    let mut v = n as f64 / 1024.0;
+    let mut unit = 0;
+    while v >= 1024.0 && unit < UNITS.len() - 1 {
+        v /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", v, UNITS[unit])
+}
 
-            if let Some(notes) = res.get("notes").and_then(|v| v.as_array()) {
-                if !notes.is_empty() {
-                    println!();
-                    println!("notes:");
-                    for n in notes.iter().filter_map(|v| v.as_str()) {
-                        println!("- {}", n);
-                    }
+/// Normalize text that ends up in tab-separated listings: tabs/newlines become a
+/// space, other control and zero-width characters are dropped, and the result is
+/// trimmed. The flag reports whether anything besides trimming was changed.
+fn sanitize_line(s: &str) -> (String, bool) {
+    let mut out = String::with_capacity(s.len());
+    let mut scrubbed = false;
+    for c in s.chars() {
+        match c {
+            '\t' | '\n' | '\r' => {
+                scrubbed = true;
+                if !out.ends_with(' ') {
+                    out.push(' ');
                 }
             }
+            '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => scrubbed = true,
+            c if c.is_control() => scrubbed = true,
+            c => out.push(c),
         }
     }
+    (out.trim().to_string(), scrubbed)
+}
 
-    Ok(())
+fn clean_line_arg(flag: &str, value: &str, strict: bool) -> Result<String> {
+    let (clean, scrubbed) = sanitize_line(value);
+    if scrubbed {
+        if strict {
+            return Err(anyhow!(
+                "{flag} contains control or zero-width characters: {value:?}"
+            ));
+        }
+        log_warn!("removed control/zero-width characters from {flag}");
+    }
+    Ok(clean)
 }
 
-fn parse_tags_csv(s: &str) -> Vec<String> {
-    s.split(',')
-        .map(|t| t.trim())
-        .filter(|t| !t.is_empty())
-        .take(32)
-        .map(|t| t.to_string())
-        .collect()
+/// Ask a yes/no question on stderr. Refuses when stdin is not a terminal so scripts
+/// must opt in with `--yes`.
+fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "{prompt} (stdin is not a terminal; pass --yes to confirm)"
+        ));
+    }
+    eprint!("{prompt} [y/N] ");
+    std::io::stderr().flush().ok();
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("read confirmation")?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
 }
 
 fn clamp_0_1(v: f64) -> f64 {
@@ -1893,17 +7160,13 @@ fn div_ceil(n: u64, d: u64) -> u64 {
     (n + d - 1) / d
 }
 
+/// Multipart limits shared with the server: at most 10k parts of 5..95 MB each.
+const MAX_UPLOAD_PARTS: u64 = 10_000;
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_PART_SIZE: u64 = 95 * 1024 * 1024;
+
 fn clamp_part_size(bytes: u64) -> u64 {
-    const MB: u64 = 1024 * 1024;
-    const MIN: u64 = 5 * MB;
-    const MAX: u64 = 95 * MB;
-    if bytes < MIN {
-        MIN
-    } else if bytes > MAX {
-        MAX
-    } else {
-        bytes
-    }
+    bytes.clamp(MIN_PART_SIZE, MAX_PART_SIZE)
 }
 
 fn choose_part_size(file_size: u64, part_size_mb: Option<u32>) -> u64 {
@@ -1933,6 +7196,24 @@ fn choose_part_size(file_size: u64, part_size_mb: Option<u32>) -> u64 {
     clamp_part_size(part)
 }
 
+/// `choose_part_size`, raised so the upload fits in `MAX_UPLOAD_PARTS`; files too big
+/// for that even at `MAX_PART_SIZE` are rejected.
+fn upload_part_size(file_name: &str, byte_size: u64, part_size_mb: Option<u32>) -> Result<u64> {
+    let part_size = choose_part_size(byte_size, part_size_mb);
+    if div_ceil(byte_size, part_size) <= MAX_UPLOAD_PARTS {
+        return Ok(part_size);
+    }
+    let min_part = div_ceil(byte_size, MAX_UPLOAD_PARTS);
+    if min_part > MAX_PART_SIZE {
+        return Err(anyhow!(
+            "{file_name} is {byte_size} bytes; the largest supported asset is {} bytes ({MAX_UPLOAD_PARTS} parts of {} MB)",
+            MAX_UPLOAD_PARTS * MAX_PART_SIZE,
+            MAX_PART_SIZE / (1024 * 1024)
+        ));
+    }
+    Ok(clamp_part_size(min_part))
+}
+
 fn de_u64_from_str_or_int<'de, D>(deserializer: D) -> Result<u64, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -1974,3 +7255,499 @@ where
 
     deserializer.deserialize_any(V)
 }
+
+/// `de_u64_from_str_or_int` for optional fields; null reads as `None`.
+fn de_opt_u64_from_str_or_int<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Count(#[serde(deserialize_with = "de_u64_from_str_or_int")] u64);
+
+    Ok(Option::<Count>::deserialize(deserializer)?.map(|Count(n)| n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_stats_sums_rows_per_category() {
+        let project = serde_json::json!({
+            "id": "p1",
+            "memory_stats": [
+                { "category": "bug", "count": 3 },
+                { "category": "pattern", "count": 2 },
+                { "category": "bug", "count": 1 },
+            ],
+        });
+        let stats = memory_stats(&project);
+        assert_eq!(stats.get("bug"), Some(&4));
+        assert_eq!(stats.get("pattern"), Some(&2));
+        assert_eq!(stats.values().sum::<u64>(), 6);
+        assert!(memory_stats(&serde_json::json!({ "id": "p1" })).is_empty());
+    }
+
+    #[test]
+    fn limit_param_clamps_to_server_max() {
+        assert_eq!(limit_param(0, 200), "200");
+        assert_eq!(limit_param(50, 200), "50");
+        assert_eq!(limit_param(200, 200), "200");
+        assert_eq!(limit_param(500, 200), "200");
+    }
+
+    #[test]
+    fn counts_deserialize_from_strings_and_integers() {
+        let read = |v: serde_json::Value| de_u64_from_str_or_int(v).map_err(|e| e.to_string());
+        assert_eq!(read(serde_json::json!(42)), Ok(42));
+        assert_eq!(read(serde_json::json!("42")), Ok(42));
+        assert!(read(serde_json::json!(-1)).is_err());
+        assert!(read(serde_json::json!("4x")).is_err());
+        assert!(read(serde_json::json!(null)).is_err());
+    }
+
+    #[test]
+    fn page_query_sends_offset_only_past_the_first_page() {
+        let pairs = |limit, offset| {
+            page_query(limit, offset)
+                .into_iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pairs(50, 0), ["limit=50"]);
+        assert_eq!(pairs(50, 100), ["limit=50", "offset=100"]);
+        let limit = effective_limit(0, ASSETS_LIST_MAX);
+        assert_eq!(
+            pairs(limit, 1),
+            [format!("limit={ASSETS_LIST_MAX}"), "offset=1".to_string()]
+        );
+    }
+
+    #[test]
+    fn uploads_up_to_the_part_cap_fit() {
+        let max = MAX_UPLOAD_PARTS * MAX_PART_SIZE;
+        assert_eq!(choose_part_size(max, None), MAX_PART_SIZE);
+        assert_eq!(
+            upload_part_size("big.bin", max, None).unwrap(),
+            MAX_PART_SIZE
+        );
+        // A small --part-size is raised rather than overflowing the part count.
+        assert_eq!(
+            upload_part_size("big.bin", max - 1, Some(5)).unwrap(),
+            MAX_PART_SIZE
+        );
+    }
+
+    #[test]
+    fn uploads_past_the_part_cap_are_rejected() {
+        let max = MAX_UPLOAD_PARTS * MAX_PART_SIZE;
+        let err = upload_part_size("big.bin", max + 1, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "big.bin is {} bytes; the largest supported asset is {max} bytes (10000 parts of 95 MB)",
+                max + 1
+            )
+        );
+    }
+
+    #[test]
+    fn env_vars_expand_and_unset_ones_stay_literal() {
+        let lookup = |name: &str| (name == "HOME_DIR").then(|| "/home/me".to_string());
+        let expand = |s| expand_env_vars_with(s, lookup);
+        assert_eq!(expand("$HOME_DIR/x"), Ok("/home/me/x".into()));
+        assert_eq!(expand("${HOME_DIR}x"), Ok("/home/mex".into()));
+        assert_eq!(expand("a/$UNSET/b"), Ok("a/$UNSET/b".into()));
+        assert_eq!(expand("a/${UNSET}b"), Ok("a/${UNSET}b".into()));
+        assert_eq!(expand("price$5.txt"), Ok("price$5.txt".into()));
+        assert_eq!(expand("a $ b $-c $"), Ok("a $ b $-c $".into()));
+        assert!(expand("${HOME_DIR").is_err());
+        assert!(expand("${}").is_err());
+    }
+
+    fn saved_token(token: &str) -> config::Config {
+        config::Config {
+            access_token: Some(token.to_string()),
+            ..config::Config::default()
+        }
+    }
+
+    #[test]
+    fn token_file_outranks_env_and_saved_token() {
+        let dir = config::test_dir("token-file");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("token");
+        std::fs::write(&file, "  from-file\n").unwrap();
+        let over = TokenOverride {
+            value: None,
+            file: Some(file.clone()),
+        };
+        let env = |_: &str| Some("from-env".to_string());
+        let token = resolve_token_with(&over, &saved_token("saved"), false, env).unwrap();
+        assert_eq!(token, "from-file");
+
+        std::fs::write(&file, "\n").unwrap();
+        let err = resolve_token_with(&over, &saved_token("saved"), false, env).unwrap_err();
+        assert!(err.to_string().contains("is empty"), "{err}");
+
+        let over = TokenOverride {
+            value: None,
+            file: Some(dir.join("missing")),
+        };
+        let err = resolve_token_with(&over, &saved_token("saved"), false, env).unwrap_err();
+        assert!(err.to_string().starts_with("read token file"), "{err}");
+    }
+
+    #[test]
+    fn token_precedence_without_a_file() {
+        let env = |_: &str| Some("from-env".to_string());
+        let no_env = |_: &str| None;
+        let flag = TokenOverride {
+            value: Some(" from-flag ".to_string()),
+            file: None,
+        };
+        let saved = saved_token("saved");
+        assert_eq!(
+            resolve_token_with(&flag, &saved, false, env).unwrap(),
+            "from-flag"
+        );
+        // A blank flag value falls through to the next source.
+        let blank = TokenOverride {
+            value: Some("  ".to_string()),
+            file: None,
+        };
+        assert_eq!(
+            resolve_token_with(&blank, &saved, false, env).unwrap(),
+            "from-env"
+        );
+        let none = TokenOverride::default();
+        let blank_env = |_: &str| Some(" ".to_string());
+        assert_eq!(
+            resolve_token_with(&none, &saved, false, blank_env).unwrap(),
+            "saved"
+        );
+        assert!(resolve_token_with(&none, &saved_token(" "), false, no_env).is_err());
+        // `--no-config` never falls back to a saved token.
+        let err = resolve_token_with(&none, &saved, true, no_env).unwrap_err();
+        assert!(err.to_string().contains("--no-config"), "{err}");
+    }
+
+    #[test]
+    fn parse_expiry_units_and_bounds() {
+        assert_eq!(parse_expiry("60"), Ok(60));
+        assert_eq!(parse_expiry("15m"), Ok(900));
+        assert_eq!(parse_expiry("1h"), Ok(3_600));
+        assert_eq!(parse_expiry("7d"), Ok(604_800));
+        assert!(parse_expiry("59s").is_err());
+        assert!(parse_expiry("8d").is_err());
+        assert!(parse_expiry("1w").is_err());
+        assert!(parse_expiry("h").is_err());
+        assert!(parse_expiry("99999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn partial_path_appends_suffix() {
+        assert_eq!(
+            partial_path(Path::new("out/build.zip")),
+            PathBuf::from("out/build.zip.partial")
+        );
+        assert_eq!(
+            partial_path(Path::new("notes")),
+            PathBuf::from("notes.partial")
+        );
+    }
+
+    /// `download_asset_file` of asset `a1` from a server answering with `response`, into a
+    /// `dest` that already holds "old". Returns the result and the directory used.
+    async fn download_into_existing(
+        name: &str,
+        response: wiremock::ResponseTemplate,
+        expected: u64,
+    ) -> (Result<u64>, PathBuf) {
+        use wiremock::matchers::{method, path};
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(method("GET"))
+            .and(path("/api/assets/a1/object"))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+        let api = ApiClient::new(&server.uri(), "t", &ClientOptions::default()).unwrap();
+        let dir = config::test_dir(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("asset.bin"), "old").unwrap();
+        let res = download_asset_file(&api, "a1", &dir.join("asset.bin"), expected, None).await;
+        (res, dir)
+    }
+
+    #[tokio::test]
+    async fn short_download_leaves_dest_untouched() {
+        let body = wiremock::ResponseTemplate::new(200).set_body_bytes(b"12345".to_vec());
+        let (res, dir) = download_into_existing("download-short", body, 20).await;
+        let err = res.unwrap_err();
+        assert_eq!(err.to_string(), "download incomplete: got 5 of 20 bytes");
+        assert_eq!(std::fs::read(dir.join("asset.bin")).unwrap(), b"old");
+        assert!(!dir.join("asset.bin.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn interrupted_download_leaves_dest_untouched() {
+        use tokio::io::AsyncReadExt;
+        // A server that promises 100 bytes, sends 5 and hangs up.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = conn.read(&mut request).await;
+            let _ = conn
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\n12345")
+                .await;
+        });
+        let api =
+            ApiClient::new(&format!("http://{addr}"), "t", &ClientOptions::default()).unwrap();
+        let dir = config::test_dir("download-cut");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("asset.bin");
+        std::fs::write(&dest, "old").unwrap();
+
+        let err = download_asset_file(&api, "a1", &dest, 100, None)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").starts_with("read download chunk"),
+            "{err:#}"
+        );
+        assert_eq!(std::fs::read(&dest).unwrap(), b"old");
+        assert!(!dir.join("asset.bin.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn complete_download_replaces_dest() {
+        let body = wiremock::ResponseTemplate::new(200).set_body_bytes(b"12345".to_vec());
+        let (res, dir) = download_into_existing("download-ok", body, 5).await;
+        assert_eq!(res.unwrap(), 5);
+        assert_eq!(std::fs::read(dir.join("asset.bin")).unwrap(), b"12345");
+        assert!(!dir.join("asset.bin.partial").exists());
+    }
+
+    #[test]
+    fn parse_checksum_modes() {
+        let hex = "AB".repeat(32);
+        assert_eq!(parse_checksum("auto"), Ok(Checksum::Auto));
+        assert_eq!(parse_checksum(" none "), Ok(Checksum::None));
+        assert_eq!(parse_checksum(&hex), Ok(Checksum::Hex("ab".repeat(32))));
+        assert!(parse_checksum("md5").is_err());
+        assert!(parse_checksum(&hex[1..]).is_err());
+    }
+
+    #[test]
+    fn complete_step_retries_transient_failures() {
+        use reqwest::StatusCode;
+        assert_eq!(
+            complete_step(None, 1),
+            CompleteStep::Retry(Duration::from_secs(1))
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::SERVICE_UNAVAILABLE), 2),
+            CompleteStep::Retry(Duration::from_secs(2))
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::TOO_MANY_REQUESTS), 3),
+            CompleteStep::Retry(Duration::from_secs(4))
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::REQUEST_TIMEOUT), COMPLETE_ATTEMPTS),
+            CompleteStep::GiveUp
+        );
+        assert_eq!(complete_step(None, COMPLETE_ATTEMPTS), CompleteStep::GiveUp);
+    }
+
+    #[test]
+    fn complete_step_checks_for_a_lost_success() {
+        use reqwest::StatusCode;
+        // The first attempt's 400 is a real error; on a retry it may mean "no longer uploading".
+        assert_eq!(
+            complete_step(Some(StatusCode::BAD_REQUEST), 1),
+            CompleteStep::Fail
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::BAD_REQUEST), 2),
+            CompleteStep::CheckReady
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::BAD_REQUEST), COMPLETE_ATTEMPTS),
+            CompleteStep::CheckReady
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::NOT_FOUND), 2),
+            CompleteStep::Fail
+        );
+        assert_eq!(
+            complete_step(Some(StatusCode::UNPROCESSABLE_ENTITY), 1),
+            CompleteStep::Fail
+        );
+    }
+
+    #[test]
+    fn parse_sha256_normalizes_hex() {
+        let hex = "AB".repeat(32);
+        assert_eq!(parse_sha256(&format!(" {hex} ")), Ok("ab".repeat(32)));
+        assert!(parse_sha256(&hex[1..]).is_err());
+        assert!(parse_sha256(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn fetch_failures_counts_errors() {
+        let ok: Vec<(String, Result<u32>)> = vec![("a".into(), Ok(1)), ("b".into(), Ok(2))];
+        assert!(fetch_failures(&ok).is_ok());
+        let mixed: Vec<(String, Result<u32>)> =
+            vec![("a".into(), Ok(1)), ("b".into(), Err(anyhow!("HTTP 404")))];
+        assert_eq!(
+            fetch_failures(&mixed).unwrap_err().to_string(),
+            "1 of 2 ids could not be fetched"
+        );
+        // Printing what was found still reports the failure.
+        assert!(print_fetched(&mixed, true, None).is_err());
+        assert!(print_fetched(&ok, true, None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn fetch_each_keeps_order_and_per_id_errors() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        for id in ["a1", "a3"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/assets/{id}")))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": id })),
+                )
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/api/assets/a2"))
+            .respond_with(
+                ResponseTemplate::new(404)
+                    .set_body_json(serde_json::json!({ "error": "Asset not found" })),
+            )
+            .mount(&server)
+            .await;
+        let api = ApiClient::new(&server.uri(), "t", &ClientOptions::default()).unwrap();
+
+        let ids = vec!["a1".to_string(), "a2".to_string(), "a3".to_string()];
+        let fetched = fetch_each::<serde_json::Value>(&api, "/api/assets", ids).await;
+        let order: Vec<&str> = fetched.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(order, ["a1", "a2", "a3"]);
+        assert_eq!(fetched[0].1.as_ref().unwrap()["id"], "a1");
+        assert!(
+            fetched[1]
+                .1
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("Asset not found")
+        );
+        assert_eq!(fetched[2].1.as_ref().unwrap()["id"], "a3");
+        assert_eq!(
+            fetch_failures(&fetched).unwrap_err().to_string(),
+            "1 of 3 ids could not be fetched"
+        );
+    }
+
+    fn memory(json: serde_json::Value, strict: bool) -> Result<(String, serde_json::Value)> {
+        memory_from_json(
+            &json.to_string(),
+            Some("p-flag".into()),
+            &config::Config::default(),
+            strict,
+        )
+    }
+
+    #[test]
+    fn memory_from_json_normalizes_the_body() {
+        let tags: Vec<String> = (0..40).map(|i| format!("t{i}")).collect();
+        let (project, body) = memory(
+            serde_json::json!({
+                "category": "bug",
+                "title": "Crash\ton\u{200B} exit",
+                "content": "...",
+                "tags": tags,
+                "confidence": 3.0,
+            }),
+            false,
+        )
+        .unwrap();
+        assert_eq!(project, "p-flag");
+        assert_eq!(body["project_id"], "p-flag");
+        assert_eq!(body["title"], "Crash on exit");
+        assert_eq!(body["tags"].as_array().unwrap().len(), MAX_TAGS);
+        assert_eq!(body["confidence"], 1.0);
+
+        let (project, _) = memory(
+            serde_json::json!({
+                "project_id": " p-body ",
+                "category": "bug",
+                "title": "t",
+                "content": "c",
+            }),
+            false,
+        )
+        .unwrap();
+        assert_eq!(project, "p-body");
+    }
+
+    #[test]
+    fn memory_from_json_rejects_invalid_bodies() {
+        let base = serde_json::json!({ "category": "bug", "title": "t", "content": "c" });
+        let with = |key: &str, value: serde_json::Value| {
+            let mut body = base.clone();
+            body[key] = value;
+            body
+        };
+        assert!(memory(base.clone(), true).is_ok());
+        assert!(memory(serde_json::json!([]), false).is_err());
+        assert!(memory(with("title", " ".into()), false).is_err());
+        assert!(memory(with("content", serde_json::Value::Null), false).is_err());
+        assert!(memory(with("project_id", 7.into()), false).is_err());
+        assert!(memory(with("source_type", "robot".into()), false).is_err());
+        assert!(memory(with("tags", "a,b".into()), false).is_err());
+        assert!(memory(with("confidence", "high".into()), false).is_err());
+        assert!(memory(with("context", serde_json::json!([1])), false).is_err());
+        // Unknown fields pass through unless --strict.
+        assert!(memory(with("extra", true.into()), false).is_ok());
+        assert!(memory(with("extra", true.into()), true).is_err());
+        // So do scrubbed characters.
+        assert!(memory(with("title", "a\tb".into()), true).is_err());
+    }
+
+    #[test]
+    fn split_ranges_covers_the_file() {
+        for (total, parts) in [
+            (10 * 1024 * 1024, 8),
+            (64 * 1024 * 1024, 4),
+            (64 * 1024 * 1024 + 1, 4),
+            (100, 4),
+            (1, 1),
+        ] {
+            let ranges = split_ranges(total, parts);
+            assert!(!ranges.is_empty() && ranges.len() <= parts);
+            assert_eq!(ranges[0].0, 0);
+            assert_eq!(ranges.last().unwrap().1, total - 1);
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[1].0, pair[0].1 + 1);
+            }
+            if ranges.len() > 1 {
+                assert!(
+                    ranges
+                        .iter()
+                        .all(|(a, b)| b - a + 1 >= MIN_RANGE_BYTES || *b == total - 1)
+                );
+            }
+        }
+        assert_eq!(split_ranges(10 * 1024 * 1024, 8).len(), 2);
+        assert_eq!(split_ranges(100, 4), vec![(0, 99)]);
+        assert_eq!(split_ranges(64 * 1024 * 1024, 0).len(), 1);
+        assert!(split_ranges(0, 4).is_empty());
+    }
+}
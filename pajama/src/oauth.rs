@@ -13,6 +13,9 @@ use tokio::sync::oneshot;
 use tokio::time::timeout;
 use url::Url;
 
+use crate::output::{self, log_info, log_warn};
+use crate::resolve::{self, IpPreference};
+
 #[derive(Debug, Deserialize)]
 pub struct OAuthMetadata {
     #[allow(dead_code)]
@@ -43,11 +46,51 @@ fn oauth_callback_timeout_secs() -> u64 {
         .unwrap_or(900)
 }
 
-pub async fn discover_oauth(api_base_url: &str) -> Result<OAuthMetadata> {
+/// Metadata documents tried in order; OIDC providers often only serve the second.
+const DISCOVERY_PATHS: &[&str] = &[
+    "/.well-known/oauth-authorization-server",
+    "/.well-known/openid-configuration",
+];
+
+/// Plain client for the OAuth endpoints (no `--header` defaults or client certificate).
+fn http_client(ip: Option<IpPreference>) -> Result<reqwest::Client> {
+    resolve::apply(reqwest::Client::builder(), ip, false)
+        .build()
+        .context("build http client")
+}
+
+/// Fetch the OAuth metadata from `metadata_url` when one is configured, otherwise from
+/// the `/.well-known` documents under `api_base_url`.
+pub async fn discover_oauth(
+    api_base_url: &str,
+    metadata_url: Option<&str>,
+    ip: Option<IpPreference>,
+) -> Result<OAuthMetadata> {
+    let client = http_client(ip)?;
+    if let Some(url) = metadata_url {
+        return fetch_oauth_metadata(&client, url)
+            .await
+            .with_context(|| format!("oauth metadata discovery failed: {url}"));
+    }
+
     let base = api_base_url.trim_end_matches('/');
-    let url = format!("{base}/.well-known/oauth-authorization-server");
 
-    let client = reqwest::Client::new();
+    let mut failures = Vec::new();
+    for path in DISCOVERY_PATHS {
+        let url = format!("{base}{path}");
+        match fetch_oauth_metadata(&client, &url).await {
+            Ok(meta) => return Ok(meta),
+            Err(err) => failures.push(format!("  {url}: {err:#}")),
+        }
+    }
+
+    Err(anyhow!(
+        "oauth metadata discovery failed:\n{}",
+        failures.join("\n")
+    ))
+}
+
+async fn fetch_oauth_metadata(client: &reqwest::Client, url: &str) -> Result<OAuthMetadata> {
     let res = client
         .get(url)
         .send()
@@ -62,11 +105,33 @@ pub async fn discover_oauth(api_base_url: &str) -> Result<OAuthMetadata> {
     }
 
     let meta: OAuthMetadata = serde_json::from_str(&text).context("parse oauth metadata json")?;
+    check_endpoint("authorization_endpoint", &meta.authorization_endpoint)?;
+    check_endpoint("token_endpoint", &meta.token_endpoint)?;
+    if let Some(endpoint) = &meta.registration_endpoint {
+        check_endpoint("registration_endpoint", endpoint)?;
+    }
     Ok(meta)
 }
 
-pub async fn register_client(registration_endpoint: &str, client_name: &str) -> Result<String> {
-    let client = reqwest::Client::new();
+/// Metadata endpoints must be absolute http(s) URLs; a relative path would otherwise be
+/// resolved against nothing and fail later with a confusing request error.
+fn check_endpoint(name: &str, value: &str) -> Result<()> {
+    let url = Url::parse(value)
+        .map_err(|err| anyhow!("oauth metadata {name} is not an absolute URL ({err}): {value}"))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow!(
+            "oauth metadata {name} must use http or https: {value}"
+        ));
+    }
+    Ok(())
+}
+
+pub async fn register_client(
+    registration_endpoint: &str,
+    client_name: &str,
+    ip: Option<IpPreference>,
+) -> Result<String> {
+    let client = http_client(ip)?;
     let res = client
         .post(registration_endpoint)
         .header(header::CONTENT_TYPE, "application/json")
@@ -231,24 +296,67 @@ fn html_escape(s: &str) -> String {
         .replace('\'', "&#x27;")
 }
 
+/// The login URL on its own stderr line (easy to copy), or as a `url` field in JSON logs.
+fn show_auth_url(url: &Url) {
+    if output::json_logs() {
+        output::log(
+            output::Level::Info,
+            "login url",
+            &[("url", url.as_str().into())],
+        );
+    } else {
+        eprintln!("{url}");
+    }
+}
+
+/// How login treats an issued token that lacks the expected API key prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixCheck {
+    /// Print a warning and continue (default).
+    Warn,
+    /// Fail the login.
+    Strict,
+    /// Skip the check entirely.
+    Off,
+}
+
+/// The expected API key prefix (from `Config::token_prefix`) and how to enforce it.
+#[derive(Debug, Clone)]
+pub struct TokenCheck {
+    /// An empty prefix disables the check.
+    pub prefix: String,
+    pub mode: PrefixCheck,
+}
+
 pub struct LoginResult {
     pub access_token: String,
     #[allow(dead_code)]
     pub token_type: String,
     #[allow(dead_code)]
     pub expires_in: Option<u64>,
-    #[allow(dead_code)]
     pub scope: Option<String>,
     pub client_id: String,
 }
 
+/// Requested scopes (space-separated, as sent) that `granted` doesn't include, in request order.
+pub fn missing_scopes(requested: &str, granted: &str) -> Vec<String> {
+    let granted: Vec<&str> = granted.split_whitespace().collect();
+    requested
+        .split_whitespace()
+        .filter(|s| !granted.contains(s))
+        .map(str::to_string)
+        .collect()
+}
+
 pub async fn login_oauth_pkce(
     meta: &OAuthMetadata,
-    api_base_url: &str,
     existing_client_id: Option<String>,
     scope: &str,
     no_open: bool,
+    token_check: &TokenCheck,
+    ip: Option<IpPreference>,
 ) -> Result<LoginResult> {
+    let reused_client = existing_client_id.is_some();
     let client_id = if let Some(cid) = existing_client_id {
         cid
     } else {
@@ -256,14 +364,25 @@ pub async fn login_oauth_pkce(
             .registration_endpoint
             .as_deref()
             .ok_or_else(|| anyhow!("oauth server does not expose a registration_endpoint"))?;
-        register_client(reg, "pajama-cli").await?
+        register_client(reg, "pajama-cli", ip).await?
     };
 
-    let listener = TcpListener::bind(("127.0.0.1", 0))
-        .await
-        .context("bind loopback callback server")?;
+    // The browser connects to whatever loopback literal the redirect names, so follow
+    // the same family preference as API requests; IPv4 unless IPv6 is preferred.
+    let loopback = ip.unwrap_or(IpPreference::V4).loopback();
+    let listener = match TcpListener::bind((loopback, 0)).await {
+        Ok(listener) => listener,
+        Err(err) if loopback.is_ipv6() => {
+            log_warn!("could not listen on [::1] ({err}); using 127.0.0.1 for the callback");
+            TcpListener::bind(("127.0.0.1", 0))
+                .await
+                .context("bind loopback callback server")?
+        }
+        Err(err) => return Err(err).context("bind loopback callback server"),
+    };
     let addr = listener.local_addr().context("read callback addr")?;
-    let redirect_uri = format!("http://127.0.0.1:{}/callback", addr.port());
+    // `SocketAddr` display brackets IPv6: http://[::1]:port/callback.
+    let redirect_uri = format!("http://{addr}/callback");
 
     let state = random_base64url(18);
     let verifier = pkce_verifier();
@@ -283,17 +402,17 @@ pub async fn login_oauth_pkce(
     }
 
     if no_open {
-        eprintln!("[pajama] Open this URL in your browser to continue login:");
-        eprintln!("{auth_url}");
+        log_info!("Open this URL in your browser to continue login:");
+        show_auth_url(&auth_url);
     } else {
         match open::that(auth_url.as_str()) {
             Ok(_) => {
-                eprintln!("[pajama] Opening browser for login...");
+                log_info!("Opening browser for login...");
             }
             Err(err) => {
-                eprintln!("[pajama] Failed to open browser: {err}");
-                eprintln!("[pajama] Open this URL manually:");
-                eprintln!("{auth_url}");
+                log_info!("Failed to open browser: {err}");
+                log_info!("Open this URL manually:");
+                show_auth_url(&auth_url);
             }
         }
     }
@@ -301,7 +420,6 @@ pub async fn login_oauth_pkce(
     let code = wait_for_oauth_callback(listener, state).await?;
 
     // Exchange code -> token
-    let client = reqwest::Client::new();
     let form = [
         ("grant_type", "authorization_code"),
         ("code", code.as_str()),
@@ -309,9 +427,41 @@ pub async fn login_oauth_pkce(
         ("code_verifier", verifier.as_str()),
         ("client_id", client_id.as_str()),
     ];
+    let token = exchange_token(
+        &meta.token_endpoint,
+        &form,
+        "exchange oauth code for token",
+        token_check,
+        ip,
+        // A saved registration the server no longer knows fails only here.
+        reused_client.then_some(
+            "the saved OAuth client may have been revoked; run `pajama login --reauthorize` to register a new one",
+        ),
+    )
+    .await?;
+
+    Ok(LoginResult {
+        access_token: token.access_token,
+        token_type: token.token_type,
+        expires_in: token.expires_in,
+        scope: token.scope,
+        client_id,
+    })
+}
 
+/// `invalid_client_hint` is appended when the server rejects the client itself
+/// (`"error": "invalid_client"`).
+async fn exchange_token(
+    token_endpoint: &str,
+    form: &[(&str, &str)],
+    what: &'static str,
+    token_check: &TokenCheck,
+    ip: Option<IpPreference>,
+    invalid_client_hint: Option<&str>,
+) -> Result<TokenResponse> {
+    let client = http_client(ip)?;
     let res = client
-        .post(&meta.token_endpoint)
+        .post(token_endpoint)
         .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
         .body(
             url::form_urlencoded::Serializer::new(String::new())
@@ -320,11 +470,18 @@ pub async fn login_oauth_pkce(
         )
         .send()
         .await
-        .context("exchange oauth code for token")?;
+        .context(what)?;
 
     let status = res.status();
     let text = res.text().await.unwrap_or_default();
     if !status.is_success() {
+        let invalid_client = serde_json::from_str::<serde_json::Value>(&text)
+            .is_ok_and(|v| v.get("error").and_then(|e| e.as_str()) == Some("invalid_client"));
+        if let Some(hint) = invalid_client_hint.filter(|_| invalid_client) {
+            return Err(anyhow!(
+                "token exchange failed (HTTP {status}): {text}; {hint}"
+            ));
+        }
         return Err(anyhow!("token exchange failed (HTTP {status}): {text}"));
     }
 
@@ -336,22 +493,26 @@ pub async fn login_oauth_pkce(
         ));
     }
 
-    // Basic sanity: make sure the token looks like our API keys.
-    if !token.access_token.starts_with("gdm_") {
-        eprintln!(
-            "[pajama] Warning: access_token does not look like a gdm_ API key. Continuing anyway."
-        );
-    }
-
-    // TODO: Add a simple post-login check (e.g., GET /api/projects) once the token is stored.
-    // We keep the OAuth module pure and let the caller do that.
-    let _ = api_base_url;
+    check_token_prefix(&token.access_token, token_check)?;
+    Ok(token)
+}
 
-    Ok(LoginResult {
-        access_token: token.access_token,
-        token_type: token.token_type,
-        expires_in: token.expires_in,
-        scope: token.scope,
-        client_id,
-    })
+/// Basic sanity: make sure the token looks like our API keys (prefix compared case-insensitively).
+pub fn check_token_prefix(token: &str, check: &TokenCheck) -> Result<()> {
+    let prefix = check.prefix.as_str();
+    let matches = prefix.is_empty()
+        || token
+            .get(..prefix.len())
+            .is_some_and(|p| p.eq_ignore_ascii_case(prefix));
+    match check.mode {
+        _ if matches => Ok(()),
+        PrefixCheck::Off => Ok(()),
+        PrefixCheck::Strict => Err(anyhow!(
+            "access_token does not look like a {prefix} API key (rejected by --strict-token)"
+        )),
+        PrefixCheck::Warn => {
+            log_warn!("access_token does not look like a {prefix} API key. Continuing anyway.");
+            Ok(())
+        }
+    }
 }
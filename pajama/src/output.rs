@@ -0,0 +1,377 @@
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Machine-readable output format selected with the global `--format` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+/// Style of stderr log lines, selected with the global `--log-format` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// `[pajama] ...` lines for humans
+    Text,
+    /// One JSON object per line for log pipelines
+    Json,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+/// `--pretty` (true) / `--compact` (false); unset means pretty only on a terminal.
+static PRETTY_JSON: OnceLock<bool> = OnceLock::new();
+static QUIET: AtomicBool = AtomicBool::new(false);
+static JSON_LOGS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_log_format(format: LogFormat) {
+    JSON_LOGS.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Whether stderr events are emitted as JSON (`--log-format json`).
+pub fn json_logs() -> bool {
+    JSON_LOGS.load(Ordering::Relaxed)
+}
+
+/// Emit one stderr event: a `[pajama] ...` line, or with `--log-format json` an object
+/// with `ts` (Unix ms), `level`, `message`, and the given structured `fields`.
+pub fn log(level: Level, message: &str, fields: &[(&str, serde_json::Value)]) {
+    if !json_logs() {
+        match level {
+            Level::Warn => eprintln!("[pajama] Warning: {message}"),
+            Level::Debug | Level::Info => eprintln!("[pajama] {message}"),
+        }
+        return;
+    }
+    let ts = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let level = match level {
+        Level::Debug => "debug",
+        Level::Info => "info",
+        Level::Warn => "warn",
+    };
+    let mut obj = serde_json::Map::new();
+    obj.insert("ts".to_string(), ts.into());
+    obj.insert("level".to_string(), level.into());
+    obj.insert("message".to_string(), message.into());
+    for (k, v) in fields {
+        obj.insert(k.to_string(), v.clone());
+    }
+    eprintln!("{}", serde_json::Value::Object(obj));
+}
+
+/// `format!`-style informational event (see `log`).
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::output::log($crate::output::Level::Info, &format!($($arg)*), &[])
+    };
+}
+
+/// `format!`-style warning event, rendered as `[pajama] Warning: ...` in text mode.
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::output::log($crate::output::Level::Warn, &format!($($arg)*), &[])
+    };
+}
+
+pub(crate) use {log_info, log_warn};
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Global `--quiet`: informational stderr notices should be skipped.
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn set_pretty_json(pretty: bool) {
+    let _ = PRETTY_JSON.set(pretty);
+}
+
+fn pretty_json() -> bool {
+    use std::io::IsTerminal;
+    *PRETTY_JSON.get_or_init(|| std::io::stdout().is_terminal())
+}
+
+/// Serialize JSON output: indented when pretty (see `set_pretty_json`), else one line.
+pub fn json_string<T: Serialize>(value: &T) -> Result<String> {
+    if pretty_json() {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+    .context("serialize json")
+}
+
+/// Whether a command should print structured output: its own `--json`, or a global `--format`.
+pub fn structured(json: bool) -> bool {
+    json || FORMAT.get().is_some()
+}
+
+/// Print a value in the selected format (JSON unless `--format yaml`).
+pub fn print_structured<T: Serialize>(value: &T) -> Result<()> {
+    match FORMAT.get().copied().unwrap_or(Format::Json) {
+        Format::Json => println!("{}", json_string(value)?),
+        // serde_yaml writes multi-line strings (memory content) as block scalars.
+        Format::Yaml => print!(
+            "{}",
+            serde_yaml::to_string(value).context("serialize yaml")?
+        ),
+    }
+    Ok(())
+}
+
+/// Parse a `--fields a,b,c` spec, validating each name against `available`.
+///
+/// Returns `None` when no spec was given so callers keep their default columns.
+pub fn parse_fields(spec: Option<&str>, available: &[&str]) -> Result<Option<Vec<String>>> {
+    let Some(spec) = spec else {
+        return Ok(None);
+    };
+
+    let mut fields = Vec::new();
+    for f in spec.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        if !available.contains(&f) {
+            return Err(anyhow!(
+                "unknown field '{f}' (available: {})",
+                available.join(",")
+            ));
+        }
+        fields.push(f.to_string());
+    }
+    if fields.is_empty() {
+        return Err(anyhow!(
+            "--fields is empty (available: {})",
+            available.join(",")
+        ));
+    }
+    Ok(Some(fields))
+}
+
+/// Print the selected fields of a row as one tab-separated line.
+pub fn print_fields<T: Serialize>(row: &T, fields: &[String]) -> Result<()> {
+    let v = serde_json::to_value(row).context("serialize row")?;
+    let cells: Vec<String> = fields
+        .iter()
+        .map(|f| cell(v.get(f).unwrap_or(&serde_json::Value::Null)))
+        .collect();
+    println!("{}", cells.join("\t"));
+    Ok(())
+}
+
+/// A parsed `--output-template` such as `"{id} {title} ({confidence})"`.
+///
+/// `{field}` is replaced with the row's value (formatted like a `--fields` cell, but a
+/// missing or null field prints as an empty string); `{{` and `}}` are literal braces.
+#[derive(Debug)]
+pub struct LineTemplate {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug)]
+enum Piece {
+    Text(String),
+    Field(String),
+}
+
+/// Parse a template up front, validating each placeholder against `available`.
+pub fn parse_output_template(
+    spec: Option<&str>,
+    available: &[&str],
+) -> Result<Option<LineTemplate>> {
+    let Some(spec) = spec else {
+        return Ok(None);
+    };
+
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = spec.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => {
+                            return Err(anyhow!("--output-template has an unclosed '{{'"));
+                        }
+                    }
+                }
+                let name = name.trim();
+                if !available.contains(&name) {
+                    return Err(anyhow!(
+                        "unknown field '{name}' in --output-template (available: {})",
+                        available.join(",")
+                    ));
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Field(name.to_string()));
+            }
+            '}' => {
+                return Err(anyhow!(
+                    "--output-template has an unmatched '}}' (write '}}}}' for a literal brace)"
+                ));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(Some(LineTemplate { pieces }))
+}
+
+/// Print one row through a `LineTemplate`.
+pub fn print_template<T: Serialize>(row: &T, template: &LineTemplate) -> Result<()> {
+    println!("{}", render_template(row, template)?);
+    Ok(())
+}
+
+fn render_template<T: Serialize>(row: &T, template: &LineTemplate) -> Result<String> {
+    let v = serde_json::to_value(row).context("serialize row")?;
+    let mut line = String::new();
+    for piece in &template.pieces {
+        match piece {
+            Piece::Text(t) => line.push_str(t),
+            Piece::Field(f) => match v.get(f) {
+                None | Some(serde_json::Value::Null) => {}
+                Some(value) => line.push_str(&cell(value)),
+            },
+        }
+    }
+    Ok(line)
+}
+
+/// A JSON value as plain text: strings unquoted, arrays comma-joined, null as `-`.
+pub fn cell(v: &serde_json::Value) -> String {
+    match v {
+        serde_json::Value::Null => "-".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(items) => items.iter().map(cell).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+/// Show long human output through a pager, like git: `PAJAMA_PAGER`, then `PAGER`,
+/// then `less`/`more`. Prints directly with `no_pager`, when stdout is not a terminal,
+/// or when the pager is set to an empty string or `cat`.
+pub fn page(text: &str, no_pager: bool) -> Result<()> {
+    use std::io::{IsTerminal, Write};
+
+    if no_pager || !std::io::stdout().is_terminal() {
+        print!("{text}");
+        return Ok(());
+    }
+
+    let configured = std::env::var("PAJAMA_PAGER")
+        .or_else(|_| std::env::var("PAGER"))
+        .ok();
+    let candidates: Vec<String> = match configured {
+        Some(cmd) => vec![cmd],
+        None => vec!["less".to_string(), "more".to_string()],
+    };
+
+    for cmd in candidates {
+        let mut parts = cmd.split_whitespace();
+        let Some(program) = parts.next() else {
+            break;
+        };
+        if program == "cat" {
+            break;
+        }
+        let mut command = std::process::Command::new(program);
+        command.args(parts).stdin(std::process::Stdio::piped());
+        if std::env::var_os("LESS").is_none() {
+            // Quit on one screen, keep colors, don't clear the screen on exit.
+            command.env("LESS", "FRX");
+        }
+        let Ok(mut child) = command.spawn() else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            match stdin.write_all(text.as_bytes()) {
+                // The user quit the pager before reading everything.
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+                other => other.context("write to pager")?,
+            }
+        }
+        child.wait().context("wait for pager")?;
+        return Ok(());
+    }
+
+    print!("{text}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["id", "title", "tags", "confidence"];
+
+    fn render(spec: &str, row: serde_json::Value) -> String {
+        let template = parse_output_template(Some(spec), FIELDS).unwrap().unwrap();
+        render_template(&row, &template).unwrap()
+    }
+
+    #[test]
+    fn output_template_fills_fields() {
+        let row = serde_json::json!({
+            "id": "m1",
+            "title": "Crash on exit",
+            "tags": ["ue5", "pie"],
+            "confidence": 0.5,
+        });
+        assert_eq!(
+            render("{id} {title} [{tags}] ({ confidence })", row),
+            "m1 Crash on exit [ue5,pie] (0.5)"
+        );
+    }
+
+    #[test]
+    fn output_template_prints_missing_and_null_as_empty() {
+        let row = serde_json::json!({ "id": "m1", "title": null });
+        assert_eq!(render("{id}|{title}|{tags}", row), "m1||");
+    }
+
+    #[test]
+    fn output_template_escapes_braces() {
+        let row = serde_json::json!({ "id": "m1" });
+        assert_eq!(render("{{{id}}}", row), "{m1}");
+    }
+
+    #[test]
+    fn output_template_rejects_bad_specs() {
+        assert!(parse_output_template(None, FIELDS).unwrap().is_none());
+        assert!(parse_output_template(Some("{nope}"), FIELDS).is_err());
+        assert!(parse_output_template(Some("{id"), FIELDS).is_err());
+        assert!(parse_output_template(Some("id}"), FIELDS).is_err());
+    }
+}
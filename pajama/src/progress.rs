@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+
+/// Where `--progress-json` events go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Stderr,
+    /// An already open file descriptor, e.g. 3 from `3>progress.jsonl` or a GUI's pipe.
+    Fd(u32),
+}
+
+static SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// `--progress-json` values: `stderr`, or a file descriptor number.
+pub fn parse_target(s: &str) -> Result<Target, String> {
+    match s.trim() {
+        "stderr" => Ok(Target::Stderr),
+        other => match other.parse::<u32>() {
+            Ok(0 | 1) => Err(format!(
+                "progress events can't share fd {other} with the command's input/output; use stderr or 3 and up"
+            )),
+            Ok(fd) => Ok(Target::Fd(fd)),
+            Err(_) => Err(format!(
+                "expected stderr or a file descriptor number, got {s:?}"
+            )),
+        },
+    }
+}
+
+/// Open the event stream. Must be called before the first `emit`; later calls are ignored.
+pub fn init(target: Target) -> Result<()> {
+    let sink: Box<dyn Write + Send> = match target {
+        Target::Stderr => Box::new(std::io::stderr()),
+        Target::Fd(fd) => Box::new(open_fd(fd)?),
+    };
+    let _ = SINK.set(Mutex::new(sink));
+    Ok(())
+}
+
+/// Reopen an inherited descriptor through `/dev/fd` (Linux, macOS, BSDs).
+#[cfg(unix)]
+fn open_fd(fd: u32) -> Result<fs::File> {
+    let path = format!("/dev/fd/{fd}");
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("open --progress-json fd {fd} (is it open for writing?)"))
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: u32) -> Result<fs::File> {
+    Err(anyhow::anyhow!(
+        "--progress-json {fd}: file descriptors are only supported on Unix; use stderr"
+    ))
+}
+
+/// Whether `--progress-json` is on; human progress lines are skipped when it is.
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// Write one event as a JSON line. A closed stream (the GUI went away) is ignored so
+/// the transfer itself still finishes.
+pub fn emit(event: serde_json::Value) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = writeln!(sink, "{event}").and_then(|_| sink.flush());
+}
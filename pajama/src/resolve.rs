@@ -0,0 +1,73 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::timing;
+
+/// Address family to try first (`--prefer-ipv4` / `--prefer-ipv6`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpPreference {
+    V4,
+    V6,
+}
+
+impl IpPreference {
+    fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            Self::V4 => addr.is_ipv4(),
+            Self::V6 => addr.is_ipv6(),
+        }
+    }
+
+    /// Loopback address for the OAuth callback server.
+    pub fn loopback(self) -> IpAddr {
+        match self {
+            Self::V4 => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Self::V6 => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        }
+    }
+}
+
+/// System resolver that can move a preferred family to the front and reports lookup
+/// time to `--trace-timing`. The connector tries the family of the first address and
+/// only races the other one after its happy eyeballs delay, so the preference is not a
+/// filter: hosts with addresses of only one family still work.
+struct SystemResolver {
+    prefer: Option<IpPreference>,
+}
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let prefer = self.prefer;
+        Box::pin(async move {
+            let started = Instant::now();
+            let mut addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            timing::record_dns(started.elapsed());
+            if let Some(prefer) = prefer {
+                // Stable sort keeps the system's order within each family.
+                addrs.sort_by_key(|a| !prefer.matches(a));
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Apply `preference` to a client and, for `trace_timing`, time lookups and new
+/// connections. With neither, resolution and connecting stay reqwest's defaults.
+pub fn apply(
+    builder: reqwest::ClientBuilder,
+    preference: Option<IpPreference>,
+    trace_timing: bool,
+) -> reqwest::ClientBuilder {
+    let builder = if trace_timing {
+        builder.connector_layer(timing::ConnectTiming)
+    } else {
+        builder
+    };
+    if preference.is_none() && !trace_timing {
+        return builder;
+    }
+    builder.dns_resolver(Arc::new(SystemResolver { prefer: preference }))
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::config_dir;
+
+/// A memory skeleton loaded from `<config dir>/templates/<name>.md`.
+///
+/// Optional frontmatter (between `---` lines) sets defaults for `category`, `tags`
+/// (comma-separated), and `confidence`; the rest of the file is the content body.
+#[derive(Debug, Default)]
+pub struct Template {
+    pub category: Option<String>,
+    pub tags: Option<String>,
+    pub confidence: Option<f64>,
+    pub body: String,
+}
+
+pub fn templates_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("templates"))
+}
+
+pub fn list_templates() -> Result<Vec<String>> {
+    let dir = templates_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+            names.push(stem.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub fn template_path(name: &str) -> Result<PathBuf> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(anyhow!("invalid template name: {name:?}"));
+    }
+    Ok(templates_dir()?.join(format!("{name}.md")))
+}
+
+pub fn read_template_text(name: &str) -> Result<String> {
+    let path = template_path(name)?;
+    if !path.exists() {
+        return Err(anyhow!(
+            "template '{name}' not found (expected {})",
+            path.display()
+        ));
+    }
+    fs::read_to_string(&path).with_context(|| format!("read template {}", path.display()))
+}
+
+pub fn load_template(name: &str) -> Result<Template> {
+    parse_template(&read_template_text(name)?).with_context(|| format!("parse template '{name}'"))
+}
+
+fn parse_template(text: &str) -> Result<Template> {
+    let mut tpl = Template::default();
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        tpl.body = text.to_string();
+        return Ok(tpl);
+    };
+
+    let mut lines = rest.split_inclusive('\n');
+    let mut consumed = 0usize;
+    let mut closed = false;
+    for line in lines.by_ref() {
+        consumed += line.len();
+        let l = line.trim();
+        if l == "---" {
+            closed = true;
+            break;
+        }
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let (key, value) = l
+            .split_once(':')
+            .ok_or_else(|| anyhow!("frontmatter line is not `key: value`: {l:?}"))?;
+        let value = value.trim().to_string();
+        match key.trim() {
+            "category" => tpl.category = Some(value),
+            "tags" => tpl.tags = Some(value),
+            "confidence" => {
+                tpl.confidence = Some(
+                    value
+                        .parse::<f64>()
+                        .with_context(|| format!("invalid confidence: {value}"))?,
+                )
+            }
+            other => return Err(anyhow!("unknown frontmatter key: {other}")),
+        }
+    }
+    if !closed {
+        return Err(anyhow!("frontmatter is missing its closing `---`"));
+    }
+
+    tpl.body = rest[consumed..].to_string();
+    Ok(tpl)
+}
+
+/// Replace `{{key}}` placeholders; unknown placeholders are left as-is.
+pub fn render(body: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = body.to_string();
+    for (k, v) in vars {
+        out = out.replace(&format!("{{{{{k}}}}}"), v);
+    }
+    out
+}
@@ -0,0 +1,297 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Upload bodies are fed to the limiter in slices of this size.
+const UPLOAD_CHUNK: usize = 64 * 1024;
+
+/// Token bucket shared by every transfer of one command (`--rate-limit`), so parallel
+/// downloads stay under the cap together.
+///
+/// The bucket holds at most one second of tokens. Taking more than is available puts
+/// the bucket in debt and the caller sleeps until it is paid off; the mutex is held
+/// while sleeping so waiters are served in order.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Arc<Self> {
+        Arc::new(Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                refilled: Instant::now(),
+            }),
+        })
+    }
+
+    /// Wait until `n` more bytes fit under the rate.
+    pub async fn take(&self, n: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate) - n as f64;
+        bucket.refilled = now;
+        if bucket.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.tokens / rate)).await;
+        }
+    }
+}
+
+/// Lowest rate a 429 can push `RequestLimiter` down to (one request every ten seconds).
+const MIN_RPS: f64 = 0.1;
+
+/// Share of the configured rate each successful response wins back after a 429.
+const RECOVERY_STEP: f64 = 0.05;
+
+/// 429s this soon after a cut are answers to requests sent at the old rate, so they
+/// don't cut it again.
+const REDUCE_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Request-rate cap for the whole process (`--rps`), shared by every clone of the API
+/// client so concurrent requests stay under it together.
+///
+/// The bucket holds a single request, so requests are evenly spaced instead of bursting;
+/// unlike `RateLimiter`, waiters aren't served in order. A 429 halves the rate (and with `Retry-After`, holds every request until then); each
+/// later success raises it by `RECOVERY_STEP` until it is back at `--rps`.
+pub struct RequestLimiter {
+    max_rps: f64,
+    state: Mutex<RequestBucket>,
+}
+
+struct RequestBucket {
+    rps: f64,
+    tokens: f64,
+    refilled: Instant,
+    reduced: Option<Instant>,
+}
+
+impl RequestLimiter {
+    pub fn new(rps: f64) -> Arc<Self> {
+        Arc::new(Self {
+            max_rps: rps,
+            state: Mutex::new(RequestBucket {
+                rps,
+                tokens: 1.0,
+                refilled: Instant::now(),
+                reduced: None,
+            }),
+        })
+    }
+
+    /// Wait for this request's turn. The lock isn't held while sleeping, so a 429 seen in
+    /// the meantime also delays requests that are already waiting.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.state.lock().await;
+                bucket.refill(Instant::now());
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                (1.0 - bucket.tokens) / bucket.rps
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+
+    /// Feed back a response status. Returns the new rate when a 429 lowered it.
+    pub async fn observe(
+        &self,
+        status: reqwest::StatusCode,
+        retry_after: Option<Duration>,
+    ) -> Option<f64> {
+        let mut bucket = self.state.lock().await;
+        if status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            if status.is_success() && bucket.rps < self.max_rps {
+                bucket.rps = (bucket.rps + self.max_rps * RECOVERY_STEP).min(self.max_rps);
+            }
+            return None;
+        }
+        let now = Instant::now();
+        bucket.refill(now);
+        let cut = bucket
+            .reduced
+            .is_none_or(|at| now.duration_since(at) >= REDUCE_COOLDOWN);
+        if cut {
+            bucket.rps = (bucket.rps / 2.0).max(MIN_RPS.min(self.max_rps));
+            bucket.reduced = Some(now);
+        }
+        if let Some(wait) = retry_after {
+            // Empty the bucket for `wait` at the current rate.
+            bucket.tokens = bucket.tokens.min(1.0 - wait.as_secs_f64() * bucket.rps);
+        }
+        cut.then_some(bucket.rps)
+    }
+}
+
+impl RequestBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rps).min(1.0);
+        self.refilled = now;
+    }
+}
+
+/// Request body that releases `bytes` no faster than `limiter` allows.
+pub fn body(bytes: Vec<u8>, limiter: Arc<RateLimiter>) -> reqwest::Body {
+    let chunks =
+        futures_util::stream::unfold((bytes, 0, limiter), |(bytes, pos, limiter)| async move {
+            if pos >= bytes.len() {
+                return None;
+            }
+            let end = (pos + UPLOAD_CHUNK).min(bytes.len());
+            limiter.take(end - pos).await;
+            let chunk = bytes[pos..end].to_vec();
+            Some((Ok::<_, std::io::Error>(chunk), (bytes, end, limiter)))
+        });
+    reqwest::Body::wrap_stream(chunks)
+}
+
+/// `--rate-limit` values: bytes per second with an optional unit and `/s`, e.g. `500000`,
+/// `500KB`, `2MB`, `1.5MiB/s`. `K`/`KB`, `M`/`MB`, `G`/`GB` are powers of 1000;
+/// `KiB`, `MiB`, `GiB` are powers of 1024.
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+    let spec = s.trim();
+    let spec = spec.strip_suffix("/s").unwrap_or(spec).trim_end();
+    let split = spec
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(spec.len());
+    let (number, unit) = spec.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a rate like 2MB or 500KiB, got {s:?}"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        other => {
+            return Err(format!(
+                "unknown unit '{other}' in {s:?} (use B, KB, MB, GB, KiB, MiB or GiB)"
+            ));
+        }
+    };
+    let bytes = (number * multiplier as f64).round();
+    if bytes < 1.0 {
+        return Err(format!(
+            "rate must be at least 1 byte per second, got {s:?}"
+        ));
+    }
+    Ok(bytes as u64)
+}
+
+/// `--rps` values: requests per second, fractions allowed (`0.5` is one every two seconds).
+pub fn parse_rps(s: &str) -> Result<f64, String> {
+    let rps: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("expected requests per second like 5 or 0.5, got {s:?}"))?;
+    if !rps.is_finite() || rps < MIN_RPS {
+        return Err(format!(
+            "rate must be at least {MIN_RPS} requests per second, got {s:?}"
+        ));
+    }
+    Ok(rps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn parse_rate_units() {
+        assert_eq!(parse_rate("500000"), Ok(500_000));
+        assert_eq!(parse_rate("500KB"), Ok(500_000));
+        assert_eq!(parse_rate("2MB"), Ok(2_000_000));
+        assert_eq!(parse_rate("1.5MiB/s"), Ok(1_572_864));
+        assert_eq!(parse_rate(" 1 gib "), Ok(1 << 30));
+        assert_eq!(parse_rate("10 B/s"), Ok(10));
+    }
+
+    #[test]
+    fn parse_rate_rejects_bad_values() {
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("5TB").is_err());
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("0.4").is_err());
+    }
+
+    #[test]
+    fn parse_rps_values() {
+        assert_eq!(parse_rps("5"), Ok(5.0));
+        assert_eq!(parse_rps(" 0.5 "), Ok(0.5));
+        assert_eq!(parse_rps("0.1"), Ok(MIN_RPS));
+        assert!(parse_rps("0.05").is_err());
+        assert!(parse_rps("0").is_err());
+        assert!(parse_rps("inf").is_err());
+        assert!(parse_rps("NaN").is_err());
+        assert!(parse_rps("lots").is_err());
+    }
+
+    #[tokio::test]
+    async fn request_limiter_halves_on_429_once_per_cooldown() {
+        let limiter = RequestLimiter::new(4.0);
+        assert_eq!(
+            limiter.observe(StatusCode::TOO_MANY_REQUESTS, None).await,
+            Some(2.0)
+        );
+        // Answers to requests sent at the old rate don't cut it again.
+        assert_eq!(
+            limiter.observe(StatusCode::TOO_MANY_REQUESTS, None).await,
+            None
+        );
+        assert_eq!(limiter.state.lock().await.rps, 2.0);
+    }
+
+    #[tokio::test]
+    async fn request_limiter_recovers_on_success() {
+        let limiter = RequestLimiter::new(4.0);
+        limiter.observe(StatusCode::TOO_MANY_REQUESTS, None).await;
+        assert_eq!(limiter.observe(StatusCode::OK, None).await, None);
+        assert!((limiter.state.lock().await.rps - 2.2).abs() < 1e-9);
+        for _ in 0..100 {
+            limiter.observe(StatusCode::OK, None).await;
+        }
+        assert_eq!(limiter.state.lock().await.rps, 4.0);
+        // Errors other than 429 leave the rate alone.
+        limiter
+            .observe(StatusCode::INTERNAL_SERVER_ERROR, None)
+            .await;
+        assert_eq!(limiter.state.lock().await.rps, 4.0);
+    }
+
+    #[tokio::test]
+    async fn request_limiter_never_drops_below_the_floor() {
+        let limiter = RequestLimiter::new(0.15);
+        assert_eq!(
+            limiter.observe(StatusCode::TOO_MANY_REQUESTS, None).await,
+            Some(MIN_RPS)
+        );
+    }
+
+    #[tokio::test]
+    async fn request_limiter_holds_requests_for_retry_after() {
+        let limiter = RequestLimiter::new(10.0);
+        limiter.acquire().await;
+        limiter
+            .observe(StatusCode::TOO_MANY_REQUESTS, Some(Duration::from_secs(3)))
+            .await;
+        // At the halved rate of 5/s, 3 seconds is 15 tokens of debt.
+        assert!(limiter.state.lock().await.tokens <= -14.0);
+    }
+}
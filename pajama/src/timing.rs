@@ -0,0 +1,165 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::output::{self, Level};
+
+tokio::task_local! {
+    /// Phases of the request `ApiClient::send` is currently driving on this task.
+    static PHASES: Arc<Mutex<Phases>>;
+}
+
+/// Connection setup observed while one request was in flight. Both stay `None` when the
+/// request reused a pooled connection.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Phases {
+    dns: Option<Duration>,
+    /// Whole connector call: DNS, TCP connect and TLS handshake.
+    connect: Option<Duration>,
+}
+
+/// Run `fut` (a request send) and collect the phases recorded while it ran. A connection
+/// that hyper finishes in the background after the request took a pooled one isn't
+/// recorded, which is right: that request didn't wait for it.
+pub async fn observe<F: Future>(fut: F) -> (F::Output, Phases) {
+    let phases = Arc::new(Mutex::new(Phases::default()));
+    let out = PHASES.scope(phases.clone(), fut).await;
+    let phases = *phases.lock().unwrap_or_else(|e| e.into_inner());
+    (out, phases)
+}
+
+pub fn record_dns(elapsed: Duration) {
+    let _ = PHASES.try_with(|p| {
+        if let Ok(mut p) = p.lock() {
+            p.dns = Some(elapsed);
+        }
+    });
+}
+
+fn record_connect(elapsed: Duration) {
+    let _ = PHASES.try_with(|p| {
+        if let Ok(mut p) = p.lock() {
+            p.connect = Some(elapsed);
+        }
+    });
+}
+
+/// Per-request timing line for `--trace-timing`. It is logged when dropped, so callers
+/// that read the body keep it alive until the body is done and `total` includes it;
+/// otherwise it is dropped with the response and `total` ends at the headers.
+pub struct RequestTiming {
+    method: String,
+    path: String,
+    status: Option<u16>,
+    started: Instant,
+    phases: Phases,
+    ttfb: Duration,
+}
+
+impl RequestTiming {
+    pub fn new(
+        method: &reqwest::Method,
+        url: &url::Url,
+        status: Option<reqwest::StatusCode>,
+        started: Instant,
+        phases: Phases,
+    ) -> Self {
+        Self {
+            method: method.to_string(),
+            path: url.path().to_string(),
+            status: status.map(|s| s.as_u16()),
+            started,
+            phases,
+            ttfb: started.elapsed(),
+        }
+    }
+}
+
+impl Drop for RequestTiming {
+    fn drop(&mut self) {
+        // Milliseconds, rounded to 0.1.
+        let ms = |d: Duration| (d.as_secs_f64() * 10_000.0).round() / 10.0;
+        let total = self.started.elapsed();
+        let dns = self.phases.dns;
+        // The connector's time includes the lookup it started with.
+        let connect = self
+            .phases
+            .connect
+            .map(|c| c.saturating_sub(dns.unwrap_or_default()));
+        let status = self
+            .status
+            .map_or_else(|| "failed".to_string(), |s| s.to_string());
+        let setup = match (dns, connect) {
+            (None, None) => "reused connection".to_string(),
+            (dns, connect) => format!(
+                "dns {}, connect+tls {}",
+                dns.map_or_else(|| "-".to_string(), |d| format!("{:.0}ms", ms(d))),
+                connect.map_or_else(|| "-".to_string(), |d| format!("{:.0}ms", ms(d))),
+            ),
+        };
+        output::log(
+            Level::Debug,
+            &format!(
+                "timing {} {} {status}: {setup}, ttfb {:.0}ms, total {:.0}ms",
+                self.method,
+                self.path,
+                ms(self.ttfb),
+                ms(total)
+            ),
+            &[
+                ("method", self.method.clone().into()),
+                ("path", self.path.clone().into()),
+                ("status", self.status.into()),
+                ("dns_ms", dns.map(ms).into()),
+                ("connect_ms", connect.map(ms).into()),
+                ("ttfb_ms", ms(self.ttfb).into()),
+                ("total_ms", ms(total).into()),
+                ("reused_connection", self.phases.connect.is_none().into()),
+            ],
+        );
+    }
+}
+
+/// Connector layer that times each new connection (`--trace-timing`). reqwest runs TCP
+/// connect and the TLS handshake inside one connector call, so they are measured together.
+#[derive(Clone)]
+pub struct ConnectTiming;
+
+impl<S> tower_layer::Layer<S> for ConnectTiming {
+    type Service = TimedConnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnect(inner)
+    }
+}
+
+#[derive(Clone)]
+pub struct TimedConnect<S>(S);
+
+impl<S, R> tower_service::Service<R> for TimedConnect<S>
+where
+    S: tower_service::Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let started = Instant::now();
+        let fut = self.0.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            if res.is_ok() {
+                record_connect(started.elapsed());
+            }
+            res
+        })
+    }
+}
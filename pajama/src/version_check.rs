@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::api::ApiClient;
+use crate::config::{config_dir, config_disabled, create_private_file, ensure_config_dir};
+use crate::output::log_warn;
+
+/// How long a `version --check` result is reused before asking the server again.
+const CHECK_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// `GET /downloads/pajama/latest`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatestRelease {
+    pub version: String,
+    #[serde(default)]
+    pub changelog_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedCheck {
+    /// Unix seconds.
+    checked_at: u64,
+    /// Server that answered; a different `--api-url` doesn't reuse the result.
+    api_base_url: String,
+    latest: LatestRelease,
+}
+
+fn cache_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("version-check.json"))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The newest published release, from a fresh cache entry unless `refresh` is set
+/// (there is no cache under `--no-config`). The flag reports whether the answer came
+/// from the cache.
+pub async fn latest(
+    api: &ApiClient,
+    api_base_url: &str,
+    refresh: bool,
+) -> Result<(LatestRelease, bool)> {
+    let cache = match ensure_config_dir().and_then(|_| cache_path()) {
+        Ok(path) => Some(path),
+        Err(_) if config_disabled() => None,
+        Err(err) => {
+            log_warn!("could not cache the version check: {err:#}");
+            None
+        }
+    };
+    latest_cached_at(api, api_base_url, refresh, cache.as_deref()).await
+}
+
+/// `latest` with the cache file passed in; `None` always asks the server.
+async fn latest_cached_at(
+    api: &ApiClient,
+    api_base_url: &str,
+    refresh: bool,
+    cache: Option<&Path>,
+) -> Result<(LatestRelease, bool)> {
+    let cached = cache
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|text| serde_json::from_str::<CachedCheck>(&text).ok())
+        .filter(|c| c.api_base_url == api_base_url)
+        .filter(|c| now_secs().saturating_sub(c.checked_at) < CHECK_TTL_SECS);
+    if let Some(c) = cached.filter(|_| !refresh) {
+        return Ok((c.latest, true));
+    }
+
+    let latest: LatestRelease = api
+        .get_json("/downloads/pajama/latest", &[])
+        .await
+        .context("query the latest pajama release")?;
+    let Some(path) = cache else {
+        return Ok((latest, false));
+    };
+    let entry = CachedCheck {
+        checked_at: now_secs(),
+        api_base_url: api_base_url.to_string(),
+        latest: latest.clone(),
+    };
+    let write = || -> Result<()> {
+        create_private_file(path)?
+            .write_all(serde_json::to_string_pretty(&entry)?.as_bytes())
+            .with_context(|| format!("write {}", path.display()))
+    };
+    if let Err(err) = write() {
+        log_warn!("could not cache the version check: {err:#}");
+    }
+    Ok((latest, false))
+}
+
+/// Whether `latest` is a higher `major.minor.patch` than `current` (a leading `v` and
+/// any `-pre`/`+build` suffix are ignored; unparseable versions never compare newer).
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    fn parse(v: &str) -> Option<Vec<u64>> {
+        let v = v.trim().trim_start_matches('v');
+        let core = v.split(['-', '+']).next().unwrap_or(v);
+        core.split('.').map(|p| p.parse().ok()).collect()
+    }
+    match (parse(latest), parse(current)) {
+        (Some(l), Some(c)) => l > c,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::ClientOptions;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn check_is_anonymous_and_cached_privately() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/downloads/pajama/latest"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "version": "9.9.9" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = ApiClient::new(&server.uri(), "", &ClientOptions::default()).unwrap();
+        let dir = crate::config::test_dir("version-check");
+        fs::create_dir_all(&dir).unwrap();
+        let cache = dir.join("version-check.json");
+
+        let (latest, cached) = latest_cached_at(&api, &server.uri(), false, Some(&cache))
+            .await
+            .unwrap();
+        assert_eq!((latest.version.as_str(), cached), ("9.9.9", false));
+        let requests = server.received_requests().await.unwrap();
+        assert!(!requests[0].headers.contains_key("authorization"));
+        #[cfg(unix)]
+        assert_eq!(crate::config::test_mode(&cache), 0o600);
+
+        // The second call is answered from the file (the mock expects one request).
+        let (_, cached) = latest_cached_at(&api, &server.uri(), false, Some(&cache))
+            .await
+            .unwrap();
+        assert!(cached);
+    }
+
+    #[test]
+    fn newer_versions_compare_numerically() {
+        assert!(is_newer("0.1.10", "0.1.9"));
+        assert!(is_newer("v1.0.0", "0.9.9-beta"));
+        assert!(!is_newer("0.1.9", "0.1.9"));
+        assert!(!is_newer("garbage", "0.1.9"));
+    }
+}
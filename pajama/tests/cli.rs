@@ -0,0 +1,250 @@
+//! End-to-end checks of flag and environment handling: the built binary against a mock API.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Empty scratch directory for one test.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pajama-cli-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `pajama` with no inherited PAJAMA_* settings, its config under `home`, and the API
+/// pointed at `server`.
+fn pajama(server: &MockServer, home: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_pajama"));
+    for (name, _) in std::env::vars() {
+        if name.starts_with("PAJAMA_") {
+            cmd.env_remove(name);
+        }
+    }
+    cmd.env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("PAJAMA_CONFIG_DIR", home.join("pajama"))
+        .env("PAJAMA_API_URL", server.uri())
+        .stdin(Stdio::null());
+    cmd
+}
+
+/// Serve `GET /api/projects` only to requests carrying `token`.
+async fn projects_for(token: &str) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/projects"))
+        .and(header("authorization", format!("Bearer {token}").as_str()))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "projects": [] })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/projects"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(serde_json::json!({ "error": "wrong token" })),
+        )
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn run(mut cmd: Command) -> Output {
+    tokio::task::spawn_blocking(move || cmd.output().unwrap())
+        .await
+        .unwrap()
+}
+
+/// `run` with `input` on stdin.
+async fn run_with_stdin(mut cmd: Command, input: &'static str) -> Output {
+    tokio::task::spawn_blocking(move || {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // A child that exits on bad flags may close stdin before reading it.
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        child.wait_with_output().unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+fn assert_ok(out: &Output) {
+    assert!(
+        out.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[tokio::test]
+async fn token_file_env_outranks_pajama_token() {
+    let server = projects_for("from-file").await;
+    let home = scratch("token-file-env");
+    std::fs::write(home.join("token"), "from-file\n").unwrap();
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN_FILE", home.join("token"))
+        .env("PAJAMA_TOKEN", "from-env")
+        .args(["projects", "list", "--json"]);
+    assert_ok(&run(cmd).await);
+}
+
+#[tokio::test]
+async fn token_file_flag_outranks_the_env_file() {
+    let server = projects_for("from-flag").await;
+    let home = scratch("token-file-flag");
+    std::fs::write(home.join("flag"), "from-flag").unwrap();
+    std::fs::write(home.join("env"), "from-env-file").unwrap();
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN_FILE", home.join("env"))
+        .arg("--token-file")
+        .arg(home.join("flag"))
+        .args(["projects", "list", "--json"]);
+    assert_ok(&run(cmd).await);
+}
+
+#[tokio::test]
+async fn pajama_token_is_used_without_a_file() {
+    let server = projects_for("from-env").await;
+    let home = scratch("token-env");
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN", " from-env ")
+        .args(["projects", "list", "--json"]);
+    assert_ok(&run(cmd).await);
+}
+
+#[tokio::test]
+async fn token_stdin_outranks_files_and_env() {
+    let server = projects_for("from-stdin").await;
+    let home = scratch("token-stdin");
+    std::fs::write(home.join("token"), "from-file").unwrap();
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN_FILE", home.join("token"))
+        .env("PAJAMA_TOKEN", "from-env")
+        .args(["--token-stdin", "projects", "list", "--json"]);
+    assert_ok(&run_with_stdin(cmd, "from-stdin\n").await);
+}
+
+#[tokio::test]
+async fn token_and_token_stdin_conflict() {
+    let server = projects_for("t").await;
+    let home = scratch("token-conflict");
+    let mut cmd = pajama(&server, &home);
+    cmd.args(["--token", "t", "--token-stdin", "projects", "list"]);
+    let out = run_with_stdin(cmd, "t").await;
+    assert!(!out.status.success());
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn config_dir_env_moves_the_config() {
+    let server = MockServer::start().await;
+    let home = scratch("config-dir");
+    let mut cmd = pajama(&server, &home);
+    cmd.arg("config-path");
+    let out = run(cmd).await;
+    assert_ok(&out);
+    let printed = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(
+        printed.trim(),
+        home.join("pajama").join("config.json").to_str().unwrap()
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn xdg_config_home_is_respected() {
+    let server = MockServer::start().await;
+    let home = scratch("xdg");
+    let mut cmd = pajama(&server, &home);
+    cmd.env_remove("PAJAMA_CONFIG_DIR").arg("config-path");
+    let out = run(cmd).await;
+    assert_ok(&out);
+    let printed = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(
+        printed.trim(),
+        home.join(".config/pajama/config.json").to_str().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn hex_checksum_is_sent_for_verification() {
+    use wiremock::matchers::body_json;
+    let server = MockServer::start().await;
+    let home = scratch("checksum-hex");
+    std::fs::write(home.join("asset.bin"), "hello").unwrap();
+    let sum = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    Mock::given(method("POST"))
+        .and(path("/api/assets"))
+        .respond_with(
+            ResponseTemplate::new(201)
+                .set_body_json(serde_json::json!({ "id": "a1", "upload_part_size": 5 })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/api/assets/a1/parts/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "ok": true })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/assets/a1/complete"))
+        .and(body_json(serde_json::json!({ "sha256": sum })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(
+            serde_json::json!({ "ok": true, "status": "ready", "sha256": sum, "sha256_verified": true }),
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN", "t")
+        .args([
+            "assets",
+            "upload",
+            "--project-id",
+            "p1",
+            "--checksum",
+            sum,
+            "--path",
+        ])
+        .arg(home.join("asset.bin"));
+    let out = run(cmd).await;
+    assert_ok(&out);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!("Server verified sha256 {sum}")),
+        "{stderr}"
+    );
+}
+
+#[tokio::test]
+async fn no_config_leaves_the_config_dir_alone() {
+    let server = projects_for("t").await;
+    let home = scratch("no-config");
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN", "t")
+        .args(["--no-config", "projects", "list", "--json"]);
+    assert_ok(&run(cmd).await);
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_NO_CONFIG", "1").arg("history");
+    let out = run(cmd).await;
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--no-config"));
+    assert!(!home.join("pajama").exists());
+    assert!(!home.join(".config").exists());
+
+    // Without the flag the same run records history there.
+    let mut cmd = pajama(&server, &home);
+    cmd.env("PAJAMA_TOKEN", "t")
+        .args(["projects", "list", "--json"]);
+    assert_ok(&run(cmd).await);
+    assert!(home.join("pajama").exists());
+}